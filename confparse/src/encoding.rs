@@ -0,0 +1,374 @@
+use std::{fmt, path::Path, sync::Arc};
+
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{Arg, Comments, Conf, Duration, GuardedPorts, Include, Task, Task_};
+
+// tasks.toml and tasks.json hold the same items as a tasks.conf. Included
+// tasks are not written out, only the INCLUDE directives which bring them in.
+
+/// File format of an OBC configuration, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Conf,
+    Toml,
+    Json,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Conf, Format::Toml, Format::Json];
+
+    /// `None` for extensions other than `.conf`, `.toml` and `.json`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Format> {
+        match path.as_ref().extension()?.to_str()? {
+            "conf" => Some(Format::Conf),
+            "toml" => Some(Format::Toml),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    /// Name of the configuration file of an OBC in this format
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Format::Conf => "tasks.conf",
+            Format::Toml => "tasks.toml",
+            Format::Json => "tasks.json",
+        }
+    }
+}
+
+impl Conf {
+    /// The configuration as the text of a file in `format`.
+    pub fn to_format(&self, format: Format) -> String {
+        match format {
+            Format::Conf => self.to_string(),
+            Format::Toml => toml::to_string(self).expect("a Conf has only strings, numbers and lists"),
+            Format::Json => {
+                serde_json::to_string_pretty(self).expect("a Conf has only strings, numbers and lists") + "\n"
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfFile {
+    #[serde(rename = "in", default)]
+    inports: Vec<Arc<str>>,
+    #[serde(rename = "out", default)]
+    outports: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    guarded_ports: Vec<GuardedPorts>,
+    #[serde(rename = "init_conditions", default)]
+    initial: Vec<Arc<str>>,
+    /// Positions count the tasks of this file only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    includes: Vec<Include>,
+    /// Templates of this file only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    templates: Vec<Task>,
+    #[serde(default)]
+    tasks: Vec<Task>,
+    #[serde(default, skip_serializing_if = "Comments::is_empty")]
+    comments: Comments,
+}
+
+impl From<Conf> for ConfFile {
+    fn from(conf: Conf) -> Self {
+        let mut tasks = vec![];
+        let mut includes = conf.includes;
+        let mut templates = conf.templates;
+        templates.truncate(templates.len() - includes.iter().map(|i| i.templates).sum::<usize>());
+        let mut next = 0;
+        let mut spliced = 0;
+        for include in &mut includes {
+            tasks.extend_from_slice(&conf.tasks[next..include.position]);
+            next = include.position + include.tasks;
+            include.position -= spliced;
+            spliced += include.tasks;
+            include.tasks = 0;
+            include.templates = 0;
+        }
+        tasks.extend_from_slice(&conf.tasks[next..]);
+
+        ConfFile {
+            inports: conf.inports,
+            outports: conf.outports,
+            guarded_ports: conf.guarded_ports,
+            initial: conf.initial,
+            includes,
+            templates,
+            tasks,
+            comments: conf.comments,
+        }
+    }
+}
+
+impl TryFrom<ConfFile> for Conf {
+    type Error = String;
+
+    fn try_from(file: ConfFile) -> Result<Self, Self::Error> {
+        let mut previous = 0;
+        for include in &file.includes {
+            if include.position < previous || include.position > file.tasks.len() {
+                return Err(format!(
+                    "position {} of include '{}' must be between {previous} and {}",
+                    include.position,
+                    include.path,
+                    file.tasks.len()
+                ));
+            }
+            previous = include.position;
+        }
+
+        Ok(Conf {
+            inports: file.inports,
+            outports: file.outports,
+            guarded_ports: file.guarded_ports,
+            initial: file.initial,
+            tasks: file.tasks,
+            templates: file.templates,
+            includes: file.includes,
+            comments: file.comments,
+        })
+    }
+}
+
+/// The length of a task: a number of ticks, or a time such as `"250us"`.
+pub(crate) enum Cycles {
+    Ticks(u16),
+    Time(Duration),
+}
+
+impl Serialize for Cycles {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Cycles::Ticks(ticks) => serializer.serialize_u16(*ticks),
+            Cycles::Time(duration) => duration.serialize(serializer),
+        }
+    }
+}
+
+// Not `#[serde(untagged)]`, so that an invalid time is reported as it is.
+impl<'de> Deserialize<'de> for Cycles {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CyclesVisitor;
+
+        impl Visitor<'_> for CyclesVisitor {
+            type Value = Cycles;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a number of ticks or a time such as \"250us\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, ticks: u64) -> Result<Cycles, E> {
+                u16::try_from(ticks)
+                    .map(Cycles::Ticks)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(ticks), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, ticks: i64) -> Result<Cycles, E> {
+                u16::try_from(ticks)
+                    .map(Cycles::Ticks)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(ticks), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, time: &str) -> Result<Cycles, E> {
+                time.parse().map(Cycles::Time).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(CyclesVisitor)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TaskFile {
+    name: Arc<str>,
+    #[serde(default)]
+    args: Vec<Arg>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    requires: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    satisfies: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    on_port: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    uses: Vec<Arc<str>>,
+    cycles: Cycles,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bcet: Option<Cycles>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    period: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deadline: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    when: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    comments: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trailing_comment: Option<Arc<str>>,
+}
+
+impl From<Task_> for TaskFile {
+    fn from(task: Task_) -> Self {
+        TaskFile {
+            name: task.name,
+            args: task.args,
+            requires: task.requires,
+            satisfies: task.satisfies,
+            on_port: task.on_port,
+            uses: task.uses,
+            cycles: match task.duration {
+                Some(duration) => Cycles::Time(duration),
+                None => Cycles::Ticks(task.cycles),
+            },
+            bcet: task.bcet.map(|bcet| match task.bcet_duration {
+                Some(duration) => Cycles::Time(duration),
+                None => Cycles::Ticks(bcet),
+            }),
+            period: task.period,
+            deadline: task.deadline,
+            priority: task.priority,
+            template: task.template,
+            when: task.when,
+            comments: task.comments,
+            trailing_comment: task.trailing_comment,
+        }
+    }
+}
+
+impl From<TaskFile> for Task_ {
+    /// Times are converted to ticks once the tick of the project is known,
+    /// the OBC id is set from the folder of the file.
+    fn from(file: TaskFile) -> Self {
+        let split = |cycles| match cycles {
+            Cycles::Ticks(ticks) => (ticks, None),
+            Cycles::Time(duration) => (0, Some(duration)),
+        };
+        let (cycles, duration) = split(file.cycles);
+        let (bcet, bcet_duration) = file.bcet.map(split).unzip();
+        Task_ {
+            name: file.name,
+            args: file.args,
+            requires: file.requires,
+            satisfies: file.satisfies,
+            on_port: file.on_port,
+            uses: file.uses,
+            cycles,
+            duration,
+            bcet,
+            bcet_duration: bcet_duration.flatten(),
+            period: file.period,
+            deadline: file.deadline,
+            priority: file.priority,
+            obc_id: 0,
+            template: file.template,
+            when: file.when,
+            comments: file.comments,
+            trailing_comment: file.trailing_comment,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{conf_from_str, get_conf, ConfError};
+
+    const CONF: &str = "\
+# ports
+IN: a
+OUT: b
+@when(lab)
+IN: c
+
+INIT_CONDITIONS: x
+
+@priority: 1
+Template poll(S:r, T): 2
+@satisfies: polled
+
+Task \"IN\"(S:r, T): 250us..1ms // slow
+@satisfies: w
+
+@priority: 0
+@requires: x
+Task p = poll(A, B) # instance
+
+@when(lab)
+@period: 8
+@deadline: 4
+@on_port: a
+Task q(S): 3
+
+# end
+";
+
+    fn tick() -> Duration {
+        "1us".parse().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let conf = conf_from_str("tasks.conf", CONF, 1, tick()).unwrap();
+        for format in Format::ALL {
+            let text = conf.to_format(format);
+            let reread = conf_from_str(format.file_name(), &text, 1, tick()).unwrap();
+            assert_eq!(reread.tasks, conf.tasks, "{format:?}");
+            assert_eq!(reread.templates, conf.templates, "{format:?}");
+            assert_eq!(reread.to_string(), CONF, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn times_and_instances_as_written() {
+        let conf = conf_from_str("tasks.conf", CONF, 1, tick()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&conf.to_format(Format::Json)).unwrap();
+        let task = &json["tasks"][0];
+        assert_eq!(task["cycles"], "1ms");
+        assert_eq!(task["bcet"], "250us");
+        // an instance keeps its template and what it adds to it
+        let instance = &json["tasks"][1];
+        assert_eq!(instance["template"], "poll");
+        assert_eq!(instance["priority"], 0);
+        assert_eq!(json["tasks"][2]["cycles"], 3);
+    }
+
+    #[test]
+    fn includes_are_written_as_directives() {
+        let dir = std::env::temp_dir().join(format!("confparse-{}-encoding", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("more.conf"), "Task b(S): 1\n\nTask c(S): 1\n").unwrap();
+        let text = "IN:\nOUT:\n\nINIT_CONDITIONS:\n\nTask a(S): 1\n\nINCLUDE: more.conf\n\nTask d(S): 1\n";
+        std::fs::write(dir.join("tasks.conf"), text).unwrap();
+        let conf = get_conf(dir.join("tasks.conf"), 1, tick()).unwrap();
+
+        for format in [Format::Toml, Format::Json] {
+            let written = conf.to_format(format);
+            assert!(!written.contains("\"b\""), "{format:?}: {written}");
+            std::fs::write(dir.join(format.file_name()), written).unwrap();
+            let reread = get_conf(dir.join(format.file_name()), 1, tick()).unwrap();
+            assert_eq!(reread.tasks, conf.tasks, "{format:?}");
+            assert_eq!(reread.to_string(), text, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn include_past_the_tasks() {
+        let toml = "tasks = []\n\n[[includes]]\npath = \"more.conf\"\nposition = 1\n";
+        let Err(ConfError::Toml { error, .. }) = conf_from_str("tasks.toml", toml, 1, tick()) else {
+            panic!("an include after the last task is an error");
+        };
+        assert!(error.to_string().contains("position 1 of include 'more.conf' must be between 0 and 0"), "{error}");
+    }
+}
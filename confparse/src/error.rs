@@ -0,0 +1,267 @@
+use std::{
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::Duration;
+
+/// Byte range into the text of a `tasks.conf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// What went wrong at a position in a `tasks.conf`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxErrorKind {
+    /// A token other than the expected one was found
+    Unexpected { expected: String, found: String },
+    /// The file ended while more was expected
+    UnexpectedEof { expected: String },
+    /// `IN:`, `OUT:` or `INIT_CONDITIONS:` given more than once
+    DuplicateSection(Arc<str>),
+    UnknownAnnotation(Arc<str>),
+    MissingTaskName,
+    InvalidCycles(Arc<str>),
+    /// A task length which is not a whole number of ticks
+    UnevenDuration { duration: Duration, tick: Duration },
+    /// A task length of more ticks than fit in a `u16`
+    DurationOverflow { duration: Duration, tick: Duration },
+    /// A `best..worst` range whose best case is longer than its worst case
+    InvertedRange { best: Arc<str>, worst: Arc<str> },
+    /// An access mode other than `r` or `w` after a task argument
+    InvalidAccess(Arc<str>),
+    /// The value of a numeric annotation such as `@period:` is out of range
+    InvalidNumber { annotation: Arc<str>, found: Arc<str> },
+    UnterminatedQuote,
+    /// A file included with `INCLUDE:` could not be found
+    UnreadableInclude { path: Arc<str>, error: Arc<str> },
+    /// A file includes itself, directly or through other files
+    IncludeCycle(Arc<str>),
+    /// `Task name = template(...)` names a template not declared above it
+    UnknownTemplate(Arc<str>),
+    /// A template declared twice in the same file
+    DuplicateTemplate(Arc<str>),
+    /// An instance passes a different number of sensors than the template takes
+    TemplateArity { template: Arc<str>, expected: usize, found: usize },
+}
+
+impl Display for SyntaxErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxErrorKind::Unexpected { expected, found } => {
+                write!(f, "Expected {expected}, found {found}")
+            }
+            SyntaxErrorKind::UnexpectedEof { expected } => {
+                write!(f, "Unexpected end of file, expected {expected}")
+            }
+            SyntaxErrorKind::DuplicateSection(section) => write!(f, "Another {section} found"),
+            SyntaxErrorKind::UnknownAnnotation(name) => write!(f, "Unknown annotation '@{name}'"),
+            SyntaxErrorKind::MissingTaskName => write!(f, "Task Name Expected"),
+            SyntaxErrorKind::InvalidCycles(cycles) => {
+                write!(f, "Cycles must be a number, a time such as 250us or a range such as 2..5, found '{cycles}'")
+            }
+            SyntaxErrorKind::UnevenDuration { duration, tick } => {
+                write!(f, "{duration} is not a whole number of ticks of {tick}")
+            }
+            SyntaxErrorKind::DurationOverflow { duration, tick } => {
+                write!(f, "{duration} is more than {} ticks of {tick}", u16::MAX)
+            }
+            SyntaxErrorKind::InvertedRange { best, worst } => {
+                write!(f, "Best case {best} is longer than the worst case {worst}")
+            }
+            SyntaxErrorKind::InvalidAccess(mode) => {
+                write!(f, "Access mode must be 'r' or 'w', found '{mode}'")
+            }
+            SyntaxErrorKind::InvalidNumber { annotation, found } => {
+                write!(f, "Invalid value '{found}' for {annotation}")
+            }
+            SyntaxErrorKind::UnterminatedQuote => write!(f, "Unterminated quoted name"),
+            SyntaxErrorKind::UnreadableInclude { path, error } => {
+                write!(f, "Cannot include '{path}': {error}")
+            }
+            SyntaxErrorKind::IncludeCycle(chain) => write!(f, "Include cycle: {chain}"),
+            SyntaxErrorKind::UnknownTemplate(name) => write!(f, "Unknown template '{name}'"),
+            SyntaxErrorKind::DuplicateTemplate(name) => write!(f, "Template '{name}' declared again"),
+            SyntaxErrorKind::TemplateArity { template, expected, found } => {
+                write!(f, "Template '{template}' takes {expected} sensor(s), found {found}")
+            }
+        }
+    }
+}
+
+/// An error found while tokenizing or parsing, before it is tied to a file.
+#[derive(Debug, Clone)]
+pub(crate) struct SyntaxError {
+    pub kind: SyntaxErrorKind,
+    pub span: Span,
+}
+
+impl SyntaxError {
+    pub fn new(kind: SyntaxErrorKind, span: Span) -> Self {
+        SyntaxError { kind, span }
+    }
+}
+
+/// A syntax error located in the file it came from.
+///
+/// `line` and `column` are 1-based.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub span: Span,
+    pub kind: SyntaxErrorKind,
+    source_line: String,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(path: &Path, source: &str, error: SyntaxError) -> Self {
+        let start = error.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+
+        Diagnostic {
+            path: path.to_path_buf(),
+            line: source[..start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+            span: error.span,
+            kind: error.kind,
+            source_line: source[line_start..line_end].trim_end_matches('\r').to_string(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.kind)?;
+
+        // rustc style: line number gutter, the offending line and carets under the span
+        let gutter = " ".repeat(self.line.to_string().len());
+        let line_chars = self.source_line.chars().count();
+        let underline = self
+            .span
+            .end
+            .saturating_sub(self.span.start)
+            .min(line_chars.saturating_sub(self.column - 1))
+            .max(1);
+
+        writeln!(
+            f,
+            "{gutter}--> {}:{}:{}",
+            self.path.display(),
+            self.line,
+            self.column
+        )?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(
+            f,
+            "{gutter} | {}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(underline)
+        )
+    }
+}
+
+/// An error while loading a `tasks.conf`.
+#[derive(Debug)]
+pub enum ConfError {
+    /// The file could not be read
+    Io { path: PathBuf, error: io::Error },
+    /// The file is not a valid `tasks.conf`
+    Syntax(Box<Diagnostic>),
+    /// The file is not a valid `tasks.toml`
+    Toml { path: PathBuf, error: toml::de::Error },
+    /// The file is not a valid `tasks.json`
+    Json { path: PathBuf, error: serde_json::Error },
+}
+
+impl ConfError {
+    pub fn path(&self) -> &Path {
+        match self {
+            ConfError::Io { path, .. } => path,
+            ConfError::Syntax(diagnostic) => &diagnostic.path,
+            ConfError::Toml { path, .. } | ConfError::Json { path, .. } => path,
+        }
+    }
+}
+
+impl Display for ConfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfError::Io { path, error } => write!(f, "{error}\n --> {}", path.display()),
+            ConfError::Syntax(diagnostic) => diagnostic.fmt(f),
+            ConfError::Toml { path, error } => write!(f, "Invalid TOML in {}\n{error}", path.display()),
+            ConfError::Json { path, error } => write!(f, "{error}\n --> {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ConfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfError::Io { error, .. } => Some(error),
+            ConfError::Syntax(_) => None,
+            ConfError::Toml { error, .. } => Some(error),
+            ConfError::Json { error, .. } => Some(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(source: &str, start: usize, end: usize) -> Diagnostic {
+        let error = SyntaxError::new(SyntaxErrorKind::MissingTaskName, Span::new(start, end));
+        Diagnostic::new(Path::new("obc1/tasks.conf"), source, error)
+    }
+
+    #[test]
+    fn line_and_column() {
+        let source = "IN: a\nOUT: b\nTask (S): 2\n";
+        let diagnostic = diagnostic(source, source.find('(').unwrap(), source.find(')').unwrap() + 1);
+        assert_eq!((diagnostic.line, diagnostic.column), (3, 6));
+        assert_eq!(diagnostic.source_line, "Task (S): 2");
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        let source = "# é\n  Task é(S): x\r\n";
+        let diagnostic = diagnostic(source, source.find('x').unwrap(), source.len() - 2);
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 14));
+        assert_eq!(diagnostic.source_line, "  Task é(S): x");
+    }
+
+    #[test]
+    fn renders_carets_under_the_span() {
+        let source = "IN:\nTask (S): 2";
+        let diagnostic = diagnostic(source, 9, 12);
+        assert_eq!(
+            diagnostic.to_string(),
+            "Task Name Expected\n --> obc1/tasks.conf:2:6\n  |\n2 | Task (S): 2\n  |      ^^^"
+        );
+    }
+
+    #[test]
+    fn at_the_end_of_the_file() {
+        // one caret past the last character, even for an empty span
+        let source = "IN: a\nTask t(S):";
+        let diagnostic = diagnostic(source, source.len(), source.len());
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 11));
+        assert!(diagnostic.to_string().ends_with("2 | Task t(S):\n  |           ^"));
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+use crate::{lexer::needs_quotes, Access, Arg, Conf, Duration, Task_};
+
+// Canonical tasks.conf text: one item per line, ": " after keywords, ", " between
+// names, sorted port and condition lists, and a blank line before each task.
+
+fn name(name: &str) -> String {
+    if needs_quotes(name) {
+        format!("\"{name}\"")
+    } else {
+        name.to_string()
+    }
+}
+
+fn list(names: &[Arc<str>]) -> String {
+    names.iter().map(|f| name(f)).collect::<Vec<_>>().join(", ")
+}
+
+fn args(args: &[Arg]) -> String {
+    // write access is the default and is not spelled out
+    args.iter()
+        .map(|arg| match arg.access {
+            Access::Read => format!("{}:r", name(&arg.sensor)),
+            Access::Write => name(&arg.sensor),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn sorted_list(names: &[Arc<str>]) -> String {
+    let mut names = names.to_vec();
+    names.sort();
+    list(&names)
+}
+
+fn write_section(f: &mut fmt::Formatter<'_>, keyword: &str, names: String) -> fmt::Result {
+    if names.is_empty() {
+        writeln!(f, "{keyword}")
+    } else {
+        writeln!(f, "{keyword} {names}")
+    }
+}
+
+fn write_comments(f: &mut fmt::Formatter<'_>, comments: &[Arc<str>]) -> fmt::Result {
+    for comment in comments {
+        writeln!(f, "{comment}")?;
+    }
+    Ok(())
+}
+
+/// A task length as written: the time when it was given with a unit, else the ticks
+pub(crate) fn length(cycles: u16, duration: Option<Duration>) -> String {
+    duration.map_or(cycles.to_string(), |d| d.to_string())
+}
+
+/// Writes `task` as a `Task` or `Template` declaration, or as an instance of `template`
+/// with only the annotations it adds to those of the template.
+fn write_task(f: &mut fmt::Formatter<'_>, task: &Task_, keyword: &str, template: Option<&Task_>) -> fmt::Result {
+    let base = template.cloned().unwrap_or(Task_ {
+        name: "".into(),
+        args: vec![],
+        requires: vec![],
+        satisfies: vec![],
+        on_port: vec![],
+        uses: vec![],
+        cycles: 0,
+        duration: None,
+        bcet: None,
+        bcet_duration: None,
+        period: None,
+        deadline: None,
+        priority: None,
+        obc_id: task.obc_id,
+        template: None,
+        when: vec![],
+        comments: vec![],
+        trailing_comment: None,
+    });
+    let added = |names: &[Arc<str>], base: &[Arc<str>]| -> Vec<Arc<str>> {
+        names.iter().filter(|name| !base.contains(name)).cloned().collect()
+    };
+
+    write_comments(f, &task.comments)?;
+    if !task.when.is_empty() && task.when != base.when {
+        writeln!(f, "@when({})", list(&task.when))?;
+    }
+    if let Some(priority) = task.priority.filter(|_| task.priority != base.priority) {
+        writeln!(f, "@priority: {priority}")?;
+    }
+    if let Some(period) = task.period.filter(|_| task.period != base.period) {
+        writeln!(f, "@period: {period}")?;
+    }
+    if let Some(deadline) = task.deadline.filter(|_| task.deadline != base.deadline) {
+        writeln!(f, "@deadline: {deadline}")?;
+    }
+    let requires = added(&task.requires, &base.requires);
+    if !requires.is_empty() {
+        write_section(f, "@requires:", sorted_list(&requires))?;
+    }
+    let on_port = added(&task.on_port, &base.on_port);
+    if !on_port.is_empty() {
+        write_section(f, "@on_port:", sorted_list(&on_port))?;
+    }
+    let uses = added(&task.uses, &base.uses);
+    if !uses.is_empty() {
+        write_section(f, "@uses:", sorted_list(&uses))?;
+    }
+    match template {
+        Some(template) => {
+            let sensors: Vec<_> = task.args.iter().map(|arg| arg.sensor.clone()).collect();
+            write!(f, "{keyword} {} = {}({})", name(&task.name), name(&template.name), list(&sensors))?
+        }
+        None => {
+            write!(f, "{keyword} {}({}): ", name(&task.name), args(&task.args))?;
+            if let Some(bcet) = task.bcet {
+                write!(f, "{}..", length(bcet, task.bcet_duration))?;
+            }
+            write!(f, "{}", length(task.cycles, task.duration))?
+        }
+    }
+    match &task.trailing_comment {
+        Some(comment) => writeln!(f, " {comment}")?,
+        None => writeln!(f)?,
+    }
+    let satisfies = added(&task.satisfies, &base.satisfies);
+    if !satisfies.is_empty() {
+        write_section(f, "@satisfies:", sorted_list(&satisfies))?;
+    }
+    Ok(())
+}
+
+impl Display for Task_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_task(f, self, "Task", None)
+    }
+}
+
+impl Display for Conf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_comments(f, &self.comments.inports)?;
+        write_section(f, "IN:", sorted_list(&self.inports))?;
+        write_comments(f, &self.comments.outports)?;
+        write_section(f, "OUT:", sorted_list(&self.outports))?;
+        for ports in &self.guarded_ports {
+            write_comments(f, &ports.comments)?;
+            writeln!(f, "@when({})", list(&ports.when))?;
+            if !ports.inports.is_empty() || ports.outports.is_empty() {
+                write_section(f, "IN:", sorted_list(&ports.inports))?;
+            }
+            if !ports.outports.is_empty() {
+                write_section(f, "OUT:", sorted_list(&ports.outports))?;
+            }
+        }
+        writeln!(f)?;
+        write_comments(f, &self.comments.initial)?;
+        write_section(f, "INIT_CONDITIONS:", sorted_list(&self.initial))?;
+
+        // templates of included files are written in those files
+        let included: usize = self.includes.iter().map(|include| include.templates).sum();
+        for template in &self.templates[..self.templates.len() - included] {
+            writeln!(f)?;
+            write_task(f, template, "Template", None)?;
+        }
+
+        // included tasks are written as the directive that brought them in
+        let mut includes = self.includes.iter().peekable();
+        let mut i = 0;
+        loop {
+            while let Some(include) = includes.next_if(|include| include.position == i) {
+                writeln!(f)?;
+                write_comments(f, &include.comments)?;
+                writeln!(f, "INCLUDE: {}", name(&include.path))?;
+                i += include.tasks;
+            }
+            let Some(task) = self.tasks.get(i) else {
+                break;
+            };
+            let template = task
+                .template
+                .as_ref()
+                .and_then(|name| self.templates.iter().find(|t| t.name == *name));
+            writeln!(f)?;
+            write_task(f, task, "Task", template.map(|t| &**t))?;
+            i += 1;
+        }
+
+        if !self.comments.trailing.is_empty() {
+            writeln!(f)?;
+            write_comments(f, &self.comments.trailing)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{conf_from_str, Conf};
+
+    fn format(text: &str) -> String {
+        let conf: Conf = conf_from_str("tasks.conf", text, 1, "1us".parse().unwrap()).unwrap();
+        conf.to_string()
+    }
+
+    const MESSY: &str = "\
+# ports
+IN:b,a
+OUT:   c
+@when(lab) IN: d
+INIT_CONDITIONS: y,x
+# shared
+@priority: 1
+Template poll(S:r, T): 2
+@satisfies: polled
+Task \"IN\"(S:r,T:w): 250us..1ms // slow
+@satisfies: z, w
+@requires: x
+@priority: 0
+Task p = poll(A, B) # instance
+@when(lab, flight)
+@deadline: 4
+@period: 8
+Task q(S): 3
+# end
+";
+
+    #[test]
+    fn canonical() {
+        assert_eq!(
+            format(MESSY),
+            "\
+# ports
+IN: a, b
+OUT: c
+@when(lab)
+IN: d
+
+INIT_CONDITIONS: x, y
+
+# shared
+@priority: 1
+Template poll(S:r, T): 2
+@satisfies: polled
+
+Task \"IN\"(S:r, T): 250us..1ms // slow
+@satisfies: w, z
+
+@priority: 0
+@requires: x
+Task p = poll(A, B) # instance
+
+@when(lab, flight)
+@period: 8
+@deadline: 4
+Task q(S): 3
+
+# end
+"
+        );
+    }
+
+    #[test]
+    fn idempotent() {
+        let formatted = format(MESSY);
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    fn instances_keep_what_differs_from_their_template() {
+        let text = "IN:\nOUT:\n\nINIT_CONDITIONS:\n\n@priority: 2\nTemplate t(S): 1\n\n@priority: 0\nTask a = t(A)\n\nTask b = t(B)\n";
+        assert_eq!(format(text), text);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{iter::Peekable, str::CharIndices, sync::Arc};
+
+use crate::error::{Span, SyntaxError, SyntaxErrorKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Lexeme {
+    /// A bare word: keyword, name or number
+    Word(Arc<str>),
+    /// A `"quoted"` name, never treated as a keyword
+    Quoted(Arc<str>),
+    Colon,
+    Comma,
+    LParen,
+    RParen,
+    At,
+    Equals,
+    /// A `#` or `//` comment up to the end of its line
+    Comment(Arc<str>),
+}
+
+pub(crate) struct Lexer<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, ':' | ',' | '(' | ')' | '@' | '=' | '"' | '#')
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer {
+            input,
+            chars: input.char_indices().peekable(),
+        }
+    }
+
+    fn starts_comment(&self, at: usize) -> bool {
+        self.input[at..].starts_with('#') || self.input[at..].starts_with("//")
+    }
+
+    fn skip_to_eol(&mut self) -> usize {
+        while self.chars.next_if(|(_, c)| *c != '\n').is_some() {}
+        self.chars.peek().map_or(self.input.len(), |(at, _)| *at)
+    }
+
+    fn next_lexeme(&mut self) -> Option<Result<(Lexeme, Span), SyntaxError>> {
+        // whitespace carries no meaning
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+        let &(at, _) = self.chars.peek()?;
+        if self.starts_comment(at) {
+            let end = self.skip_to_eol();
+            let comment = self.input[at..end].trim_end();
+            return Some(Ok((Lexeme::Comment(comment.into()), Span::new(at, at + comment.len()))));
+        }
+
+        let (start, c) = self.chars.next()?;
+        let single = |lexeme| Some(Ok((lexeme, Span::new(start, start + 1))));
+        match c {
+            ':' => single(Lexeme::Colon),
+            ',' => single(Lexeme::Comma),
+            '(' => single(Lexeme::LParen),
+            ')' => single(Lexeme::RParen),
+            '@' => single(Lexeme::At),
+            '=' => single(Lexeme::Equals),
+            '"' => {
+                let Some((end, _)) = self.chars.find(|(_, c)| *c == '"' || *c == '\n') else {
+                    return Some(Err(SyntaxError::new(
+                        SyntaxErrorKind::UnterminatedQuote,
+                        Span::new(start, self.input.len()),
+                    )));
+                };
+                if &self.input[end..end + 1] == "\n" {
+                    return Some(Err(SyntaxError::new(
+                        SyntaxErrorKind::UnterminatedQuote,
+                        Span::new(start, end),
+                    )));
+                }
+                let name = &self.input[start + 1..end];
+                Some(Ok((Lexeme::Quoted(name.into()), Span::new(start, end + 1))))
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(at, c)) = self.chars.peek() {
+                    if !is_word_char(c) || self.starts_comment(at) {
+                        break;
+                    }
+                    end = at + c.len_utf8();
+                    self.chars.next();
+                }
+                let word = &self.input[start..end];
+                Some(Ok((Lexeme::Word(word.into()), Span::new(start, end))))
+            }
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<(Lexeme, Span), SyntaxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_lexeme()
+    }
+}
+
+/// Whether `name` has to be written as `"name"` to be read back as the same name.
+pub(crate) fn needs_quotes(name: &str) -> bool {
+    name.is_empty()
+        || matches!(name, "IN" | "OUT" | "INIT_CONDITIONS" | "INCLUDE" | "Task" | "Template")
+        || name.contains("//")
+        || !name.chars().all(is_word_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(input: &str) -> Vec<Lexeme> {
+        Lexer::new(input).map(|lexeme| lexeme.unwrap().0).collect()
+    }
+
+    fn word(w: &str) -> Lexeme {
+        Lexeme::Word(w.into())
+    }
+
+    #[test]
+    fn words_symbols_and_comments() {
+        assert_eq!(
+            lex("Task t(S:r, T) = x # a comment\n@period: 4 // another"),
+            [
+                word("Task"),
+                word("t"),
+                Lexeme::LParen,
+                word("S"),
+                Lexeme::Colon,
+                word("r"),
+                Lexeme::Comma,
+                word("T"),
+                Lexeme::RParen,
+                Lexeme::Equals,
+                word("x"),
+                Lexeme::Comment("# a comment".into()),
+                Lexeme::At,
+                word("period"),
+                Lexeme::Colon,
+                word("4"),
+                Lexeme::Comment("// another".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_end_words() {
+        assert_eq!(lex("a//b"), [word("a"), Lexeme::Comment("//b".into())]);
+        assert_eq!(lex("a#b"), [word("a"), Lexeme::Comment("#b".into())]);
+        // a single slash is part of a word
+        assert_eq!(lex("../common/a.conf"), [word("../common/a.conf")]);
+    }
+
+    #[test]
+    fn spans() {
+        let spans: Vec<Span> = Lexer::new("IN: é,\n  \"b c\"").map(|lexeme| lexeme.unwrap().1).collect();
+        assert_eq!(spans, [Span::new(0, 2), Span::new(2, 3), Span::new(4, 6), Span::new(6, 7), Span::new(10, 15)]);
+    }
+
+    #[test]
+    fn quoted_names() {
+        assert_eq!(lex("\"IN\" \"a b\""), [Lexeme::Quoted("IN".into()), Lexeme::Quoted("a b".into())]);
+        let error = Lexer::new("\"a\nb\"").next().unwrap().unwrap_err();
+        assert_eq!((error.kind, error.span), (SyntaxErrorKind::UnterminatedQuote, Span::new(0, 2)));
+        let error = Lexer::new("x \"a").nth(1).unwrap().unwrap_err();
+        assert_eq!((error.kind, error.span), (SyntaxErrorKind::UnterminatedQuote, Span::new(2, 4)));
+    }
+
+    #[test]
+    fn names_needing_quotes() {
+        for name in ["", "IN", "Task", "a b", "a:b", "a//b", "a#b", "a=b"] {
+            assert!(needs_quotes(name), "{name:?}");
+        }
+        for name in ["a", "task_1", "in", "a/b", "é"] {
+            assert!(!needs_quotes(name), "{name:?}");
+        }
+    }
+}
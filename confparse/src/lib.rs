@@ -1,27 +1,137 @@
+use crate::error::SyntaxError;
 use crate::parse::Keyword;
 use crate::parse::Symbol;
 use crate::parse::Token;
+use crate::parse::Parsed;
+use crate::simpleiter::{CommentIter, SimpleIter};
+use serde::{Deserialize, Serialize};
 use std::fs::read_to_string;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::vec;
 
+mod encoding;
+mod error;
+mod format;
+mod lexer;
 mod parse;
 mod simpleiter;
+mod time;
 
-#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub use encoding::Format;
+pub use error::{ConfError, Diagnostic, Span, SyntaxErrorKind};
+pub use time::{Duration, TimeUnit};
+
+/// A task of an OBC. Serialized through `encoding::TaskFile`, where `cycles`
+/// holds either the ticks or the duration as written.
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(into = "encoding::TaskFile", from = "encoding::TaskFile")]
 pub struct Task_ {
     pub name: Arc<str>,
-    pub args: Vec<Arc<str>>,
+    pub args: Vec<Arg>,
     pub requires: Vec<Arc<str>>,
     pub satisfies: Vec<Arc<str>>,
+    /// Ports of `@on_port:`. On the OBC which reads a port the task waits for the
+    /// task which writes it, on the OBC which writes it the task is that writer.
+    pub on_port: Vec<Arc<str>>,
+    /// Shared resources of `sensors.json` the task has to itself while it runs,
+    /// such as a bus. Unlike sensors they are not passed to the task function.
+    pub uses: Vec<Arc<str>>,
+    /// Length of the task in ticks, its worst case (WCET) when a range is given
     pub cycles: u16,
-    pub obc_id: u32
+    /// The length as written when it was given with a unit, such as `250us`
+    pub duration: Option<Duration>,
+    /// Best case length (BCET) in ticks of a `best..worst` range
+    pub bcet: Option<u16>,
+    /// The best case as written when it was given with a unit
+    pub bcet_duration: Option<Duration>,
+    /// Release the task every `period` cycles instead of once per loop
+    pub period: Option<u16>,
+    /// Cycles after its release by which the task must finish, defaults to the period
+    pub deadline: Option<u16>,
+    /// Higher priority tasks are scheduled first, 0 when not given
+    pub priority: Option<u16>,
+    /// Taken from the folder of the OBC, not from the file
+    pub obc_id: u32,
+    /// The template this task was instantiated from
+    pub template: Option<Arc<str>>,
+    /// Profiles in which the task is built, every profile when empty
+    pub when: Vec<Arc<str>>,
+    pub comments: Vec<Arc<str>>,
+    /// Comment at the end of the `Task` line, which stays there when formatted
+    pub trailing_comment: Option<Arc<str>>,
+}
+
+impl Task_ {
+    /// The priority of the task, 0 when not given
+    pub fn priority(&self) -> u16 {
+        self.priority.unwrap_or_default()
+    }
+}
+
+/// Whether something guarded by `@when(when)` is built in `profile`
+fn is_active(when: &[Arc<str>], profile: Option<&str>) -> bool {
+    when.is_empty() || profile.is_some_and(|profile| when.iter().any(|p| &**p == profile))
+}
+
+/// The OBC and name of a condition of `@requires:` written `obc<id>.<name>`, which
+/// is satisfied by the tasks of that OBC. Other conditions belong to the task's OBC.
+pub fn qualified_condition(condition: &str) -> Option<(u32, &str)> {
+    let (obc, name) = condition.split_once('.')?;
+    let id = obc.strip_prefix("obc")?;
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) || name.is_empty() {
+        return None;
+    }
+    Some((id.parse().ok()?, name))
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Task(Arc<Task_>);
 
+/// How a task uses a sensor. Any number of tasks may read a sensor at once,
+/// a task writing it has it to itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum Access {
+    /// `SENSOR:r`
+    Read,
+    /// `SENSOR:w`, or `SENSOR` without a mode
+    #[default]
+    Write,
+}
+
+/// A sensor passed to a task, written `TEMP:r` in TOML and JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
+pub struct Arg {
+    pub sensor: Arc<str>,
+    pub access: Access,
+}
+
+impl From<Arg> for String {
+    fn from(arg: Arg) -> Self {
+        match arg.access {
+            Access::Read => format!("{}:r", arg.sensor),
+            Access::Write => arg.sensor.to_string(),
+        }
+    }
+}
+
+impl From<String> for Arg {
+    fn from(arg: String) -> Self {
+        let (sensor, access) = match arg.rsplit_once(':') {
+            Some((sensor, "r")) => (sensor, Access::Read),
+            Some((sensor, "w")) => (sensor, Access::Write),
+            _ => (arg.as_str(), Access::Write),
+        };
+        Arg {
+            sensor: sensor.into(),
+            access,
+        }
+    }
+}
+
 impl Deref for Task {
     type Target = Task_;
 
@@ -31,170 +141,951 @@ impl Deref for Task {
 }
 
 
-#[derive(Debug, Clone)]
+/// Comments of a `tasks.conf`, kept so the file can be written back out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Comments {
+    #[serde(rename = "in", skip_serializing_if = "Vec::is_empty")]
+    pub inports: Vec<Arc<str>>,
+    #[serde(rename = "out", skip_serializing_if = "Vec::is_empty")]
+    pub outports: Vec<Arc<str>>,
+    #[serde(rename = "init_conditions", skip_serializing_if = "Vec::is_empty")]
+    pub initial: Vec<Arc<str>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trailing: Vec<Arc<str>>,
+}
+
+impl Comments {
+    pub fn is_empty(&self) -> bool {
+        self.inports.is_empty()
+            && self.outports.is_empty()
+            && self.initial.is_empty()
+            && self.trailing.is_empty()
+    }
+}
+
+/// `IN:` and `OUT:` lists after a `@when(...)`, added to the ports of the OBC in those profiles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuardedPorts {
+    pub when: Vec<Arc<str>>,
+    #[serde(rename = "in", default, skip_serializing_if = "Vec::is_empty")]
+    pub inports: Vec<Arc<str>>,
+    #[serde(rename = "out", default, skip_serializing_if = "Vec::is_empty")]
+    pub outports: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<Arc<str>>,
+}
+
+/// An `INCLUDE:` directive of a `tasks.conf`.
+///
+/// The included tasks are spliced into `Conf::tasks`, the directive is kept
+/// so the file can be written back out with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Include {
+    /// Path as written, relative to the including file
+    pub path: Arc<str>,
+    /// Index in `Conf::tasks` of the first included task
+    pub position: usize,
+    /// Number of tasks spliced in, including those of nested includes
+    #[serde(skip)]
+    pub tasks: usize,
+    /// Number of templates appended to `Conf::templates`, including those of nested includes
+    #[serde(skip)]
+    pub templates: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<Arc<str>>,
+    #[serde(skip)]
+    span: Span,
+}
+
+/// The configuration of one OBC.
+///
+/// Serializes to the items written in the file: tasks brought in by an
+/// `INCLUDE:` are left out and loaded again from the included file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "encoding::ConfFile", try_from = "encoding::ConfFile")]
 pub struct Conf {
     pub inports: Vec<Arc<str>>,
     pub outports: Vec<Arc<str>>,
+    /// Ports only used in some profiles
+    pub guarded_ports: Vec<GuardedPorts>,
     pub initial: Vec<Arc<str>>,
-    pub tasks: Vec<Task>
+    pub tasks: Vec<Task>,
+    /// Templates declared in the file, followed by those of included files.
+    /// The sensors of a template are its parameters.
+    pub templates: Vec<Task>,
+    pub includes: Vec<Include>,
+    pub comments: Comments
+}
+
+impl Conf {
+    /// The configuration built in `profile`: tasks of other profiles are left out and the
+    /// guarded ports of `profile` are added to the others. Without a profile only what is
+    /// not guarded is kept.
+    ///
+    /// The result is for scheduling and code generation, it has no includes to write back.
+    pub fn for_profile(&self, profile: Option<&str>) -> Conf {
+        let mut conf = self.clone();
+        for ports in std::mem::take(&mut conf.guarded_ports) {
+            if is_active(&ports.when, profile) {
+                conf.inports.extend(ports.inports);
+                conf.outports.extend(ports.outports);
+            }
+        }
+        conf.tasks.retain(|task| is_active(&task.when, profile));
+        conf.includes.clear();
+        conf
+    }
+
+    /// Every profile named in a `@when(...)`
+    pub fn profiles(&self) -> Vec<Arc<str>> {
+        let mut profiles: Vec<_> = self
+            .guarded_ports
+            .iter()
+            .flat_map(|ports| ports.when.iter())
+            .chain(self.tasks.iter().flat_map(|task| task.when.iter()))
+            .cloned()
+            .collect();
+        profiles.sort();
+        profiles.dedup();
+        profiles
+    }
 }
 
-fn parse_keyword(next_token: Token, keyword_type: Keyword) -> Result<(), String> {
+fn parse_keyword((next_token, span): (Token, Span), keyword_type: Keyword) -> Result<(), SyntaxError> {
     // Errors if keyword_type does not matches
-    let Token::Keyword(x) = next_token else {
-        Err(format!("Not Keyword: {next_token:?}, expected: {keyword_type:?}").to_string())?
-    };
-    if x != keyword_type {
-        Err(format!("Different type keyword, expected : {keyword_type:?} , got: {next_token:?}").to_string())?
+    if !matches!(next_token, Token::Keyword(x) if x == keyword_type) {
+        Err(SyntaxError::new(SyntaxErrorKind::Unexpected { expected: keyword_type.to_string(), found: next_token.to_string() }, span))?
     };
     Ok(())
 }
 
-fn parse_symbol(next_token: Token, symbol_type: Symbol) -> Result<(), String> {
+fn parse_symbol((next_token, span): (Token, Span), symbol_type: Symbol) -> Result<(), SyntaxError> {
     // Errors if keyword_type does not matches
-    let Token::Symbol(x) = next_token else {
-        Err(format!("Not Symbol : {next_token:?}").to_string())?
-    };
-    if x != symbol_type {
-        Err(format!("Different type keyword, expected : {symbol_type:?} , got: {next_token:?}").to_string())?
+    if !matches!(next_token, Token::Symbol(x) if x == symbol_type) {
+        Err(SyntaxError::new(SyntaxErrorKind::Unexpected { expected: Token::Symbol(symbol_type).to_string(), found: next_token.to_string() }, span))?
     };
     Ok(())
 }
 
-fn get_token(tokens: &mut std::slice::Iter<'_, Token>) -> Result<Token, String> {
-    return tokens
-        .next()
-        .ok_or("Failed to extract token".to_string())
-        .cloned();
+fn populate(inports: &mut Vec<Arc<str>>, tokens: &mut SimpleIter) -> Result<(), SyntaxError> {
+    parse_symbol(tokens.next_token("'['")?, Symbol::StartArray)?;
+
+    let token = loop {
+        match tokens.next_token("a name or ']'")? {
+            (Token::Literal(x), _) => {
+                inports.push(x);
+            }
+            token => break token,
+        }
+    };
+
+    parse_symbol(token, Symbol::EndArray)?;
+    Ok(())
 }
 
-fn populate( inports: &mut Vec<Arc<str>>, tokens: &mut std::slice::Iter<'_, Token>) -> Result<(), String>{
-    let mut token = get_token( tokens)?;
-    parse_symbol(token.clone(), Symbol::StartArray)?;
+fn populate_args(args: &mut Vec<Arg>, tokens: &mut SimpleIter) -> Result<(), SyntaxError> {
+    parse_symbol(tokens.next_token("'('")?, Symbol::StartArray)?;
 
-    loop {
-        let tmp_token = match get_token(tokens) {
-            Ok(token) => token,
-            Err(e) => return Err(e), // Directly return on error
-        };
-    
-        match tmp_token {
-            Token::Literal(x) => {
-                inports.push(x.into());
-            }
-            _ => {
-                token = tmp_token;
-                break;
+    let token = loop {
+        match tokens.next_token("a sensor or ')'")? {
+            (Token::Literal(sensor), _) => {
+                let mut access = Access::default();
+                if let Some((Token::Access(mode), _)) = tokens.peek() {
+                    access = *mode;
+                    tokens.next_token("access mode")?;
+                }
+                args.push(Arg { sensor, access });
             }
+            token => break token,
         }
-    }
-    
-    parse_symbol(token.clone(), Symbol::EndArray)?;
+    };
+
+    parse_symbol(token, Symbol::EndArray)?;
     Ok(())
 }
 
-fn parse_conf(tokens: &mut std::slice::Iter<'_, Token>) -> Result<Conf, String> {
+fn parse_number(tokens: &mut SimpleIter) -> Result<u16, SyntaxError> {
+    // the value was checked to be a number while tokenizing
+    let (token, span) = tokens.next_token("number")?;
+    match token {
+        Token::Literal(x) => x
+            .parse()
+            .map_err(|_| SyntaxError::new(SyntaxErrorKind::InvalidCycles(x.clone()), span)),
+        token => Err(SyntaxError::new(SyntaxErrorKind::Unexpected { expected: "number".to_string(), found: token.to_string() }, span)),
+    }
+}
+
+fn parse_conf(tokens: &mut SimpleIter, comments: &mut CommentIter) -> Result<Conf, SyntaxError> {
     let mut config = Conf {
         inports: vec![],
         initial: vec![],
         outports: vec![],
-        tasks: vec![]
+        guarded_ports: vec![],
+        tasks: vec![],
+        templates: vec![],
+        includes: vec![],
+        comments: Comments::default()
     };
-    
-    let mut token = get_token(tokens)?;
-    parse_keyword(token.clone(), Keyword::IN)?;
-    populate(&mut config.inports, tokens )?;
-    
-    token = get_token(tokens)?;
-    parse_keyword(token.clone(), Keyword::OUT)?;
-    populate(&mut config.outports,tokens)?;
-    
-    token = get_token(tokens)?;
-    parse_keyword(token.clone(), Keyword::INIT_CONDITIONS)?;
-    populate(&mut config.initial,tokens)?;
-    
+
+    parse_keyword(tokens.next_token("IN:")?, Keyword::In)?;
+    populate(&mut config.inports, tokens)?;
+    config.comments.inports = comments.take_until(tokens.position());
+
+    parse_keyword(tokens.next_token("OUT:")?, Keyword::Out)?;
+    populate(&mut config.outports, tokens)?;
+    config.comments.outports = comments.take_until(tokens.position());
+
+    // @when(...) followed by IN: and OUT: lists, before INIT_CONDITIONS:
+    while let Some((Token::Keyword(Keyword::When), _)) = tokens.peek() {
+        tokens.next_token("@when")?;
+        let mut ports = GuardedPorts {
+            when: vec![],
+            inports: vec![],
+            outports: vec![],
+            comments: vec![],
+        };
+        populate(&mut ports.when, tokens)?;
+        let has_in = matches!(tokens.peek(), Some((Token::Keyword(Keyword::In), _)));
+        if has_in {
+            tokens.next_token("IN:")?;
+            populate(&mut ports.inports, tokens)?;
+        }
+        if let Some((Token::Keyword(Keyword::Out), _)) = tokens.peek() {
+            tokens.next_token("OUT:")?;
+            populate(&mut ports.outports, tokens)?;
+        } else if !has_in {
+            let (token, span) = tokens.next_token("IN: or OUT: after @when")?;
+            Err(SyntaxError::new(SyntaxErrorKind::Unexpected { expected: "IN: or OUT: after @when".to_string(), found: token.to_string() }, span))?
+        }
+        ports.comments = comments.take_until(tokens.position());
+        config.guarded_ports.push(ports);
+    }
+
+    parse_keyword(tokens.next_token("INIT_CONDITIONS:")?, Keyword::InitConditions)?;
+    populate(&mut config.initial, tokens)?;
+    config.comments.initial = comments.take_until(tokens.position());
+
     Ok(config)
 }
 
-fn parse_tasks( tokens: &mut std::slice::Iter<'_, Token>, obc_id: u32) -> Result<Task, String>{
+/// A `Task` or a `Template` declaration
+enum Declaration {
+    Task(Task),
+    Template(Task),
+    /// A task instantiating a template, with the span of the template's name. It is
+    /// expanded once the templates of the included files are known.
+    Instance(Task, Span),
+}
+
+/// What a task is parsed for: the OBC it runs on and the tick its duration is counted in
+#[derive(Clone, Copy)]
+struct Context {
+    obc_id: u32,
+    tick: Duration,
+}
+
+/// Converts a task length with a unit into whole ticks
+fn to_ticks(duration: Duration, tick: Duration, span: Span) -> Result<u16, SyntaxError> {
+    if !duration.is_multiple_of(tick) {
+        Err(SyntaxError::new(SyntaxErrorKind::UnevenDuration { duration, tick }, span))?
+    }
+    duration
+        .ticks(tick)
+        .ok_or_else(|| SyntaxError::new(SyntaxErrorKind::DurationOverflow { duration, tick }, span))
+}
+
+/// Reads a task length in ticks or with a unit, returning the ticks and the time as written
+fn parse_length(text: &str, tick: Duration, span: Span) -> Result<(u16, Option<Duration>), SyntaxError> {
+    if let Ok(cycles) = text.parse() {
+        return Ok((cycles, None));
+    }
+    let duration = text
+        .parse()
+        .map_err(|_| SyntaxError::new(SyntaxErrorKind::InvalidCycles(text.into()), span))?;
+    Ok((to_ticks(duration, tick, span)?, Some(duration)))
+}
+
+/// Checks that the best case of `task` is no longer than its worst case
+fn check_range(task: &Task_, span: Span) -> Result<(), SyntaxError> {
+    match task.bcet {
+        Some(bcet) if bcet > task.cycles => Err(SyntaxError::new(
+            SyntaxErrorKind::InvertedRange {
+                best: format::length(bcet, task.bcet_duration).into(),
+                worst: format::length(task.cycles, task.duration).into(),
+            },
+            span,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Union of two lists, keeping the order of `first`
+fn union(first: &[Arc<str>], second: &[Arc<str>]) -> Vec<Arc<str>> {
+    let mut names = first.to_vec();
+    names.extend(second.iter().filter(|name| !first.contains(name)).cloned());
+    names
+}
+
+/// Expands an instance of `template`: the sensors of `instance` take the place of the
+/// template parameters, its annotations override those of the template and its
+/// conditions are added to the template's.
+fn instantiate(template: &Task_, instance: &Task_, span: Span) -> Result<Task_, SyntaxError> {
+    if template.args.len() != instance.args.len() {
+        Err(SyntaxError::new(
+            SyntaxErrorKind::TemplateArity {
+                template: template.name.clone(),
+                expected: template.args.len(),
+                found: instance.args.len(),
+            },
+            span,
+        ))?
+    }
+    Ok(Task_ {
+        name: instance.name.clone(),
+        args: template
+            .args
+            .iter()
+            .zip(&instance.args)
+            .map(|(param, arg)| Arg {
+                sensor: arg.sensor.clone(),
+                access: param.access,
+            })
+            .collect(),
+        requires: union(&template.requires, &instance.requires),
+        satisfies: union(&template.satisfies, &instance.satisfies),
+        on_port: union(&template.on_port, &instance.on_port),
+        uses: union(&template.uses, &instance.uses),
+        cycles: template.cycles,
+        duration: template.duration,
+        bcet: template.bcet,
+        bcet_duration: template.bcet_duration,
+        period: instance.period.or(template.period),
+        deadline: instance.deadline.or(template.deadline),
+        priority: instance.priority.or(template.priority),
+        obc_id: instance.obc_id,
+        template: Some(template.name.clone()),
+        when: if instance.when.is_empty() { template.when.clone() } else { instance.when.clone() },
+        comments: instance.comments.clone(),
+        trailing_comment: instance.trailing_comment.clone(),
+    })
+}
+
+fn parse_tasks(
+    tokens: &mut SimpleIter,
+    comments: &mut CommentIter,
+    context: Context,
+    templates: &[Task],
+) -> Result<Declaration, SyntaxError> {
     let mut task = Task_{
         name:"".into(),
         args:vec![],
         requires:vec![],
         satisfies:vec![],
+        on_port:vec![],
+        uses:vec![],
         cycles: 0,
-        obc_id
-    };
-    // requires
-    // Checking manually so if no token the we return NULL
-    let mut token = match get_token(tokens) {
-        Ok(token) => token,
-        Err(_) => Err("EMPTY")? // TODO: to later convert all thses errors to ENUMS
+        duration: None,
+        bcet: None,
+        bcet_duration: None,
+        period: None,
+        deadline: None,
+        priority: None,
+        obc_id: context.obc_id,
+        template: None,
+        when: vec![],
+        comments: vec![],
+        trailing_comment: None,
     };
-    match parse_keyword(token.clone(), Keyword::REQUIRES){
-        Ok(_) => {
-            populate(&mut task.requires, tokens)?;
-            token = get_token(tokens)?;
-        },
-        Err(_) => {}
-    };
-    //
+    // annotations before the task, in any order
+    let mut token = tokens.next_token("Task")?;
+    let mut seen = vec![];
+    while let (Token::Keyword(keyword), span) = &token {
+        if seen.contains(keyword) {
+            Err(SyntaxError::new(SyntaxErrorKind::DuplicateSection(keyword.to_string().into()), *span))?
+        }
+        match keyword {
+            Keyword::Requires => populate(&mut task.requires, tokens)?,
+            Keyword::OnPort => populate(&mut task.on_port, tokens)?,
+            Keyword::Uses => populate(&mut task.uses, tokens)?,
+            Keyword::Period => task.period = Some(parse_number(tokens)?),
+            Keyword::Deadline => task.deadline = Some(parse_number(tokens)?),
+            Keyword::Priority => task.priority = Some(parse_number(tokens)?),
+            Keyword::When => populate(&mut task.when, tokens)?,
+            _ => break,
+        }
+        seen.push(*keyword);
+        token = tokens.next_token("Task")?;
+    }
+
+    let is_template = matches!(token, (Token::Keyword(Keyword::Template), _));
+    if !is_template {
+        parse_keyword(token, Keyword::Task)?;
+    }
 
-    parse_keyword(token.clone(), Keyword::TASK)?;
-    
     // task name
-    token = get_token(tokens)?;
+    let (token, span) = tokens.next_token("task name")?;
     let Token::Literal(x) = token else {
-        Err("Task Name Expected")?
+        Err(SyntaxError::new(SyntaxErrorKind::MissingTaskName, span))?
     };
-    task.name = <String as Into<Arc<str>>>::into(x.to_string());
-    populate(&mut task.args, tokens)?;
-    // 
+    task.name = x;
 
-    // cycles
-    token = get_token(tokens)?;
-    let Token::Literal(x) = token else {
-        Err("Task Name Expected")?
-    };
-    task.cycles = x.parse().unwrap();
-    //
-
-    // satisfies
-    if let Ok(token) = get_token(tokens) {
-        match parse_keyword(token.clone(), Keyword::SATISFIES){
-            Ok(_) => {
-                populate(&mut task.satisfies, tokens)?;
-            },
-            Err(_) => {}
+    // `Task name = template(sensors)`, expanded once the whole instance is read
+    let mut template = None;
+    if let Some((Token::Symbol(Symbol::Equals), _)) = tokens.peek() {
+        tokens.next_token("'='")?;
+        let (token, span) = tokens.next_token("template name")?;
+        let Token::Literal(name) = token else {
+            Err(SyntaxError::new(SyntaxErrorKind::Unexpected { expected: "template name".to_string(), found: token.to_string() }, span))?
         };
+        template = Some((name, span));
+    }
+    populate_args(&mut task.args, tokens)?;
+
+    // cycles, which instances take from their template
+    if template.is_none() {
+        let (token, span) = tokens.next_token("cycles")?;
+        let Token::Literal(x) = token else {
+            Err(SyntaxError::new(SyntaxErrorKind::Unexpected { expected: "cycles".to_string(), found: token.to_string() }, span))?
+        };
+        let worst = match x.split_once("..") {
+            Some((best, worst)) => {
+                (task.bcet, task.bcet_duration) = parse_length(best, context.tick, span)
+                    .map(|(ticks, duration)| (Some(ticks), duration))?;
+                worst
+            }
+            None => &x,
+        };
+        (task.cycles, task.duration) = parse_length(worst, context.tick, span)?;
+        check_range(&task, span)?;
+    }
+    let (mut leading, trailing) = comments.take_line(tokens.position());
+    task.trailing_comment = trailing;
+
+    // satisfies, only consumed if the next token is actually @satisfies
+    if let Some((Token::Keyword(Keyword::Satisfies), _)) = tokens.peek() {
+        tokens.next_token("@satisfies")?;
+        populate(&mut task.satisfies, tokens)?;
     }
-    //
-    
-    Ok(Task(Arc::new(task)))
+
+    leading.extend(comments.take_until(tokens.position()));
+    task.comments = leading;
+
+    match template {
+        None if is_template => Ok(Declaration::Template(Task(Arc::new(task)))),
+        None => Ok(Declaration::Task(Task(Arc::new(task)))),
+        // a template made from another one is expanded right away, from the templates before it
+        Some((name, span)) if is_template => {
+            let Some(found) = templates.iter().find(|t| t.name == name) else {
+                Err(SyntaxError::new(SyntaxErrorKind::UnknownTemplate(name), span))?
+            };
+            Ok(Declaration::Template(Task(Arc::new(instantiate(found, &task, span)?))))
+        }
+        Some((name, span)) => {
+            task.template = Some(name);
+            Ok(Declaration::Instance(Task(Arc::new(task)), span))
+        }
+    }
+}
+
+/// Expands the instances of a file, given by their index in `tasks` and the span of
+/// the name of their template, with the templates of the file and of its includes.
+fn expand_instances(tasks: &mut [Task], templates: &[Task], instances: &[(usize, Span)]) -> Result<(), SyntaxError> {
+    for &(i, span) in instances {
+        let name = tasks[i].template.clone().unwrap_or_default();
+        let Some(template) = templates.iter().find(|t| t.name == name) else {
+            Err(SyntaxError::new(SyntaxErrorKind::UnknownTemplate(name), span))?
+        };
+        tasks[i] = Task(Arc::new(instantiate(template, &tasks[i], span)?));
+    }
+    Ok(())
 }
 
-fn coder(tokens: Vec<Token>, obc_id: u32) -> Result<Conf, String>{
-    let mut tokens_iter: std::slice::Iter<'_, Token> = tokens.iter();
-    let mut config = parse_conf(&mut tokens_iter)?;
-    loop {
-        let task = match parse_tasks(&mut tokens_iter, obc_id) {
-            Ok(t) => t,
-            Err(e) => match e.as_str() {
-                "EMPTY" => {
-                    break;
+fn parse_include(tokens: &mut SimpleIter, comments: &mut CommentIter, position: usize) -> Result<Include, SyntaxError> {
+    parse_keyword(tokens.next_token("INCLUDE:")?, Keyword::Include)?;
+    let (token, span) = tokens.next_token("path of the included file")?;
+    let Token::Literal(path) = token else {
+        Err(SyntaxError::new(SyntaxErrorKind::Unexpected { expected: "path of the included file".to_string(), found: token.to_string() }, span))?
+    };
+    Ok(Include {
+        path,
+        position,
+        tasks: 0,
+        templates: 0,
+        comments: comments.take_until(tokens.position()),
+        span,
+    })
+}
+
+/// Tasks, templates and includes of a file, before its includes are resolved
+#[derive(Default)]
+struct Items {
+    tasks: Vec<Task>,
+    templates: Vec<Task>,
+    includes: Vec<Include>,
+    /// Tasks instantiating a template, by index in `tasks`, with the span of the template's name
+    instances: Vec<(usize, Span)>,
+}
+
+fn parse_items(tokens: &mut SimpleIter, comments: &mut CommentIter, context: Context) -> Result<Items, SyntaxError> {
+    let mut items = Items::default();
+    // tasks, templates and include directives, in the order they are written
+    while !tokens.is_empty() {
+        if let Some((Token::Keyword(Keyword::Include), _)) = tokens.peek() {
+            items.includes.push(parse_include(tokens, comments, items.tasks.len())?);
+            continue;
+        }
+        let start = tokens.peek().map(|(_, span)| *span);
+        match parse_tasks(tokens, comments, context, &items.templates)? {
+            Declaration::Task(task) => items.tasks.push(task),
+            Declaration::Instance(task, span) => {
+                // the template comes before, in this file or in a file included above
+                let name = task.template.clone().unwrap_or_default();
+                if items.includes.is_empty() && !items.templates.iter().any(|t| t.name == name) {
+                    Err(SyntaxError::new(SyntaxErrorKind::UnknownTemplate(name), span))?
+                }
+                items.instances.push((items.tasks.len(), span));
+                items.tasks.push(task);
+            }
+            Declaration::Template(template) => {
+                if items.templates.iter().any(|t| t.name == template.name) {
+                    let span = start.unwrap_or_default();
+                    Err(SyntaxError::new(SyntaxErrorKind::DuplicateTemplate(template.name.clone()), span))?
                 }
-                _ => {
-                    Err(e)?
+                let early = items.instances.iter().find(|(i, _)| items.tasks[*i].template == Some(template.name.clone()));
+                if let Some((_, span)) = early {
+                    Err(SyntaxError::new(SyntaxErrorKind::UnknownTemplate(template.name.clone()), *span))?
                 }
+                items.templates.push(template);
             }
+        }
+    }
+    Ok(items)
+}
+
+/// The configuration of a `tasks.conf`, with its instances left to [`expand_instances`]
+fn coder(
+    (tokens, comments): Parsed,
+    input: &str,
+    context: Context,
+) -> Result<(Conf, Vec<(usize, Span)>), SyntaxError> {
+    let mut tokens_iter = SimpleIter::new(&tokens, input.trim_end().len());
+    let mut comments = CommentIter::new(input, comments);
+    let mut config = parse_conf(&mut tokens_iter, &mut comments)?;
+    let items = parse_items(&mut tokens_iter, &mut comments, context)?;
+    config.tasks = items.tasks;
+    config.templates = items.templates;
+    config.includes = items.includes;
+    config.comments.trailing = comments.rest();
+    Ok((config, items.instances))
+}
+
+fn read_conf(path: &Path) -> Result<String, ConfError> {
+    read_to_string(path).map_err(|error| ConfError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+fn syntax_error(path: &Path, content: &str, error: SyntaxError) -> ConfError {
+    ConfError::Syntax(Box::new(Diagnostic::new(path, content, error)))
+}
+
+/// Splices the tasks of each include into the tasks of `items` and appends its templates
+/// to theirs. The instances of the file are expanded in between, so that they may use
+/// the templates of the included files.
+///
+/// `stack` holds the canonical paths of the files being included, from the
+/// `tasks.conf` down to `path`, to find include cycles.
+fn resolve_includes(
+    path: &Path,
+    content: &str,
+    context: Context,
+    items: &mut Items,
+    stack: &mut Vec<PathBuf>,
+) -> Result<(), ConfError> {
+    let Items { tasks, templates, includes, instances } = items;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut loaded = vec![];
+    for include in includes.iter_mut() {
+        let error = |kind| syntax_error(path, content, SyntaxError::new(kind, include.span));
+
+        let included_path = dir.join(&*include.path);
+        let canonical = included_path.canonicalize().map_err(|e| {
+            error(SyntaxErrorKind::UnreadableInclude {
+                path: include.path.clone(),
+                error: e.to_string().into(),
+            })
+        })?;
+        if let Some(start) = stack.iter().position(|f| *f == canonical) {
+            let chain = stack[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|f| f.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(error(SyntaxErrorKind::IncludeCycle(chain.into())));
+        }
+
+        stack.push(canonical);
+        let included = load_tasks(&included_path, context, stack)?;
+        stack.pop();
+
+        include.templates = included.templates.len();
+        templates.extend(included.templates);
+        loaded.push(included.tasks);
+    }
+
+    expand_instances(tasks, templates, instances).map_err(|e| syntax_error(path, content, e))?;
+
+    let mut spliced = 0;
+    for (include, included) in includes.iter_mut().zip(loaded) {
+        include.position += spliced;
+        include.tasks = included.len();
+        spliced += included.len();
+        tasks.splice(include.position..include.position, included);
+    }
+    Ok(())
+}
+
+/// Parses a file included with `INCLUDE:`, which holds only tasks, templates and includes.
+fn load_tasks(path: &Path, context: Context, stack: &mut Vec<PathBuf>) -> Result<Items, ConfError> {
+    let content = read_conf(path)?;
+    let mut items = parse::parse(&content)
+        .and_then(|(tokens, comments)| {
+            let mut tokens_iter = SimpleIter::new(&tokens, content.trim_end().len());
+            let mut comments = CommentIter::new(&content, comments);
+            parse_items(&mut tokens_iter, &mut comments, context)
+        })
+        .map_err(|e| syntax_error(path, &content, e))?;
+    resolve_includes(path, &content, context, &mut items, stack)?;
+    Ok(items)
+}
+
+/// Span of the first quoted `text` in a TOML or JSON file, for diagnostics.
+fn find_quoted(content: &str, text: &str) -> Span {
+    content
+        .find(&format!("\"{text}\""))
+        .map_or(Span::default(), |start| Span::new(start + 1, start + 1 + text.len()))
+}
+
+/// Parses the OBC configuration at `path`, splicing in the tasks of included files.
+///
+/// `.toml` and `.json` files are read with serde, any other file as a
+/// `tasks.conf`. Included files always use the `tasks.conf` syntax.
+///
+/// Task lengths written with a unit, such as `250us`, are converted to ticks
+/// of `tick`.
+///
+/// Errors carry the file, line and column of the offending text and
+/// render as a caret diagnostic through `Display`.
+pub fn get_conf(path: impl AsRef<Path>, obc_id: u32, tick: Duration) -> Result<Conf, ConfError> {
+    let path = path.as_ref();
+    conf_from_str(path, &read_conf(path)?, obc_id, tick)
+}
+
+/// Parses `content` as the OBC configuration at `path`, such as the unsaved text
+/// of an editor. Included files are still read from disk, relative to `path`.
+pub fn conf_from_str(path: impl AsRef<Path>, content: &str, obc_id: u32, tick: Duration) -> Result<Conf, ConfError> {
+    let path = path.as_ref();
+    let context = Context { obc_id, tick };
+    let (mut conf, mut instances) = match Format::from_path(path) {
+        Some(Format::Toml) => toml::from_str::<Conf>(content).map_err(|error| ConfError::Toml {
+            path: path.to_path_buf(),
+            error,
+        })
+        .map(|conf| (conf, vec![]))?,
+        Some(Format::Json) => serde_json::from_str::<Conf>(content).map_err(|error| ConfError::Json {
+            path: path.to_path_buf(),
+            error,
+        })
+        .map(|conf| (conf, vec![]))?,
+        _ => parse::parse(content)
+            .and_then(|parsed| coder(parsed, content, context))
+            .map_err(|e| syntax_error(path, content, e))?,
+    };
+    if matches!(Format::from_path(path), Some(Format::Toml | Format::Json)) {
+        for task in conf.templates.iter_mut().chain(&mut conf.tasks) {
+            let mut resolved = Task_ { obc_id, ..(**task).clone() };
+            let error = |e| syntax_error(path, content, e);
+            if let Some(duration) = task.duration {
+                let span = find_quoted(content, &duration.to_string());
+                resolved.cycles = to_ticks(duration, tick, span).map_err(error)?;
+            }
+            if let Some(duration) = task.bcet_duration {
+                let span = find_quoted(content, &duration.to_string());
+                resolved.bcet = Some(to_ticks(duration, tick, span).map_err(error)?);
+            }
+            check_range(&resolved, find_quoted(content, &task.name)).map_err(error)?;
+            *task = Task(Arc::new(resolved));
+        }
+        for (i, task) in conf.tasks.iter().enumerate() {
+            // instances are written out expanded, expand them again so they follow their template
+            if let Some(name) = &task.template {
+                instances.push((i, find_quoted(content, name)));
+            }
+        }
+        for include in &mut conf.includes {
+            include.span = find_quoted(content, &include.path);
+        }
+    }
+
+    let mut stack: Vec<PathBuf> = path.canonicalize().into_iter().collect();
+    let mut items = Items {
+        tasks: std::mem::take(&mut conf.tasks),
+        templates: std::mem::take(&mut conf.templates),
+        includes: std::mem::take(&mut conf.includes),
+        instances,
+    };
+    resolve_includes(path, content, context, &mut items, &mut stack)?;
+    conf.tasks = items.tasks;
+    conf.templates = items.templates;
+    conf.includes = items.includes;
+    Ok(conf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` to a fresh directory and returns its path
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("confparse-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+        dir
+    }
+
+    const HEADER: &str = "IN:\nOUT:\n\nINIT_CONDITIONS:\n\n";
+
+    fn parse(text: &str) -> Result<Conf, ConfError> {
+        conf_from_str("tasks.conf", text, 1, Duration::DEFAULT_TICK)
+    }
+
+    /// The kind of the syntax error of `text`, with its line and column
+    fn error_at(text: &str) -> (SyntaxErrorKind, usize, usize) {
+        match parse(text) {
+            Err(ConfError::Syntax(diagnostic)) => (diagnostic.kind, diagnostic.line, diagnostic.column),
+            other => panic!("expected a syntax error, found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error_at(&format!("{HEADER}Task (S): 2\n")), (SyntaxErrorKind::MissingTaskName, 6, 6));
+        assert_eq!(error_at("IN: a\nIN: b\n"), (SyntaxErrorKind::DuplicateSection("IN".into()), 2, 1));
+        assert_eq!(
+            error_at(&format!("{HEADER}@often: 2\nTask t(S): 2\n")),
+            (SyntaxErrorKind::UnknownAnnotation("often".into()), 6, 1)
+        );
+        assert_eq!(error_at(&format!("{HEADER}Task \"t(S): 2\n")), (SyntaxErrorKind::UnterminatedQuote, 6, 6));
+        assert_eq!(
+            error_at("IN:\nOUT:\n"),
+            (SyntaxErrorKind::UnexpectedEof { expected: "INIT_CONDITIONS:".to_string() }, 2, 5)
+        );
+    }
+
+    #[test]
+    fn file_errors() {
+        let missing = Path::new("no such dir").join("tasks.conf");
+        assert!(matches!(get_conf(&missing, 1, Duration::DEFAULT_TICK), Err(ConfError::Io { path, .. }) if path == missing));
+        let toml = conf_from_str("tasks.toml", "inports = 1", 1, Duration::DEFAULT_TICK);
+        assert!(matches!(toml, Err(ConfError::Toml { .. })));
+        let json = conf_from_str("tasks.json", "{", 1, Duration::DEFAULT_TICK);
+        assert!(matches!(json, Err(ConfError::Json { .. })));
+    }
+
+    const POLL: &str = "Template poll(S:r): 2\n@satisfies: polled\n";
+    const TASKS: &str = "IN:\nOUT:\n\nINIT_CONDITIONS:\n\nINCLUDE: poll.conf\n\nTask poll_t = poll(TEMP)\n";
+
+    #[test]
+    fn instance_of_included_template() {
+        let dir = write_files("included-template", &[("poll.conf", POLL), ("tasks.conf", TASKS)]);
+        let conf = get_conf(dir.join("tasks.conf"), 1, Duration::DEFAULT_TICK).unwrap();
+        let task = &conf.tasks[0];
+        assert_eq!(&*task.name, "poll_t");
+        assert_eq!(task.cycles, 2);
+        assert_eq!(task.args, [Arg { sensor: "TEMP".into(), access: Access::Read }]);
+        assert_eq!(task.satisfies, [Arc::from("polled")]);
+
+        // the instance is written back as an instance, and read again from TOML
+        assert!(conf.to_string().contains("Task poll_t = poll(TEMP)"));
+        let toml = conf.to_format(Format::Toml);
+        let reread = conf_from_str(dir.join("tasks.toml"), &toml, 1, Duration::DEFAULT_TICK).unwrap();
+        assert_eq!(reread.tasks[0].args, task.args);
+        assert_eq!(reread.tasks[0].satisfies, task.satisfies);
+    }
+
+    #[test]
+    fn instance_of_unknown_template() {
+        let tasks = TASKS.replace("poll(TEMP)", "pol(TEMP)");
+        let dir = write_files("unknown-template", &[("poll.conf", POLL), ("tasks.conf", &tasks)]);
+        let Err(ConfError::Syntax(diagnostic)) = get_conf(dir.join("tasks.conf"), 1, Duration::DEFAULT_TICK) else {
+            panic!("an unknown template is an error");
         };
-        config.tasks.push(task);
+        assert!(diagnostic.to_string().contains("pol"));
     }
-    Ok(config)
 
+    #[test]
+    fn included_tasks_at_the_directive() {
+        let dir = write_files(
+            "includes",
+            &[
+                ("inner.conf", "Task c(S): 1\n"),
+                ("outer.conf", "Task b(S): 1\n\nINCLUDE: inner.conf\n"),
+                ("tasks.conf", &format!("{HEADER}Task a(S): 1\n\nINCLUDE: outer.conf\n\nTask d(S): 1\n")),
+            ],
+        );
+        let conf = get_conf(dir.join("tasks.conf"), 1, Duration::DEFAULT_TICK).unwrap();
+        let names: Vec<&str> = conf.tasks.iter().map(|task| &*task.name).collect();
+        assert_eq!(names, ["a", "b", "c", "d"]);
+        // included tasks stay in their files
+        assert_eq!(conf.to_string(), format!("{HEADER}Task a(S): 1\n\nINCLUDE: outer.conf\n\nTask d(S): 1\n"));
+    }
+
+    #[test]
+    fn include_errors() {
+        let kind = |dir: PathBuf| match get_conf(dir.join("tasks.conf"), 1, Duration::DEFAULT_TICK) {
+            Err(ConfError::Syntax(diagnostic)) => diagnostic.kind,
+            other => panic!("expected a syntax error, found {other:?}"),
+        };
+
+        let dir = write_files(
+            "include-cycle",
+            &[("a.conf", "INCLUDE: b.conf\n"), ("b.conf", "INCLUDE: a.conf\n"), ("tasks.conf", &format!("{HEADER}INCLUDE: a.conf\n"))],
+        );
+        let SyntaxErrorKind::IncludeCycle(chain) = kind(dir) else {
+            panic!("a file including itself is an error");
+        };
+        let files: Vec<_> = chain.split(" -> ").map(|f| Path::new(f).file_name().unwrap().to_owned()).collect();
+        assert_eq!(files, ["a.conf", "b.conf", "a.conf"]);
+
+        let dir = write_files("include-missing", &[("tasks.conf", &format!("{HEADER}INCLUDE: missing.conf\n"))]);
+        assert!(matches!(kind(dir), SyntaxErrorKind::UnreadableInclude { path, .. } if &*path == "missing.conf"));
+    }
+
+    #[test]
+    fn instances_merge_with_their_template() {
+        let text = format!(
+            "{HEADER}@requires: x\n@period: 8\n@priority: 2\nTemplate t(S:r, T): 3\n@satisfies: y\n\n\
+             @requires: z\n@deadline: 4\n@priority: 0\nTask a = t(A, B)\n@satisfies: y, w\n"
+        );
+        let conf = parse(&text).unwrap();
+        let a = &conf.tasks[0];
+        assert_eq!(a.args, [Arg { sensor: "A".into(), access: Access::Read }, Arg { sensor: "B".into(), access: Access::Write }]);
+        assert_eq!(a.requires, [Arc::from("x"), "z".into()]);
+        assert_eq!(a.satisfies, [Arc::from("y"), "w".into()]);
+        assert_eq!((a.cycles, a.period, a.deadline, a.priority), (3, Some(8), Some(4), Some(0)));
+        assert_eq!(a.template.as_deref(), Some("t"));
+    }
+
+    #[test]
+    fn template_errors() {
+        let template = format!("{HEADER}Template t(S, T): 1\n\n");
+        assert_eq!(
+            error_at(&format!("{template}Task a = t(A)\n")),
+            (SyntaxErrorKind::TemplateArity { template: "t".into(), expected: 2, found: 1 }, 8, 10)
+        );
+        assert_eq!(error_at(&format!("{template}Task a = u(A, B)\n")), (SyntaxErrorKind::UnknownTemplate("u".into()), 8, 10));
+        assert_eq!(
+            error_at(&format!("{template}Template t(S): 1\n")),
+            (SyntaxErrorKind::DuplicateTemplate("t".into()), 8, 1)
+        );
+        // templates are declared before their instances
+        assert_eq!(
+            error_at(&format!("{HEADER}Task a = t(A)\n\nTemplate t(S): 1\n")),
+            (SyntaxErrorKind::UnknownTemplate("t".into()), 6, 10)
+        );
+        let tasks = TASKS.replace("INCLUDE: poll.conf\n", "INCLUDE: poll.conf\n\nTask a = t(A)\n\nTemplate t(S): 1\n");
+        let dir = write_files("late-template", &[("poll.conf", POLL), ("tasks.conf", &tasks)]);
+        let Err(ConfError::Syntax(diagnostic)) = get_conf(dir.join("tasks.conf"), 1, Duration::DEFAULT_TICK) else {
+            panic!("a template declared after its instance is an error");
+        };
+        assert_eq!((diagnostic.kind, diagnostic.line), (SyntaxErrorKind::UnknownTemplate("t".into()), 8));
+    }
+
+    #[test]
+    fn lengths_in_ticks() {
+        let tick: Duration = "250us".parse().unwrap();
+        let conf = conf_from_str("tasks.conf", &format!("{HEADER}Task a(S): 1ms\n\nTask b(S): 3\n"), 1, tick).unwrap();
+        assert_eq!((conf.tasks[0].cycles, conf.tasks[0].duration), (4, Some("1ms".parse().unwrap())));
+        assert_eq!((conf.tasks[1].cycles, conf.tasks[1].duration), (3, None));
+
+        let error = |length: &str| match conf_from_str("tasks.conf", &format!("{HEADER}Task a(S): {length}\n"), 1, tick) {
+            Err(ConfError::Syntax(diagnostic)) => (diagnostic.kind, diagnostic.column),
+            other => panic!("expected a syntax error, found {other:?}"),
+        };
+        let duration = |text: &str| text.parse().unwrap();
+        assert_eq!(
+            error("300us"),
+            (SyntaxErrorKind::UnevenDuration { duration: duration("300us"), tick }, 12)
+        );
+        assert_eq!(error("20s"), (SyntaxErrorKind::DurationOverflow { duration: duration("20s"), tick }, 12));
+    }
+
+    #[test]
+    fn best_and_worst_case() {
+        let tick: Duration = "250us".parse().unwrap();
+        let conf = conf_from_str("tasks.conf", &format!("{HEADER}Task a(S): 2..5\n\nTask b(S): 250us..1ms\n"), 1, tick).unwrap();
+        assert_eq!((conf.tasks[0].bcet, conf.tasks[0].cycles), (Some(2), 5));
+        assert_eq!((conf.tasks[1].bcet, conf.tasks[1].cycles), (Some(1), 4));
+        assert_eq!(conf.tasks[1].bcet_duration, Some("250us".parse().unwrap()));
+
+        let inverted = SyntaxErrorKind::InvertedRange { best: "1ms".into(), worst: "500us".into() };
+        let Err(ConfError::Syntax(diagnostic)) = conf_from_str("tasks.conf", &format!("{HEADER}Task a(S): 1ms..500us\n"), 1, tick) else {
+            panic!("a best case longer than the worst case is an error");
+        };
+        assert_eq!(diagnostic.kind, inverted);
+        // the same in TOML, where the range is split in two
+        let toml = "[[tasks]]\nname = \"a\"\nargs = [\"S\"]\ncycles = \"500us\"\nbcet = \"1ms\"\n";
+        let Err(ConfError::Syntax(diagnostic)) = conf_from_str("tasks.toml", toml, 1, "1us".parse().unwrap()) else {
+            panic!("a best case longer than the worst case is an error");
+        };
+        assert_eq!(diagnostic.kind, inverted);
+    }
+
+    #[test]
+    fn tasks_and_ports_of_profiles() {
+        let text = "IN: a\nOUT: b\n@when(lab)\nOUT: diag\n@when(lab, flight)\nIN: telemetry\nOUT: beacon\n\nINIT_CONDITIONS:\n\n\
+                    @when(lab)\nTask probe(S): 1\n\nTask always(S): 2\n\n@when(flight)\nTemplate t(S): 1\n\n\
+                    Task poll = t(T)\n\n@when(lab)\nTask lab_poll = t(U)\n";
+        let conf = parse(text).unwrap();
+        assert_eq!(conf.profiles(), [Arc::from("flight"), "lab".into()]);
+        assert_eq!(conf.guarded_ports.len(), 2);
+
+        let built = |profile| {
+            let conf = conf.for_profile(profile);
+            let names = |names: &[Arc<str>]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>().join(" ");
+            let tasks: Vec<_> = conf.tasks.iter().map(|task| task.name.clone()).collect();
+            assert!(conf.guarded_ports.is_empty() && conf.includes.is_empty());
+            (names(&conf.inports), names(&conf.outports), names(&tasks))
+        };
+        // instances take the profiles of their template unless they have their own
+        assert_eq!(built(Some("lab")), ("a telemetry".into(), "b diag beacon".into(), "probe always lab_poll".into()));
+        assert_eq!(built(Some("flight")), ("a telemetry".into(), "b beacon".into(), "always poll".into()));
+        // without a profile, or with one named nowhere, only what is not guarded is built
+        assert_eq!(built(None), ("a".into(), "b".into(), "always".into()));
+        assert_eq!(built(Some("ground")), built(None));
+        // the whole configuration is kept as it was
+        assert_eq!(conf.tasks.len(), 4);
+        assert_eq!(conf.tasks[2].when, [Arc::from("flight")]);
+
+        assert_eq!(
+            error_at("IN:\nOUT:\n@when(lab)\nINIT_CONDITIONS:\n"),
+            (SyntaxErrorKind::Unexpected { expected: "IN: or OUT: after @when".into(), found: "INIT_CONDITIONS:".into() }, 4, 1)
+        );
+    }
+
+    #[test]
+    fn conditions_of_other_obcs() {
+        assert_eq!(qualified_condition("obc2.ready"), Some((2, "ready")));
+        assert_eq!(qualified_condition("obc12.a.b"), Some((12, "a.b")));
+        for condition in ["ready", "obc.ready", "obcx.ready", "obc2.", "obc+2.ready", "node2.ready"] {
+            assert_eq!(qualified_condition(condition), None, "{condition}");
+        }
+    }
 }
-pub fn get_conf(path: &str, obc_id: u32) -> Result<Conf, String> {
-    let content = read_to_string(path).map_err(|e| e.to_string())?;
-    let tokens = parse::parse(&content).map_err(|e| e.to_string())?;
-    coder(tokens, obc_id)
-}
\ No newline at end of file
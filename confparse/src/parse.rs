@@ -1,113 +1,469 @@
 use std::sync::Arc;
 
+use crate::error::{Span, SyntaxError, SyntaxErrorKind};
+use crate::lexer::{Lexeme, Lexer};
+use crate::{Access, Duration};
+
 #[derive(Debug, Clone)]
 pub enum Token {
     Keyword(Keyword),
     Symbol(Symbol),
     Literal(Arc<str>),
+    /// Access mode after a task argument, `:r` or `:w`
+    Access(Access),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Symbol {
     StartArray,
     EndArray,
+    /// `=` between the name of a task and the template it instantiates
+    Equals,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Keyword {
-    IN,
-    OUT,
-    #[allow(non_camel_case_types)]
-    INIT_CONDITIONS,
-    TASK,
-    REQUIRES,
-    SATISFIES,
+    In,
+    Out,
+    InitConditions,
+    Task,
+    Template,
+    Requires,
+    Satisfies,
+    OnPort,
+    Uses,
+    Period,
+    Deadline,
+    Priority,
+    When,
+    Include,
+}
+
+impl std::fmt::Display for Keyword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // spelled the way it is written in tasks.conf
+        let keyword = match self {
+            Keyword::In => "IN:",
+            Keyword::Out => "OUT:",
+            Keyword::InitConditions => "INIT_CONDITIONS:",
+            Keyword::Task => "Task",
+            Keyword::Template => "Template",
+            Keyword::Requires => "@requires:",
+            Keyword::Satisfies => "@satisfies:",
+            Keyword::OnPort => "@on_port:",
+            Keyword::Uses => "@uses:",
+            Keyword::Period => "@period:",
+            Keyword::Deadline => "@deadline:",
+            Keyword::Priority => "@priority:",
+            Keyword::When => "@when",
+            Keyword::Include => "INCLUDE:",
+        };
+        write!(f, "{keyword}")
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Keyword(keyword) => write!(f, "{keyword}"),
+            Token::Symbol(Symbol::StartArray) => write!(f, "start of list"),
+            Token::Symbol(Symbol::EndArray) => write!(f, "end of list"),
+            Token::Symbol(Symbol::Equals) => write!(f, "'='"),
+            Token::Literal(name) => write!(f, "'{name}'"),
+            Token::Access(Access::Read) => write!(f, "':r'"),
+            Token::Access(Access::Write) => write!(f, "':w'"),
+        }
+    }
+}
+
+type Lexemes = std::iter::Peekable<std::vec::IntoIter<(Lexeme, Span)>>;
+
+fn describe(lexeme: &Lexeme) -> String {
+    match lexeme {
+        Lexeme::Word(w) => format!("'{w}'"),
+        Lexeme::Quoted(w) => format!("\"{w}\""),
+        Lexeme::Colon => "':'".to_string(),
+        Lexeme::Comma => "','".to_string(),
+        Lexeme::LParen => "'('".to_string(),
+        Lexeme::RParen => "')'".to_string(),
+        Lexeme::At => "'@'".to_string(),
+        Lexeme::Equals => "'='".to_string(),
+        Lexeme::Comment(_) => "comment".to_string(),
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    matches!(word, "IN" | "OUT" | "INIT_CONDITIONS" | "INCLUDE" | "Task" | "Template")
 }
 
-pub fn parse(input: &str) -> Result<Vec<Token>, String> {
+fn expect(
+    lexemes: &mut Lexemes,
+    eof: Span,
+    expected: &str,
+    matches: impl Fn(&Lexeme) -> bool,
+) -> Result<(Lexeme, Span), SyntaxError> {
+    match lexemes.next() {
+        Some((lexeme, span)) if matches(&lexeme) => Ok((lexeme, span)),
+        Some((lexeme, span)) => Err(SyntaxError::new(
+            SyntaxErrorKind::Unexpected {
+                expected: expected.to_string(),
+                found: describe(&lexeme),
+            },
+            span,
+        )),
+        None => Err(SyntaxError::new(
+            SyntaxErrorKind::UnexpectedEof {
+                expected: expected.to_string(),
+            },
+            eof,
+        )),
+    }
+}
+
+fn name(lexemes: &mut Lexemes) -> Option<(Arc<str>, Span)> {
+    match lexemes.peek()? {
+        (Lexeme::Word(w), _) if is_keyword(w) => None,
+        (Lexeme::Word(w) | Lexeme::Quoted(w), span) => {
+            let item = (w.clone(), *span);
+            lexemes.next();
+            Some(item)
+        }
+        _ => None,
+    }
+}
+
+fn push_list(tokens: &mut Vec<(Token, Span)>, lexemes: &mut Lexemes, colon: Span) {
+    // comma separated names, the list ends at the first name not followed by a comma
+    // so it may continue over several lines after a trailing ','
+    tokens.push((Token::Symbol(Symbol::StartArray), colon));
+    let mut end = colon;
+    while let Some((item, span)) = name(lexemes) {
+        tokens.push((Token::Literal(item), span));
+        end = span;
+        if lexemes.next_if(|(l, _)| *l == Lexeme::Comma).is_none() {
+            break;
+        }
+    }
+    tokens.push((Token::Symbol(Symbol::EndArray), Span::new(end.end, end.end)));
+}
+
+fn push_args(
+    tokens: &mut Vec<(Token, Span)>,
+    lexemes: &mut Lexemes,
+    eof: Span,
+) -> Result<(), SyntaxError> {
+    let (_, open) = expect(lexemes, eof, "'(' after task name", |l| *l == Lexeme::LParen)?;
+    tokens.push((Token::Symbol(Symbol::StartArray), open));
+    loop {
+        if let Some((_, close)) = lexemes.next_if(|(l, _)| *l == Lexeme::RParen) {
+            tokens.push((Token::Symbol(Symbol::EndArray), close));
+            return Ok(());
+        }
+        let (item, span) = expect(lexemes, eof, "sensor name or ')'", |l| {
+            matches!(l, Lexeme::Word(_) | Lexeme::Quoted(_))
+        })?;
+        let (Lexeme::Word(item) | Lexeme::Quoted(item)) = item else {
+            unreachable!()
+        };
+        tokens.push((Token::Literal(item), span));
+        if lexemes.next_if(|(l, _)| *l == Lexeme::Colon).is_some() {
+            let (mode, mode_span) = expect(lexemes, eof, "access mode 'r' or 'w'", |l| {
+                matches!(l, Lexeme::Word(_))
+            })?;
+            let access = match mode {
+                Lexeme::Word(w) if &*w == "r" => Access::Read,
+                Lexeme::Word(w) if &*w == "w" => Access::Write,
+                Lexeme::Word(w) => Err(SyntaxError::new(SyntaxErrorKind::InvalidAccess(w), mode_span))?,
+                _ => unreachable!(),
+            };
+            tokens.push((Token::Access(access), mode_span));
+        }
+        if lexemes.next_if(|(l, _)| *l == Lexeme::Comma).is_none() {
+            let (_, close) = expect(lexemes, eof, "',' or ')' after task args", |l| {
+                *l == Lexeme::RParen
+            })?;
+            tokens.push((Token::Symbol(Symbol::EndArray), close));
+            return Ok(());
+        }
+    }
+}
+
+fn push_profiles(
+    tokens: &mut Vec<(Token, Span)>,
+    lexemes: &mut Lexemes,
+    eof: Span,
+) -> Result<(), SyntaxError> {
+    let (_, open) = expect(lexemes, eof, "'(' after @when", |l| *l == Lexeme::LParen)?;
+    tokens.push((Token::Symbol(Symbol::StartArray), open));
+    loop {
+        let (item, span) = expect(lexemes, eof, "profile name", |l| {
+            matches!(l, Lexeme::Word(_) | Lexeme::Quoted(_))
+        })?;
+        let (Lexeme::Word(item) | Lexeme::Quoted(item)) = item else {
+            unreachable!()
+        };
+        tokens.push((Token::Literal(item), span));
+        if lexemes.next_if(|(l, _)| *l == Lexeme::Comma).is_none() {
+            let (_, close) = expect(lexemes, eof, "',' or ')' after profile name", |l| {
+                *l == Lexeme::RParen
+            })?;
+            tokens.push((Token::Symbol(Symbol::EndArray), close));
+            return Ok(());
+        }
+    }
+}
+
+/// The tokens of a file and its comments, each with its position in the input
+pub type Parsed = (Vec<(Token, Span)>, Vec<(Arc<str>, Span)>);
+
+/// Comments are returned apart from the tokens, with their position in the input.
+pub fn parse(input: &str) -> Result<Parsed, SyntaxError> {
     let mut tokens = Vec::new();
-    let lines = input.lines();
+    let mut comments = Vec::new();
+    let mut lexemes: Lexemes = Lexer::new(input)
+        .filter(|lexeme| match lexeme {
+            Ok((Lexeme::Comment(comment), span)) => {
+                comments.push((comment.clone(), *span));
+                false
+            }
+            _ => true,
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .peekable();
+    let eof = Span::new(input.trim_end().len(), input.trim_end().len());
 
     let mut found_in = false;
     let mut found_out = false;
     let mut found_init = false;
+    // IN: and OUT: lists right after a @when(...) may repeat those without one
+    let mut guarded = false;
 
-    for line in lines.filter(|f| !f.is_empty()).map(|f| f.trim()) {
-        if line.starts_with("IN:") {
-            if found_in {
-                Err("Another IN found")?;
+    while let Some((lexeme, span)) = lexemes.next() {
+        let after_when = std::mem::take(&mut guarded);
+        match lexeme {
+            Lexeme::Word(w) if &*w == "IN" || &*w == "OUT" || &*w == "INIT_CONDITIONS" => {
+                let (keyword, found) = match &*w {
+                    "IN" => (Keyword::In, &mut found_in),
+                    "OUT" => (Keyword::Out, &mut found_out),
+                    _ => (Keyword::InitConditions, &mut found_init),
+                };
+                if after_when && keyword != Keyword::InitConditions {
+                    guarded = true;
+                } else if *found {
+                    Err(SyntaxError::new(SyntaxErrorKind::DuplicateSection(w.clone()), span))?;
+                } else {
+                    *found = true;
+                }
+                let (_, colon) = expect(&mut lexemes, eof, &format!("':' after {w}"), |l| {
+                    *l == Lexeme::Colon
+                })?;
+                tokens.push((Token::Keyword(keyword), span));
+                push_list(&mut tokens, &mut lexemes, colon);
             }
-            tokens.push(Token::Keyword(Keyword::IN));
-            tokens.push(Token::Symbol(Symbol::StartArray));
-            tokens.extend(
-                line[3..]
-                    .split(',')
-                    .map(|f| Token::Literal(f.trim().into())),
-            );
-            tokens.push(Token::Symbol(Symbol::EndArray));
-            found_in = true;
-        } else if line.starts_with("OUT:") {
-            if found_out {
-                Err("Another OUT found")?;
+            Lexeme::At => {
+                let (keyword, keyword_span) =
+                    expect(&mut lexemes, eof, "annotation name after @", |l| {
+                        matches!(l, Lexeme::Word(_))
+                    })?;
+                let keyword_span = Span::new(span.start, keyword_span.end);
+                let keyword = match keyword {
+                    Lexeme::Word(w) if &*w == "requires" => Keyword::Requires,
+                    Lexeme::Word(w) if &*w == "satisfies" => Keyword::Satisfies,
+                    Lexeme::Word(w) if &*w == "on_port" => Keyword::OnPort,
+                    Lexeme::Word(w) if &*w == "uses" => Keyword::Uses,
+                    Lexeme::Word(w) if &*w == "period" => Keyword::Period,
+                    Lexeme::Word(w) if &*w == "deadline" => Keyword::Deadline,
+                    Lexeme::Word(w) if &*w == "priority" => Keyword::Priority,
+                    Lexeme::Word(w) if &*w == "when" => Keyword::When,
+                    Lexeme::Word(w) => Err(SyntaxError::new(
+                        SyntaxErrorKind::UnknownAnnotation(w),
+                        keyword_span,
+                    ))?,
+                    _ => unreachable!(),
+                };
+                if keyword == Keyword::When {
+                    // @when(profile, ...)
+                    tokens.push((Token::Keyword(keyword), keyword_span));
+                    push_profiles(&mut tokens, &mut lexemes, eof)?;
+                    guarded = true;
+                    continue;
+                }
+                let (_, colon) = expect(&mut lexemes, eof, "':' after @", |l| {
+                    *l == Lexeme::Colon
+                })?;
+                tokens.push((Token::Keyword(keyword), keyword_span));
+                if matches!(keyword, Keyword::Period | Keyword::Deadline | Keyword::Priority) {
+                    let (value, value_span) = expect(&mut lexemes, eof, "number of cycles", |l| {
+                        matches!(l, Lexeme::Word(_))
+                    })?;
+                    let Lexeme::Word(value) = value else {
+                        unreachable!()
+                    };
+                    // a priority may be 0, periods and deadlines may not
+                    if !value
+                        .parse::<u16>()
+                        .is_ok_and(|v| v > 0 || keyword == Keyword::Priority)
+                    {
+                        return Err(SyntaxError::new(
+                            SyntaxErrorKind::InvalidNumber {
+                                annotation: keyword.to_string().into(),
+                                found: value,
+                            },
+                            value_span,
+                        ));
+                    }
+                    tokens.push((Token::Literal(value), value_span));
+                } else {
+                    push_list(&mut tokens, &mut lexemes, colon);
+                }
             }
-            tokens.push(Token::Keyword(Keyword::OUT));
-            tokens.push(Token::Symbol(Symbol::StartArray));
-            tokens.extend(
-                line[4..]
-                    .split(',')
-                    .map(|f| Token::Literal(f.trim().into())),
-            );
-            tokens.push(Token::Symbol(Symbol::EndArray));
-            found_out = true;
-        } else if line.starts_with("INIT_CONDITIONS:") {
-            if found_init {
-                Err("Another INIT_CONDITIONS found")?;
+            Lexeme::Word(w) if &*w == "INCLUDE" => {
+                expect(&mut lexemes, eof, "':' after INCLUDE", |l| *l == Lexeme::Colon)?;
+                tokens.push((Token::Keyword(Keyword::Include), span));
+                let Some((path, path_span)) = name(&mut lexemes) else {
+                    let found = lexemes.peek().map_or("end of file".to_string(), |(l, _)| describe(l));
+                    let span = lexemes.peek().map_or(eof, |(_, s)| *s);
+                    Err(SyntaxError::new(
+                        SyntaxErrorKind::Unexpected {
+                            expected: "path of the included file".to_string(),
+                            found,
+                        },
+                        span,
+                    ))?
+                };
+                tokens.push((Token::Literal(path), path_span));
             }
-            tokens.push(Token::Keyword(Keyword::INIT_CONDITIONS));
-            tokens.push(Token::Symbol(Symbol::StartArray));
-            tokens.extend(
-                line[16..]
-                    .split(',')
-                    .map(|f| Token::Literal(f.trim().into())),
-            );
-            tokens.push(Token::Symbol(Symbol::EndArray));
-            found_init = true;
-        } else if line.starts_with("@") {
-            let (keyword, args) = line.split_once(':').ok_or("Missing ':' after @")?;
-            match &keyword[1..] {
-                "requires" => {
-                    tokens.push(Token::Keyword(Keyword::REQUIRES));
-                    tokens.push(Token::Symbol(Symbol::StartArray));
-                    tokens.extend(args.split(',').map(|f| Token::Literal(f.trim().into())));
-                    tokens.push(Token::Symbol(Symbol::EndArray));
+            Lexeme::Word(w) if &*w == "Task" || &*w == "Template" => {
+                let keyword = if &*w == "Task" { Keyword::Task } else { Keyword::Template };
+                tokens.push((Token::Keyword(keyword), span));
+                let Some((task_name, name_span)) = name(&mut lexemes) else {
+                    let span = lexemes.peek().map_or(eof, |(_, s)| *s);
+                    Err(SyntaxError::new(SyntaxErrorKind::MissingTaskName, span))?
+                };
+                tokens.push((Token::Literal(task_name), name_span));
+
+                // `Task name = template(args)` takes its cycles from the template
+                if keyword == Keyword::Task {
+                    if let Some((_, equals)) = lexemes.next_if(|(l, _)| *l == Lexeme::Equals) {
+                        tokens.push((Token::Symbol(Symbol::Equals), equals));
+                        let Some((template, template_span)) = name(&mut lexemes) else {
+                            let found = lexemes.peek().map_or("end of file".to_string(), |(l, _)| describe(l));
+                            let span = lexemes.peek().map_or(eof, |(_, s)| *s);
+                            Err(SyntaxError::new(
+                                SyntaxErrorKind::Unexpected {
+                                    expected: "template name".to_string(),
+                                    found,
+                                },
+                                span,
+                            ))?
+                        };
+                        tokens.push((Token::Literal(template), template_span));
+                        push_args(&mut tokens, &mut lexemes, eof)?;
+                        continue;
+                    }
                 }
-                "satisfies" => {
-                    tokens.push(Token::Keyword(Keyword::SATISFIES));
-                    tokens.push(Token::Symbol(Symbol::StartArray));
-                    tokens.extend(args.split(',').map(|f| Token::Literal(f.trim().into())));
-                    tokens.push(Token::Symbol(Symbol::EndArray));
+                push_args(&mut tokens, &mut lexemes, eof)?;
+                expect(&mut lexemes, eof, "':' after task args", |l| *l == Lexeme::Colon)?;
+                let (cycles, cycle_span) = expect(&mut lexemes, eof, "cycles", |l| {
+                    matches!(l, Lexeme::Word(_))
+                })?;
+                let Lexeme::Word(cycles) = cycles else {
+                    unreachable!()
+                };
+                // ticks, or a time converted to ticks by the parser, or a `best..worst` range of them
+                let is_length = |text: &str| text.parse::<u16>().is_ok() || text.parse::<Duration>().is_ok();
+                let valid = match cycles.split_once("..") {
+                    Some((best, worst)) => is_length(best) && is_length(worst),
+                    None => is_length(&cycles),
+                };
+                if !valid {
+                    Err(SyntaxError::new(SyntaxErrorKind::InvalidCycles(cycles.clone()), cycle_span))?
                 }
-                _ => Err("Unknown keyword after @")?,
+                tokens.push((Token::Literal(cycles), cycle_span));
             }
-        } else if line.starts_with("Task") {
-            tokens.push(Token::Keyword(Keyword::TASK));
-            let (task_name, args_and_cycle) = line[5..]
-                .split_once("(")
-                .ok_or("Missing '(' after task name")?;
-            let (args, cycle_str) = args_and_cycle
-                .split_once(")")
-                .ok_or("Missing ')' after task args")?;
-            let cycle = cycle_str[1..].trim()
-                .parse::<u16>()
-                .map_err(|_| "Cycle must be a number")?;
-            tokens.push(Token::Literal(task_name.trim().into()));
-            tokens.push(Token::Symbol(Symbol::StartArray));
-            tokens.extend(args.split(',').map(|f| Token::Literal(f.trim().into())));
-            tokens.push(Token::Symbol(Symbol::EndArray));
-            tokens.push(Token::Literal(cycle.to_string().into()));
+            lexeme => Err(SyntaxError::new(
+                SyntaxErrorKind::Unexpected {
+                    expected: "IN:, OUT:, INIT_CONDITIONS:, INCLUDE:, Task, Template or an @ annotation".to_string(),
+                    found: describe(&lexeme),
+                },
+                span,
+            ))?,
         }
     }
-    let nonempty = tokens.into_iter().filter(|f| !matches!(f, Token::Literal(x) if x.is_empty())).collect();
-    // println!("{:?}", nonempty);
-    Ok(nonempty)
+    Ok((tokens, comments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tokens of `input` as they are displayed
+    fn tokens(input: &str) -> Vec<String> {
+        parse(input).unwrap().0.iter().map(|(token, _)| token.to_string()).collect()
+    }
+
+    #[test]
+    fn lists_over_several_lines() {
+        assert_eq!(
+            tokens("IN: a,\n  b,\n\n  c\nOUT:\nINIT_CONDITIONS: x"),
+            [
+                "IN:", "start of list", "'a'", "'b'", "'c'", "end of list",
+                "OUT:", "start of list", "end of list",
+                "INIT_CONDITIONS:", "start of list", "'x'", "end of list",
+            ]
+        );
+    }
+
+    #[test]
+    fn tasks_and_annotations() {
+        assert_eq!(
+            tokens("@requires: a, b\n@period: 8\nTask t(S:r, T:w,\n  U): 250us..1ms\nTask i = tpl(S)"),
+            [
+                "@requires:", "start of list", "'a'", "'b'", "end of list",
+                "@period:", "'8'",
+                "Task", "'t'", "start of list", "'S'", "':r'", "'T'", "':w'", "'U'", "end of list", "'250us..1ms'",
+                "Task", "'i'", "'='", "'tpl'", "start of list", "'S'", "end of list",
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_apart_from_tokens() {
+        let input = "# first\nIN: a # after a\n// last";
+        let (tokens, comments) = parse(input).unwrap();
+        assert_eq!(tokens.len(), 4);
+        let comments: Vec<(&str, &str)> = comments.iter().map(|(c, span)| (&**c, &input[span.start..span.end])).collect();
+        assert_eq!(comments, [("# first", "# first"), ("# after a", "# after a"), ("// last", "// last")]);
+    }
+
+    #[test]
+    fn quoted_keywords_are_names() {
+        assert_eq!(tokens("Task \"IN\"(\"OUT\"): 1"), ["Task", "'IN'", "start of list", "'OUT'", "end of list", "'1'"]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |input: &str| {
+            let error = parse(input).unwrap_err();
+            (error.kind, error.span)
+        };
+        assert_eq!(
+            error("IN a"),
+            (SyntaxErrorKind::Unexpected { expected: "':' after IN".to_string(), found: "'a'".to_string() }, Span::new(3, 4))
+        );
+        assert_eq!(error("IN:\nIN:"), (SyntaxErrorKind::DuplicateSection("IN".into()), Span::new(4, 6)));
+        assert_eq!(error("Task t(S): often"), (SyntaxErrorKind::InvalidCycles("often".into()), Span::new(11, 16)));
+        assert_eq!(error("@often: 1"), (SyntaxErrorKind::UnknownAnnotation("often".into()), Span::new(0, 6)));
+        assert_eq!(
+            error("@period: 0"),
+            (SyntaxErrorKind::InvalidNumber { annotation: "@period:".into(), found: "0".into() }, Span::new(9, 10))
+        );
+        assert_eq!(error("Task (S): 1"), (SyntaxErrorKind::MissingTaskName, Span::new(5, 6)));
+        assert_eq!(error("Task t(S:x): 1"), (SyntaxErrorKind::InvalidAccess("x".into()), Span::new(9, 10)));
+        assert_eq!(
+            error("Task t(S"),
+            (SyntaxErrorKind::UnexpectedEof { expected: "',' or ')' after task args".to_string() }, Span::new(8, 8))
+        );
+    }
 }
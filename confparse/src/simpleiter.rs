@@ -0,0 +1,92 @@
+use std::{iter::Peekable, sync::Arc, vec::IntoIter};
+
+use crate::error::{Span, SyntaxError, SyntaxErrorKind};
+use crate::parse::Token;
+
+/// Cursor over the parsed tokens which knows where the input ends,
+/// so running out of tokens can still be reported at a position.
+pub struct SimpleIter<'a> {
+    tokens: std::slice::Iter<'a, (Token, Span)>,
+    eof: Span,
+    position: usize,
+}
+
+impl<'a> SimpleIter<'a> {
+    pub fn new(tokens: &'a [(Token, Span)], input_len: usize) -> Self {
+        SimpleIter {
+            tokens: tokens.iter(),
+            eof: Span::new(input_len, input_len),
+            position: 0,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&'a (Token, Span)> {
+        self.tokens.clone().next()
+    }
+
+    /// End of the last token taken
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn next_token(&mut self, expected: &str) -> Result<(Token, Span), SyntaxError> {
+        let token = self.tokens.next().cloned();
+        if let Some((_, span)) = &token {
+            self.position = span.end;
+        }
+        token.ok_or(SyntaxError::new(
+            SyntaxErrorKind::UnexpectedEof {
+                expected: expected.to_string(),
+            },
+            self.eof,
+        ))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.len() == 0
+    }
+}
+
+/// Hands each comment to the item it belongs to: the first item ending after it,
+/// or the item on whose last line it trails.
+pub struct CommentIter<'a> {
+    input: &'a str,
+    comments: Peekable<IntoIter<(Arc<str>, Span)>>,
+}
+
+impl<'a> CommentIter<'a> {
+    pub fn new(input: &'a str, comments: Vec<(Arc<str>, Span)>) -> Self {
+        CommentIter {
+            input,
+            comments: comments.into_iter().peekable(),
+        }
+    }
+
+    pub fn take_until(&mut self, item_end: usize) -> Vec<Arc<str>> {
+        let line_end = self.input[item_end..]
+            .find('\n')
+            .map_or(self.input.len(), |i| item_end + i);
+        let mut taken = vec![];
+        while let Some((comment, _)) = self.comments.next_if(|(_, span)| span.start < line_end) {
+            taken.push(comment);
+        }
+        taken
+    }
+
+    /// The comments before `at`, and the comment at the end of the line of `at` apart
+    pub fn take_line(&mut self, at: usize) -> (Vec<Arc<str>>, Option<Arc<str>>) {
+        let mut before = vec![];
+        while let Some((comment, _)) = self.comments.next_if(|(_, span)| span.start < at) {
+            before.push(comment);
+        }
+        let trailing = self
+            .comments
+            .next_if(|(_, span)| !self.input[at..span.start].contains('\n'))
+            .map(|(comment, _)| comment);
+        (before, trailing)
+    }
+
+    pub fn rest(self) -> Vec<Arc<str>> {
+        self.comments.map(|(comment, _)| comment).collect()
+    }
+}
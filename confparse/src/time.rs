@@ -0,0 +1,143 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Unit of a [`Duration`], written after the number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum TimeUnit {
+    Ns,
+    Us,
+    Ms,
+    S,
+}
+
+impl TimeUnit {
+    fn nanos(self) -> u64 {
+        match self {
+            TimeUnit::Ns => 1,
+            TimeUnit::Us => 1_000,
+            TimeUnit::Ms => 1_000_000,
+            TimeUnit::S => 1_000_000_000,
+        }
+    }
+}
+
+/// A length of time as written in a configuration, such as `250us` or `2ms`.
+///
+/// Task durations with a unit are converted to ticks of the project's tick duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Duration {
+    pub value: u64,
+    pub unit: TimeUnit,
+}
+
+impl Duration {
+    /// One second, the tick of projects which do not choose one, as long as a cycle
+    /// was before ticks could be chosen
+    pub const DEFAULT_TICK: Duration = Duration {
+        value: 1,
+        unit: TimeUnit::S,
+    };
+
+    pub fn as_nanos(&self) -> u128 {
+        self.value as u128 * self.unit.nanos() as u128
+    }
+
+    /// Whether this duration is a whole number of `tick`s
+    pub fn is_multiple_of(&self, tick: Duration) -> bool {
+        tick.as_nanos() != 0 && self.as_nanos().is_multiple_of(tick.as_nanos())
+    }
+
+    /// Number of whole `tick`s in this duration, `None` if it is not a multiple of
+    /// `tick` or does not fit in a `u16`
+    pub fn ticks(&self, tick: Duration) -> Option<u16> {
+        if !self.is_multiple_of(tick) {
+            return None;
+        }
+        (self.as_nanos() / tick.as_nanos()).try_into().ok()
+    }
+}
+
+impl Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.unit {
+            TimeUnit::Ns => "ns",
+            TimeUnit::Us => "us",
+            TimeUnit::Ms => "ms",
+            TimeUnit::S => "s",
+        };
+        write!(f, "{}{unit}", self.value)
+    }
+}
+
+impl FromStr for Duration {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+        let (value, unit) = text.split_at(split);
+        let unit = match unit {
+            "ns" => TimeUnit::Ns,
+            "us" => TimeUnit::Us,
+            "ms" => TimeUnit::Ms,
+            "s" => TimeUnit::S,
+            _ => return Err(format!("'{text}' is not a time such as 250us, units are ns, us, ms and s")),
+        };
+        let value = value
+            .parse()
+            .map_err(|_| format!("'{text}' is not a time such as 250us"))?;
+        Ok(Duration { value, unit })
+    }
+}
+
+impl From<Duration> for String {
+    fn from(duration: Duration) -> Self {
+        duration.to_string()
+    }
+}
+
+impl TryFrom<String> for Duration {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(text: &str) -> Duration {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn units() {
+        for (text, nanos) in [("3ns", 3), ("250us", 250_000), ("2ms", 2_000_000), ("1s", 1_000_000_000)] {
+            assert_eq!(duration(text).as_nanos(), nanos);
+            assert_eq!(duration(text).to_string(), text);
+        }
+        assert_eq!(duration("1s"), Duration::DEFAULT_TICK);
+        for text in ["250", "250 us", "2min", "us", "-1ms", "1.5ms"] {
+            assert!(text.parse::<Duration>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn ticks() {
+        assert_eq!(duration("1ms").ticks(duration("250us")), Some(4));
+        assert_eq!(duration("0ms").ticks(duration("1ms")), Some(0));
+        // not a whole number of ticks
+        assert_eq!(duration("300us").ticks(duration("250us")), None);
+        assert!(!duration("1ms").is_multiple_of(duration("0ns")));
+        // more ticks than a u16 holds
+        assert_eq!(duration("65535us").ticks(duration("1us")), Some(u16::MAX));
+        assert_eq!(duration("65536us").ticks(duration("1us")), None);
+        assert!(duration("65536us").is_multiple_of(duration("1us")));
+    }
+}
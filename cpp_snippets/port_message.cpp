@@ -0,0 +1,6 @@
+#ifndef NAME_msg_
+#define NAME_msg_
+struct NAME_msg {
+FIELDS} __attribute__((packed));
+static_assert(sizeof(NAME_msg) == SIZE, "NAME_msg does not match the message of NAME in sensors.json");
+#endif
\ No newline at end of file
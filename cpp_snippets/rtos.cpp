@@ -1,5 +1,5 @@
 #include "stdio.h"
-#include <unistd.h>
+#include <time.h>
 #include <stdarg.h>
 #include <string>
 
@@ -19,15 +19,24 @@ void log(const char* buf, va_list va_args) {
     // cout << buf;
 }
 
-void runTask(void (*f)(void**), void* args[], int cycles) {
-    printf( "Running for : %d cycles.\n", cycles);
+// length of one tick in nanoseconds
+const long long tick_ns = {TICK_NS};
+
+void sleep_ticks(int ticks) {
+    long long ns = ticks * tick_ns;
+    struct timespec duration = { (time_t) (ns / 1000000000), (long) (ns % 1000000000) };
+    nanosleep(&duration, NULL);
+}
+
+void runTask(void (*f)(void**), void* args[], int ticks) {
+    printf( "Running for : %d ticks.\n", ticks);
     f(args);
-    sleep(cycles);
+    sleep_ticks(ticks);
 }
 
-void delay(int cycles) {
-    printf( "Delaying for : %d cycles.\n", cycles);
-    sleep(cycles);
+void delay(int ticks) {
+    printf( "Delaying for : %d ticks.\n", ticks);
+    sleep_ticks(ticks);
 }
 
 
@@ -2,7 +2,7 @@
 #define NAME_
 class NAME {
 public:
-    void read(char *buffer, int size);
+    void read(char *buffer, int size) const;
     void write(char *buffer, int size);
 };
 #endif
\ No newline at end of file
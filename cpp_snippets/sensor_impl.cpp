@@ -16,7 +16,7 @@ public:
         }
         return instance;
     } 
-    void read(char *buffer, int size){
+    void read(char *buffer, int size) const {
         sensor_read(id, buffer, size);
     }
     void write(char *buffer, int size){
@@ -0,0 +1,5 @@
+#pragma once
+#include "../ports.hpp"
+
+TEMPLATE
+void TEMPLATENAME(ARGS) {}
@@ -0,0 +1,7 @@
+#ifndef NAME_
+#define NAME_
+class NAME {
+public:
+    static void read(NAME_msg &msg);
+};
+#endif
\ No newline at end of file
@@ -0,0 +1,13 @@
+
+{MESSAGE}
+class {NAME} {
+private:
+    {NAME}(){}
+public:
+    static void read({NAME}_msg &msg){
+        port_read({ID}, (char*) &msg, sizeof({NAME}_msg));
+    }
+    static void write(const {NAME}_msg &msg){
+        port_write({ID}, (char*) &msg, sizeof({NAME}_msg));
+    }
+};
\ No newline at end of file
@@ -0,0 +1,216 @@
+use std::hash::{Hash, Hasher};
+
+/// Set of small integers, one bit each, growing to fit the largest.
+/// Bits past the end are unset, so sets of different widths compare by their content.
+#[derive(Clone, Debug, Default)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+const BITS: usize = u64::BITS as usize;
+
+impl BitSet {
+    pub fn new() -> Self {
+        BitSet { words: vec![] }
+    }
+
+    /// The set of `0..len`
+    pub fn full(len: usize) -> Self {
+        let mut words = vec![u64::MAX; len / BITS];
+        if !len.is_multiple_of(BITS) {
+            words.push((1 << (len % BITS)) - 1);
+        }
+        BitSet { words }
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.words
+            .get(index / BITS)
+            .is_some_and(|word| word & (1 << (index % BITS)) != 0)
+    }
+
+    pub fn insert(&mut self, index: usize) {
+        if self.words.len() <= index / BITS {
+            self.words.resize(index / BITS + 1, 0);
+        }
+        self.words[index / BITS] |= 1 << (index % BITS);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if let Some(word) = self.words.get_mut(index / BITS) {
+            *word &= !(1 << (index % BITS));
+        }
+    }
+
+    /// Number of integers in the set
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Smallest integer of the set
+    pub fn first(&self) -> Option<usize> {
+        self.words
+            .iter()
+            .enumerate()
+            .find(|(_, word)| **word != 0)
+            .map(|(i, word)| i * BITS + word.trailing_zeros() as usize)
+    }
+
+    /// Integers of the set, in increasing order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                let bit = word.trailing_zeros() as usize;
+                word &= word.wrapping_sub(1);
+                (bit < BITS).then_some(i * BITS + bit)
+            })
+        })
+    }
+
+    /// Whether the sets have an integer in common
+    pub fn intersects(&self, other: &BitSet) -> bool {
+        self.words.iter().zip(&other.words).any(|(a, b)| a & b != 0)
+    }
+
+    /// Number of integers in both sets
+    pub fn intersection_len(&self, other: &BitSet) -> usize {
+        self.words.iter().zip(&other.words).map(|(a, b)| (a & b).count_ones() as usize).sum()
+    }
+
+    pub fn intersection(&self, other: &BitSet) -> BitSet {
+        let words = self.words.iter().zip(&other.words).map(|(a, b)| a & b).collect();
+        BitSet { words }
+    }
+
+    pub fn union(&self, other: &BitSet) -> BitSet {
+        BitSet { words: self.zip_longest(other, |a, b| a | b) }
+    }
+
+    pub fn difference(&self, other: &BitSet) -> BitSet {
+        let words = self
+            .words
+            .iter()
+            .enumerate()
+            .map(|(i, a)| a & !other.words.get(i).unwrap_or(&0))
+            .collect();
+        BitSet { words }
+    }
+
+    /// Words of both sets combined by `op`, as long as the longest
+    fn zip_longest(&self, other: &BitSet, op: impl Fn(u64, u64) -> u64) -> Vec<u64> {
+        (0..self.words.len().max(other.words.len()))
+            .map(|i| op(*self.words.get(i).unwrap_or(&0), *other.words.get(i).unwrap_or(&0)))
+            .collect()
+    }
+
+    /// Words up to the last one with a bit set
+    fn trimmed(&self) -> &[u64] {
+        let len = self.words.iter().rposition(|word| *word != 0).map_or(0, |i| i + 1);
+        &self.words[..len]
+    }
+}
+
+impl PartialEq for BitSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.trimmed() == other.trimmed()
+    }
+}
+
+impl Eq for BitSet {}
+
+impl Hash for BitSet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.trimmed().hash(state);
+    }
+}
+
+impl FromIterator<usize> for BitSet {
+    fn from_iter<T: IntoIterator<Item = usize>>(iter: T) -> Self {
+        let mut set = BitSet::new();
+        iter.into_iter().for_each(|index| set.insert(index));
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const WIDTHS: [usize; 11] = [0, 1, 63, 64, 65, 127, 128, 129, 255, 256, 1000];
+
+    #[test]
+    fn full_has_exactly_its_width() {
+        for width in WIDTHS {
+            let set = BitSet::full(width);
+            assert_eq!(set.iter().collect::<Vec<_>>(), (0..width).collect::<Vec<_>>());
+            assert!(!set.contains(width), "width {width}");
+            assert_eq!(set.first(), (width > 0).then_some(0));
+            if width > 0 {
+                assert!(set.contains(width - 1), "width {width}");
+            }
+        }
+    }
+
+    #[test]
+    fn insert_grows_and_remove_shrinks() {
+        for width in WIDTHS.into_iter().filter(|width| *width > 0) {
+            let last = width - 1;
+            let mut set = BitSet::new();
+            set.insert(last);
+            assert!(set.contains(last));
+            assert_eq!(set.first(), Some(last));
+            assert_eq!(set.iter().collect::<Vec<_>>(), vec![last]);
+            set.remove(last);
+            assert_eq!(set, BitSet::new());
+            assert_eq!(set.first(), None);
+            // removing past the end does nothing
+            set.remove(width + 1000);
+            assert_eq!(set, BitSet::new());
+        }
+    }
+
+    #[test]
+    fn intersects_only_on_common_bits() {
+        for width in WIDTHS.into_iter().filter(|width| *width > 0) {
+            let last = width - 1;
+            let high: BitSet = [last].into_iter().collect();
+            assert!(high.intersects(&high), "width {width}");
+            assert!(high.intersects(&BitSet::full(width)), "width {width}");
+            assert!(!high.intersects(&BitSet::full(last)), "width {width}");
+            assert!(!high.intersects(&BitSet::new()), "width {width}");
+
+            let two: BitSet = [0, last].into_iter().collect();
+            assert_eq!(two.intersection(&high), high, "width {width}");
+            assert_eq!(two.intersection_len(&high), 1, "width {width}");
+            assert_eq!(two.intersection_len(&BitSet::full(width)), two.iter().count(), "width {width}");
+        }
+    }
+
+    #[test]
+    fn operations_across_widths() {
+        for a in WIDTHS {
+            for b in WIDTHS {
+                let (x, y) = (BitSet::full(a), BitSet::full(b));
+                let (small, large) = (BitSet::full(a.min(b)), BitSet::full(a.max(b)));
+                assert_eq!(x.intersection(&y), small, "{a} {b}");
+                assert_eq!(x.union(&y), large, "{a} {b}");
+                assert_eq!(x.difference(&y), large.difference(&small).intersection(&x), "{a} {b}");
+                assert_eq!(x.intersects(&y), a.min(b) > 0, "{a} {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn equal_whatever_the_capacity() {
+        let mut wide: BitSet = [3, 200].into_iter().collect();
+        wide.remove(200);
+        let narrow: BitSet = [3].into_iter().collect();
+        assert_eq!(wide, narrow);
+        assert!(HashSet::from([wide]).contains(&narrow));
+        let sets: HashSet<BitSet> = [narrow, BitSet::full(4).difference(&BitSet::full(3))].into_iter().collect();
+        assert_eq!(sets.len(), 1);
+    }
+}
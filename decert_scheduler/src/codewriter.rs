@@ -1,21 +1,24 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{self, create_dir_all},
     io::Write,
     path::PathBuf,
     sync::Arc,
 };
 
+use crate::{schedule::Slot, ScheduleError};
+
 #[derive(Debug, Clone)]
 pub struct FunctionCall {
     pub fn_identifier: Arc<str>,
+    /// Length of the call in ticks, the WCET of the task
     pub cycles: u16,
     pub args: Vec<Arc<str>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Delay {
-    pub call_time_ms: i32,
+    pub ticks: i32,
 }
 
 #[derive(Debug, Clone)]
@@ -62,16 +65,8 @@ fn write_args_array(
     )
 }
 
-fn write_run_task(
-    name: &str,
-    args: Vec<Arc<str>>,
-    arg_vars: HashMap<Arc<str>, String>,
-    delay: u32,
-) -> String {
-    format!(
-        "{}\n\t\trunTask(wrapper_{name}, args_{name}, {delay});",
-        write_args_array(name, args, arg_vars)
-    )
+fn write_run_task(name: &str, delay: u32) -> String {
+    format!("runTask(wrapper_{name}, args_{name}, {delay});")
 }
 
 #[derive(Debug, Clone)]
@@ -87,43 +82,59 @@ impl CodeWriter {
             delayed_at: None,
         }
     }
-    pub fn append(&mut self, task: CodeTask, current_time_ms: i32) {
+    /// Calls of the tasks of the slots of an OBC, with delays in the gaps between them
+    pub fn from_slots(slots: &[Slot]) -> CodeWriter {
+        let mut codewriter = CodeWriter::new();
+        let mut time = 0;
+        for slot in slots {
+            if slot.start > time {
+                codewriter.start_delay(time);
+            }
+            codewriter.append(
+                CodeTask::FunctionCall(FunctionCall {
+                    fn_identifier: slot.task.clone(),
+                    cycles: slot.cycles() as u16,
+                    args: slot.sensors.iter().map(|arg| arg.sensor.clone()).collect(),
+                }),
+                slot.start,
+            );
+            time = slot.end;
+        }
+        codewriter
+    }
+    pub fn append(&mut self, task: CodeTask, current_time: i32) {
         /*May add a delay if a delay was started previously */
-        self.end_delay(current_time_ms);
+        self.end_delay(current_time);
         self.tasks.push(task);
     }
-    pub fn start_delay(&mut self, current_time_ms: i32) {
+    pub fn start_delay(&mut self, current_time: i32) {
         /*
         Starts a delay, it is safe to call this function multiple times.
         Delay will be counted from the first call to this function.
          */
         match self.delayed_at {
             Some(x) => {
-                if current_time_ms < x {
-                    self.delayed_at = Some(current_time_ms);
+                if current_time < x {
+                    self.delayed_at = Some(current_time);
                 }
             }
             None => {
-                self.delayed_at = Some(current_time_ms);
+                self.delayed_at = Some(current_time);
             }
         }
     }
 
-    fn end_delay(&mut self, current_time_ms: i32) {
-        match self.delayed_at {
-            Some(t) => {
-                println!("t: {t}, current_time_ms: {current_time_ms}");
-                assert!(t < current_time_ms);
-                self.tasks.push(CodeTask::Delay(Delay {
-                    call_time_ms: current_time_ms - t,
-                }));
-                self.delayed_at = None;
-            }
-            None => {}
+    fn end_delay(&mut self, current_time: i32) {
+        if let Some(t) = self.delayed_at {
+            assert!(t < current_time);
+            self.tasks.push(CodeTask::Delay(Delay {
+                ticks: current_time - t,
+            }));
+            self.delayed_at = None;
         }
     }
 
-    pub fn commit(&mut self, path: PathBuf, end_time: i32) -> Result<(), String> {
+    pub fn commit(&mut self, path: PathBuf, end_time: i32) -> Result<(), ScheduleError> {
         /*
         Commits the code to the file.
         Starts writing from the beginning, hence better to run this only at the end
@@ -131,30 +142,34 @@ impl CodeWriter {
         let total_time = self.tasks.iter().fold(0, |acc, x| {
             match x {
                 CodeTask::FunctionCall(t) => acc + t.cycles as i32,
-                CodeTask::Delay(t) => acc + t.call_time_ms,
+                CodeTask::Delay(t) => acc + t.ticks,
             }
         });
 
         if total_time > end_time {
-            return Err(format!(
-                "Total time of tasks exceeds the end time. Total time: {total_time}, End time: {end_time}"
-            ));
+            return Err(ScheduleError::FrameOverrun {
+                total_time,
+                end_time,
+            });
         } else if total_time < end_time {
-            println!("total_time: {total_time}, end_time: {end_time}");
             self.start_delay(total_time);
             self.end_delay(end_time);
         }
 
-        let task_wrappers = self
+        // periodic tasks run several times per frame but are defined once
+        let mut defined = HashSet::new();
+        let calls: Vec<&FunctionCall> = self
             .tasks
             .iter()
-            .filter_map(|f| {
-                if let CodeTask::FunctionCall(t) = f {
-                    Some(write_wrapper_fn(&t.fn_identifier, t.args.clone()))
-                } else {
-                    None
-                }
+            .filter_map(|f| match f {
+                CodeTask::FunctionCall(t) if defined.insert(t.fn_identifier.clone()) => Some(t),
+                _ => None,
             })
+            .collect();
+
+        let task_wrappers = calls
+            .iter()
+            .map(|t| write_wrapper_fn(&t.fn_identifier, t.args.clone()))
             .fold(String::new(), |acc, x| acc + &x);
 
         let all_args: Vec<Arc<str>> = self
@@ -167,29 +182,34 @@ impl CodeWriter {
                     None
                 }
             })
-            .flat_map(|f| f)
+            .flatten()
             .collect();
 
+        // each sensor is instantiated once even when several tasks use it
+        let mut seen = HashSet::new();
+        let unique_args: Vec<&Arc<str>> = all_args.iter().filter(|f| seen.insert(*f)).collect();
+
         let arg_vars: HashMap<Arc<str>, String> = all_args
             .iter()
             .map(|f| (f.clone(), format!("var_{}", f.to_lowercase())))
             .collect();
 
-        let inits = all_args
+        let inits = unique_args
             .iter()
-            .map(|f| format!("{}* {} = new {}();\n\t", f, arg_vars[f], f))
+            .map(|f| format!("{}* {} = new {}();\n\t", f, arg_vars[*f], f))
+            .chain(calls.iter().map(|t| {
+                format!(
+                    "{}\n\t",
+                    write_args_array(&t.fn_identifier, t.args.clone(), arg_vars.clone())
+                )
+            }))
             .fold(String::new(), |acc, x| acc + &x);
 
         let mut tasks_string = "".to_string();
         for task in self.tasks.clone() {
             match task {
                 CodeTask::FunctionCall(t) => {
-                    tasks_string += &write_run_task(
-                        &t.fn_identifier,
-                        t.args.clone(),
-                        arg_vars.clone(),
-                        t.cycles as u32,
-                    );
+                    tasks_string += &write_run_task(&t.fn_identifier, t.cycles as u32);
                     tasks_string += "\n\t\t";
                     //&format!(
                     //     "runTask({}, {} ,{});\n\t\t",
@@ -199,7 +219,7 @@ impl CodeWriter {
                     // );
                 }
                 CodeTask::Delay(t) => {
-                    tasks_string += &format!("delay({});\n", t.call_time_ms);
+                    tasks_string += &format!("delay({});\n\t\t", t.ticks);
                 }
             }
         }
@@ -209,17 +229,22 @@ impl CodeWriter {
             .replace("{TASKS}", &tasks_string)
             .replace("{INITS}", &inits);
 
-        create_dir_all(&path).map_err(|e| e.to_string())?;
+        let entry_path = path.join("entry.cpp");
+        let write_error = |error| ScheduleError::WriteEntry {
+            path: entry_path.clone(),
+            error,
+        };
+        create_dir_all(&path).map_err(write_error)?;
 
         let mut entry_cpp = fs::OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(path.join("entry.cpp"))
-            .map_err(|e| e.to_string())?;
+            .open(&entry_path)
+            .map_err(write_error)?;
         entry_cpp
-            .write(final_code.as_bytes())
-            .map_err(|e| e.to_string())?;
+            .write_all(final_code.as_bytes())
+            .map_err(write_error)?;
         Ok(())
     }
 }
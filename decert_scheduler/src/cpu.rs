@@ -6,60 +6,158 @@ use std::{
 use confparse::Task;
 
 #[derive(Clone, Debug)]
-pub struct CPU {
+pub struct Cpu {
     pub id: u32,
     tasks: Vec<Task>,
-    runnable_tasks: BinaryHeap<(i32, Task)>,
-    completed_tasks: HashSet<Task>,
+    /// Length of the schedule when any task is periodic
+    hyperperiod: Option<i32>,
+    runnable_tasks: BinaryHeap<((u16, i32), Task)>,
+    /// Number of finished instances of each task
+    completed_tasks: HashMap<Task, u32>,
     satisfied: HashSet<Arc<str>>,
+    /// Ports of `@on_port:` written by another OBC, with the number of writes per schedule
+    reads: HashMap<Arc<str>, u32>,
 }
 
-impl CPU {
-    pub fn new(id: u32, tasks: Vec<Task>, initials: Vec<Arc<str>>) -> Self {
-        let mut runnable_tasks = BinaryHeap::new();
-        for task in &tasks {
-            if task.requires.is_empty() || task.requires.iter().all(|req| initials.contains(req)) {
-                runnable_tasks.push((-(task.cycles as i32), task.clone()));
-            }
-        }
-        CPU {
+/// Number of times the task runs in one schedule
+pub fn instances(task: &Task, hyperperiod: Option<i32>) -> u32 {
+    match (task.period, hyperperiod) {
+        (Some(period), Some(hyperperiod)) => (hyperperiod / period as i32) as u32,
+        _ => 1,
+    }
+}
+
+impl Cpu {
+    pub fn new(
+        id: u32,
+        tasks: Vec<Task>,
+        initials: Vec<Arc<str>>,
+        hyperperiod: Option<i32>,
+        reads: HashMap<Arc<str>, u32>,
+    ) -> Self {
+        let mut cpu = Cpu {
             id,
             tasks,
-            runnable_tasks,
-            completed_tasks: HashSet::new(),
+            hyperperiod,
+            runnable_tasks: BinaryHeap::new(),
+            completed_tasks: HashMap::new(),
             satisfied: initials.into_iter().collect(),
-        }
+            reads,
+        };
+        cpu.reset(0, &HashMap::new());
+        cpu
     }
 
     pub fn get_task(&mut self) -> Option<(Task, usize)> {
-        println!("runnable_tasks: {:?}", self.runnable_tasks);
         self.runnable_tasks.pop().map(|(_, task)| (task, self.runnable_tasks.len()))
     }
 
     pub fn task_complete(&mut self, task: &Task) {
-        self.completed_tasks.insert(task.clone());
+        *self.completed_tasks.entry(task.clone()).or_default() += 1;
         for cond in &task.satisfies {
             self.satisfied.insert(cond.clone());
         }
     }
 
-    pub fn reset(&mut self) {
+    /// Sets a condition satisfied by a task of another OBC, or of this one as `obc{id}.condition`
+    pub fn satisfy(&mut self, condition: Arc<str>) {
+        self.satisfied.insert(condition);
+    }
+
+    fn is_pending(&self, task: &Task) -> bool {
+        self.completed_tasks.get(task).copied().unwrap_or(0) < instances(task, self.hyperperiod)
+    }
+
+    /// Whether the writers of the ports the task reads have run, once for each run
+    /// of the task for as long as they run as often
+    fn ports_written(&self, task: &Task, written: &HashMap<Arc<str>, u32>) -> bool {
+        let completed = self.completed_tasks.get(task).copied().unwrap_or(0);
+        task.on_port.iter().all(|port| match self.reads.get(port) {
+            Some(writes) => written.get(port).copied().unwrap_or(0) >= (completed + 1).min(*writes),
+            None => true,
+        })
+    }
+
+    /// Release time of the next instance of the task
+    pub fn release_time(&self, task: &Task) -> i32 {
+        let completed = self.completed_tasks.get(task).copied().unwrap_or(0) as i32;
+        task.period.map_or(0, |period| completed * period as i32)
+    }
+
+    /// Time by which the next instance of the task must finish, if it has a deadline
+    pub fn deadline(&self, task: &Task) -> Option<i32> {
+        task.deadline
+            .or(task.period)
+            .map(|deadline| self.release_time(task) + deadline as i32)
+    }
+
+    /// Earliest release of a pending instance after `time`
+    pub fn next_release(&self, time: i32) -> Option<i32> {
+        self.tasks
+            .iter()
+            .filter(|task| self.is_pending(task))
+            .map(|task| self.release_time(task))
+            .filter(|release| *release > time)
+            .min()
+    }
+
+    /// A task with an instance which never ran
+    pub fn unfinished(&self) -> Option<&Task> {
+        self.tasks.iter().find(|task| self.is_pending(task))
+    }
+
+    /// Finds the runnable tasks at `time`, `written` holds the number of times each port has been written
+    pub fn reset(&mut self, time: i32, written: &HashMap<Arc<str>, u32>) {
         self.runnable_tasks.clear();
         for task in &self.tasks {
-            if self.completed_tasks.contains(task) {
+            if !self.is_pending(task) || self.release_time(task) > time {
                 continue;
             }
-            if task.requires.iter().all(|req| self.satisfied.contains(req)) {
-                self.runnable_tasks
-                    .push((-(task.cycles as i32), task.clone()));
+            if task.requires.iter().all(|req| self.satisfied.contains(req)) && self.ports_written(task, written) {
+                self.runnable_tasks.push((rank(task), task.clone()));
             }
         }
     }
 }
 
-pub fn get_next_tasks(unutilised_cpus: &HashSet<u32>, cpus: &mut HashMap<u32, CPU>) -> HashMap<u32, Option<(Task, usize)>> {
+/// Order of runnable tasks: highest priority first, shorter tasks break ties
+fn rank(task: &Task) -> (u16, i32) {
+    (task.priority(), -(task.cycles as i32))
+}
+
+pub fn get_next_tasks(unutilised_cpus: &HashSet<u32>, cpus: &mut HashMap<u32, Cpu>) -> HashMap<u32, Option<(Task, usize)>> {
     /* returns a HashMap of next tasks for the given list of cpus*/
-    unutilised_cpus.into_iter()
-        .filter_map(|id| cpus.get_mut(&id).map(|f| (*id, f.get_task())))
+    unutilised_cpus.iter()
+        .filter_map(|id| cpus.get_mut(id).map(|f| (*id, f.get_task())))
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use confparse::{conf_from_str, Duration};
+
+    fn cpu(text: &str) -> Cpu {
+        let conf = conf_from_str("tasks.conf", text, 1, Duration::DEFAULT_TICK).unwrap();
+        Cpu::new(1, conf.tasks, conf.initial, None, HashMap::new())
+    }
+
+    #[test]
+    fn unfinished_without_deadline() {
+        let mut cpu = cpu("IN:\nOUT:\n\nINIT_CONDITIONS:\n\nTask a(S): 1\n\n@requires: never\nTask b(S): 1\n");
+        let (a, _) = cpu.get_task().unwrap();
+        assert_eq!(&*a.name, "a");
+        cpu.task_complete(&a);
+        cpu.reset(1, &HashMap::new());
+        assert!(cpu.get_task().is_none());
+        assert_eq!(cpu.unfinished().map(|task| &*task.name), Some("b"));
+    }
+
+    #[test]
+    fn highest_priority_first() {
+        let mut cpu = cpu("IN:\nOUT:\n\nINIT_CONDITIONS:\n\nTask a(S): 1\n\nTask b(S): 2\n\n@priority: 3\nTask c(S): 5\n\n@priority: 1\nTask d(S): 4\n");
+        let order: Vec<_> = std::iter::from_fn(|| cpu.get_task()).map(|(task, _)| task.name.to_string()).collect();
+        // shorter tasks first among equal priorities
+        assert_eq!(order, ["c", "d", "a", "b"]);
+    }
+}
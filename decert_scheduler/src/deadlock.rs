@@ -0,0 +1,303 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
+
+use confparse::{qualified_condition, Conf, Task};
+
+use crate::validate::Problem;
+
+// Tasks wait for the conditions they require, which stay satisfied once a task
+// satisfies them, and for the ports of `@on_port:` other OBCs write, which are
+// written once a writer of the port has run. Starting from `INIT_CONDITIONS:`,
+// the tasks whose waits are all over can run and satisfy more, until no more
+// tasks can. The tasks left never run: they wait on each other in a cycle, or on
+// tasks which do.
+
+/// A condition and the OBC it belongs to
+pub type Condition = (u32, Arc<str>);
+
+/// What a task waits for before it runs
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Wait {
+    Condition(Condition),
+    /// A port of `@on_port:` which the task's OBC reads, written by another OBC
+    Port(Arc<str>),
+}
+
+/// The tasks of every OBC with what they wait for from each other, conditions
+/// of other OBCs and ports included
+struct Dependencies<'a> {
+    topology: &'a HashMap<u32, Conf>,
+    /// Tasks in the order of OBC ids, then of their files
+    tasks: Vec<(u32, &'a Task)>,
+    initial: HashSet<Wait>,
+    /// The tasks which satisfy each condition or write each port
+    satisfiers: HashMap<Wait, Vec<usize>>,
+}
+
+impl<'a> Dependencies<'a> {
+    fn new(topology: &'a HashMap<u32, Conf>) -> Self {
+        let mut obc_ids: Vec<_> = topology.keys().copied().collect();
+        obc_ids.sort();
+        let tasks: Vec<(u32, &Task)> = obc_ids
+            .iter()
+            .flat_map(|obc_id| topology[obc_id].tasks.iter().map(|task| (*obc_id, task)))
+            .collect();
+        let initial = obc_ids
+            .iter()
+            .flat_map(|obc_id| {
+                topology[obc_id].initial.iter().map(|condition| Wait::Condition((*obc_id, condition.clone())))
+            })
+            .collect();
+        let mut satisfiers: HashMap<Wait, Vec<usize>> = HashMap::new();
+        for (i, (obc_id, task)) in tasks.iter().enumerate() {
+            for condition in &task.satisfies {
+                satisfiers.entry(Wait::Condition((*obc_id, condition.clone()))).or_default().push(i);
+            }
+            for port in task.on_port.iter().filter(|port| topology[obc_id].outports.contains(port)) {
+                satisfiers.entry(Wait::Port(port.clone())).or_default().push(i);
+            }
+        }
+        Dependencies { topology, tasks, initial, satisfiers }
+    }
+
+    /// What the task waits for, each with how it is written in the task
+    fn waits(&self, task: usize) -> impl Iterator<Item = (&Arc<str>, Wait)> + '_ {
+        let (obc_id, task) = self.tasks[task];
+        let conf = &self.topology[&obc_id];
+        let requires = task.requires.iter().map(move |condition| match qualified_condition(condition) {
+            Some((obc_id, name)) => (condition, Wait::Condition((obc_id, name.into()))),
+            None => (condition, Wait::Condition((obc_id, condition.clone()))),
+        });
+        let ports = task
+            .on_port
+            .iter()
+            .filter(|port| conf.inports.contains(port) && !conf.outports.contains(port))
+            .map(|port| (port, Wait::Port(port.clone())));
+        requires.chain(ports)
+    }
+
+    /// What ends up satisfied or written and whether each task runs, when
+    /// `extra` is satisfied from the start as well
+    fn run(&self, extra: Option<&Wait>) -> (HashSet<Wait>, Vec<bool>) {
+        let mut satisfied = self.initial.clone();
+        satisfied.extend(extra.cloned());
+        let mut runs = vec![false; self.tasks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, (obc_id, task)) in self.tasks.iter().enumerate() {
+                if runs[i] || !self.waits(i).all(|(_, wait)| satisfied.contains(&wait)) {
+                    continue;
+                }
+                runs[i] = true;
+                changed = true;
+                satisfied.extend(task.satisfies.iter().map(|condition| Wait::Condition((*obc_id, condition.clone()))));
+                let outports = &self.topology[obc_id].outports;
+                satisfied.extend(task.on_port.iter().filter(|port| outports.contains(port)).map(|port| Wait::Port(port.clone())));
+            }
+        }
+        (satisfied, runs)
+    }
+
+    /// Tasks whose waits form a cycle, each with what it waits for from the next
+    /// one, the last one's from the first.
+    fn cycles(&self, satisfied: &HashSet<Wait>, runs: &[bool]) -> Vec<Vec<(usize, Wait)>> {
+        // a task points to those satisfying a condition or writing a port it waits for, which never run either
+        let edges: Vec<Vec<(usize, Wait)>> = (0..self.tasks.len())
+            .map(|i| {
+                if runs[i] {
+                    return vec![];
+                }
+                self.waits(i)
+                    .filter(|(_, wait)| !satisfied.contains(wait))
+                    .flat_map(|(_, wait)| {
+                        let satisfiers = self.satisfiers.get(&wait).map_or(&[][..], |tasks| tasks);
+                        satisfiers.iter().map(move |task| (*task, wait.clone()))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut cycles = vec![];
+        let mut in_cycle = vec![false; self.tasks.len()];
+        for start in 0..self.tasks.len() {
+            if in_cycle[start] {
+                continue;
+            }
+            let Some(cycle) = shortest_cycle(&edges, start) else {
+                continue;
+            };
+            // other cycles through these tasks are found from the tasks not yet in one
+            for (task, _) in &cycle {
+                in_cycle[*task] = true;
+            }
+            cycles.push(cycle);
+        }
+        cycles
+    }
+}
+
+/// Shortest path of `edges` from `start` back to itself
+fn shortest_cycle(edges: &[Vec<(usize, Wait)>], start: usize) -> Option<Vec<(usize, Wait)>> {
+    // the edge each task was first reached by
+    let mut reached: HashMap<usize, (usize, &Wait)> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(task) = queue.pop_front() {
+        for (next, wait) in &edges[task] {
+            if reached.contains_key(next) {
+                continue;
+            }
+            reached.insert(*next, (task, wait));
+            if *next == start {
+                let mut cycle = vec![];
+                let mut at = start;
+                loop {
+                    let (from, wait) = reached[&at];
+                    cycle.push((from, wait.clone()));
+                    at = from;
+                    if at == start {
+                        break;
+                    }
+                }
+                cycle.reverse();
+                return Some(cycle);
+            }
+            queue.push_back(*next);
+        }
+    }
+    None
+}
+
+/// Tasks which never run because of the conditions they require or the ports they
+/// wait on. A requirement no task satisfies is left to [`Problem::UnsatisfiableRequirement`],
+/// a port no task writes to [`Problem::NoPortWriter`].
+pub fn deadlocks(topology: &HashMap<u32, Conf>) -> Vec<Problem> {
+    let dependencies = Dependencies::new(topology);
+    let (satisfied, runs) = dependencies.run(None);
+    if runs.iter().all(|runs| *runs) {
+        return vec![];
+    }
+
+    let mut problems = vec![];
+    let mut in_cycle = vec![false; runs.len()];
+    for cycle in dependencies.cycles(&satisfied, &runs) {
+        // a condition of the cycle which lets all its tasks run once set initially
+        let fix = cycle.iter().find_map(|(_, wait)| match wait {
+            Wait::Condition(condition) => {
+                let (_, runs) = dependencies.run(Some(wait));
+                cycle.iter().all(|(task, _)| runs[*task]).then(|| condition.clone())
+            }
+            Wait::Port(_) => None,
+        });
+        for (task, _) in &cycle {
+            in_cycle[*task] = true;
+        }
+        let cycle = cycle
+            .into_iter()
+            .map(|(task, wait)| {
+                let (obc_id, task) = dependencies.tasks[task];
+                (obc_id, task.name.clone(), wait)
+            })
+            .collect();
+        problems.push(Problem::DependencyCycle { cycle, fix });
+    }
+
+    for (i, (obc_id, task)) in dependencies.tasks.iter().enumerate() {
+        if runs[i] || in_cycle[i] {
+            continue;
+        }
+        // the first wait which has tasks to end it, as written
+        let waiting = dependencies
+            .waits(i)
+            .find(|(_, wait)| !satisfied.contains(wait) && dependencies.satisfiers.contains_key(wait));
+        match waiting {
+            Some((condition, Wait::Condition(_))) => problems.push(Problem::UnreachableTask {
+                obc_id: *obc_id,
+                task: task.name.clone(),
+                condition: condition.clone(),
+            }),
+            Some((port, Wait::Port(_))) => problems.push(Problem::UnwrittenPort {
+                obc_id: *obc_id,
+                task: task.name.clone(),
+                port: port.clone(),
+            }),
+            None => {}
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use confparse::{conf_from_str, Duration};
+
+    fn topology(confs: &[(u32, &str)]) -> HashMap<u32, Conf> {
+        confs
+            .iter()
+            .map(|(obc_id, text)| (*obc_id, conf_from_str("tasks.conf", text, *obc_id, Duration::DEFAULT_TICK).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn runs_in_order() {
+        let topology = topology(&[(
+            1,
+            "IN:\nOUT:\n\nINIT_CONDITIONS: ready\n\n@requires: ready\nTask a(S): 1\n@satisfies: done\n\n@requires: done\nTask b(S): 1\n",
+        )]);
+        assert_eq!(deadlocks(&topology), []);
+    }
+
+    #[test]
+    fn condition_cycle() {
+        let topology = topology(&[(
+            1,
+            "IN:\nOUT:\n\nINIT_CONDITIONS:\n\n@requires: y\nTask a(S): 1\n@satisfies: x\n\n@requires: x\nTask b(S): 1\n@satisfies: y\n\n@requires: y\nTask c(S): 1\n",
+        )]);
+        let problems = deadlocks(&topology);
+        let condition = |name: &str| Wait::Condition((1, name.into()));
+        assert_eq!(
+            problems,
+            [
+                Problem::DependencyCycle {
+                    cycle: vec![(1, "a".into(), condition("y")), (1, "b".into(), condition("x"))],
+                    fix: Some((1, "y".into())),
+                },
+                Problem::UnreachableTask { obc_id: 1, task: "c".into(), condition: "y".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn port_cycle() {
+        // each task writes its OBC's port once it has read the other's
+        let topology = topology(&[
+            (1, "IN: p2\nOUT: p1\n\nINIT_CONDITIONS:\n\n@on_port: p1, p2\nTask a(S): 1\n"),
+            (2, "IN: p1\nOUT: p2\n\nINIT_CONDITIONS:\n\n@on_port: p2, p1\nTask b(S): 1\n\nTask c(S): 1\n"),
+        ]);
+        let problems = deadlocks(&topology);
+        assert_eq!(
+            problems,
+            [Problem::DependencyCycle {
+                cycle: vec![(1, "a".into(), Wait::Port("p2".into())), (2, "b".into(), Wait::Port("p1".into()))],
+                fix: None,
+            }]
+        );
+        assert!(problems[0].to_string().contains("'a' → port p2 → 'obc2.b' → port p1 → 'a'"));
+    }
+
+    #[test]
+    fn unwritten_port() {
+        // the only writer of p1 waits for a condition nothing satisfies in time
+        let topology = topology(&[
+            (1, "IN:\nOUT: p1\n\nINIT_CONDITIONS:\n\n@requires: x\n@on_port: p1\nTask w(S): 1\n@satisfies: x\n"),
+            (2, "IN: p1\nOUT:\n\nINIT_CONDITIONS:\n\n@on_port: p1\nTask r(S): 1\n"),
+        ]);
+        let problems = deadlocks(&topology);
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(&problems[0], Problem::DependencyCycle { cycle, .. } if cycle.len() == 1));
+        assert_eq!(problems[1], Problem::UnwrittenPort { obc_id: 2, task: "r".into(), port: "p1".into() });
+    }
+}
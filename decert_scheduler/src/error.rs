@@ -0,0 +1,95 @@
+use std::{
+    fmt::{self, Display},
+    io,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use crate::validate::Problem;
+
+/// An error while scheduling the tasks of all OBCs.
+#[derive(Debug)]
+pub enum ScheduleError {
+    /// `sensors.json` could not be read
+    ReadSensors(io::Error),
+    /// `sensors.json` is not valid
+    ParseSensors(serde_json::Error),
+    /// The project has mistakes which keep it from being scheduled
+    Invalid(Vec<Problem>),
+    /// The code generated for an OBC runs longer than the schedule
+    FrameOverrun { total_time: i32, end_time: i32 },
+    /// `entry.cpp` could not be written
+    WriteEntry { path: PathBuf, error: io::Error },
+    /// An instance of a task cannot finish before its deadline
+    DeadlineMiss {
+        obc_id: u32,
+        task: Arc<str>,
+        release: i32,
+        deadline: i32,
+    },
+    /// A task without a deadline is still waiting for its requirements or ports when
+    /// nothing else runs, it would be left out of the generated code
+    NeverRuns { obc_id: u32, task: Arc<str> },
+    /// Tasks keep running past the end of the hyperperiod
+    HyperperiodOverrun { end: i32, hyperperiod: i32 },
+    /// The least common multiple of all periods does not fit in the schedule
+    HyperperiodTooLong(u64),
+}
+
+impl Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::ReadSensors(e) => write!(f, "Failed to read sensors.json: {e}"),
+            ScheduleError::ParseSensors(e) => write!(f, "Invalid sensors.json: {e}"),
+            ScheduleError::Invalid(problems) => {
+                write!(f, "Found {} problem(s) in the project", problems.len())?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            }
+            ScheduleError::FrameOverrun {
+                total_time,
+                end_time,
+            } => write!(
+                f,
+                "Total time of tasks exceeds the end time. Total time: {total_time}, End time: {end_time}"
+            ),
+            ScheduleError::WriteEntry { path, error } => {
+                write!(f, "Failed to write {}: {error}", path.display())
+            }
+            ScheduleError::DeadlineMiss {
+                obc_id,
+                task,
+                release,
+                deadline,
+            } => write!(
+                f,
+                "Task '{task}' on obc{obc_id} released at cycle {release} misses its deadline at cycle {deadline}"
+            ),
+            ScheduleError::NeverRuns { obc_id, task } => write!(
+                f,
+                "Task '{task}' on obc{obc_id} never runs, it still waits on a condition or port when the schedule ends"
+            ),
+            ScheduleError::HyperperiodOverrun { end, hyperperiod } => write!(
+                f,
+                "Tasks run until cycle {end}, past the hyperperiod of {hyperperiod} cycles"
+            ),
+            ScheduleError::HyperperiodTooLong(hyperperiod) => write!(
+                f,
+                "Hyperperiod of {hyperperiod} cycles is too long, choose periods with a smaller common multiple"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScheduleError::ReadSensors(e) => Some(e),
+            ScheduleError::ParseSensors(e) => Some(e),
+            ScheduleError::WriteEntry { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
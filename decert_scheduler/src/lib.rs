@@ -1,18 +1,34 @@
 use std::{
-    collections::{BinaryHeap, HashMap, HashSet},
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
     fs::read_to_string,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
-use codewriter::{CodeTask, CodeWriter, FunctionCall};
+use codewriter::CodeWriter;
 use confparse::{Conf, Task};
-use cpu::{get_next_tasks, CPU};
-use scheduler::{task_schedule, BitMap};
+use cpu::{get_next_tasks, instances, Cpu};
+use scheduler::{task_schedule, SensorLocks};
 use serde::Deserialize;
+mod bitset;
 mod codewriter;
 mod cpu;
+mod deadlock;
+mod error;
+mod message;
+mod report;
+mod schedule;
 mod scheduler;
+mod select;
+mod validate;
+
+pub use error::ScheduleError;
+pub use message::{Field, FieldType, Port};
+pub use report::{FrameSlack, SlackReport, TaskSlack};
+pub use schedule::{Schedule, Slot};
+pub use deadlock::Wait;
+pub use validate::{is_identifier, validate, Problem};
 
 #[derive(Deserialize, Clone)]
 pub struct Sensors {
@@ -24,47 +40,132 @@ pub struct Sensors {
 #[derive(Deserialize)]
 pub struct SensorJson {
     pub sensors: Vec<Sensors>,
-    pub ports: Vec<String>,
+    pub ports: Vec<Port>,
+    /// Buses, channels and other resources tasks take turns to use, with `@uses:`
+    #[serde(default)]
+    pub resources: Vec<Arc<str>>,
 }
 
-fn read_sensors() -> Result<SensorJson, String> {
-    let data = read_to_string("./sensors.json").map_err(|e| e.to_string())?;
-    let sensorjson: SensorJson = serde_json::from_str(&data)
-        .map_err(|e| e.to_string())
-        .map_err(|e| e.to_string())?;
+pub fn read_sensors(path: impl AsRef<Path>) -> Result<SensorJson, ScheduleError> {
+    let data = read_to_string(path).map_err(ScheduleError::ReadSensors)?;
+    let sensorjson: SensorJson =
+        serde_json::from_str(&data).map_err(ScheduleError::ParseSensors)?;
     Ok(sensorjson)
 }
 
-pub fn schedule(topology: &HashMap<u32, Conf>) -> Result<SensorJson, String> {
-    let sensorjson = read_sensors()?;
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Least common multiple of the periods of all tasks, `None` when no task is periodic
+fn hyperperiod(topology: &HashMap<u32, Conf>) -> Result<Option<i32>, ScheduleError> {
+    let mut hyperperiod: Option<u64> = None;
+    for period in topology
+        .values()
+        .flat_map(|conf| conf.tasks.iter())
+        .filter_map(|task| task.period)
+    {
+        let period = period as u64;
+        let lcm = match hyperperiod {
+            Some(h) => h / gcd(h, period) * period,
+            None => period,
+        };
+        if lcm > i32::MAX as u64 {
+            return Err(ScheduleError::HyperperiodTooLong(lcm));
+        }
+        hyperperiod = Some(lcm);
+    }
+    Ok(hyperperiod.map(|h| h as i32))
+}
+
+/// Name of a condition of the OBC `obc_id` as required by the tasks of other OBCs
+pub(crate) fn qualified(obc_id: u32, condition: &str) -> Arc<str> {
+    format!("obc{obc_id}.{condition}").into()
+}
+
+/// Whether `task` writes `port` for the tasks of other OBCs which wait on it
+fn writes_port(task: &Task, port: &str, port2obc: &HashMap<String, u32>) -> bool {
+    port2obc.get(port) == Some(&task.obc_id) && task.on_port.iter().any(|p| &**p == port)
+}
+
+/// Number of writes per schedule of each port read with `@on_port:` by the OBC `obc_id`
+fn port_reads(
+    obc_id: u32,
+    topology: &HashMap<u32, Conf>,
+    port2obc: &HashMap<String, u32>,
+    hyperperiod: Option<i32>,
+) -> HashMap<Arc<str>, u32> {
+    let writers: Vec<&Task> = topology.values().flat_map(|conf| &conf.tasks).collect();
+    topology[&obc_id]
+        .tasks
+        .iter()
+        .flat_map(|task| &task.on_port)
+        .filter(|port| port2obc.get(&***port).is_some_and(|writer| *writer != obc_id))
+        .map(|port| {
+            let writes = writers
+                .iter()
+                .filter(|task| writes_port(task, port, port2obc))
+                .map(|task| instances(task, hyperperiod))
+                .sum();
+            (port.clone(), writes)
+        })
+        .collect()
+}
+
+/// Schedules the tasks of every OBC. `port2obc` maps each port to the OBC which
+/// writes it, a task with `@on_port:` runs after the writer of the port has.
+///
+/// Tasks are given their WCET, [`SlackReport::new`] tells how much of it is left over
+/// at BCET. Nothing is written or printed, [`write_entries`] generates the code of the
+/// schedule and the warnings of [`validate`] are returned with it.
+pub fn schedule(
+    topology: &HashMap<u32, Conf>,
+    port2obc: &HashMap<String, u32>,
+    sensorjson: &SensorJson,
+) -> Result<Schedule, ScheduleError> {
     let sensors = sensorjson.sensors.clone();
-    let mut cpus: HashMap<u32, CPU> = topology
+    let hyperperiod = hyperperiod(topology)?;
+    // conditions of other OBCs are required as `obc{id}.condition`
+    let qualified_initials: Vec<Arc<str>> = topology
+        .iter()
+        .flat_map(|(cpu_id, conf)| conf.initial.iter().map(move |condition| qualified(*cpu_id, condition)))
+        .collect();
+    let mut cpus: HashMap<u32, Cpu> = topology
         .iter()
         .map(|(cpu_id, conf)| {
-            (
-                *cpu_id,
-                CPU::new(*cpu_id, conf.tasks.clone(), conf.initial.clone()),
-            )
+            let reads = port_reads(*cpu_id, topology, port2obc, hyperperiod);
+            let initials = conf.initial.iter().chain(&qualified_initials).cloned().collect();
+            (*cpu_id, Cpu::new(*cpu_id, conf.tasks.clone(), initials, hyperperiod, reads))
         })
         .collect();
+    let mut written: HashMap<Arc<str>, u32> = HashMap::new(); // number of writes of each port so far
 
-    let sensors_to_int: HashMap<Arc<str>, u8> = sensors
+    let sensors_to_int: HashMap<Arc<str>, usize> = sensors
         .iter()
+        .map(|sensor| &sensor.name)
+        .chain(&sensorjson.resources)
         .enumerate()
-        .map(|(loc, sensor)| (sensor.name.clone(), loc as u8))
-        .collect(); // gives a map from sensor name to its location in sensors vector
+        .map(|(loc, name)| (name.clone(), loc))
+        .collect(); // gives a map from sensor name to its location in sensors vector, resources come after the sensors
+
+    let (mut warnings, problems): (Vec<_>, Vec<_>) =
+        validate(topology, sensorjson).into_iter().partition(Problem::is_warning);
+    if !problems.is_empty() {
+        return Err(ScheduleError::Invalid(problems));
+    }
 
-    let mut sensor_bitmap = BitMap::new(); // sensor bit map
+    let mut sensor_locks = SensorLocks::new([], &sensors_to_int); // sensors held by running tasks
 
-    let mut cpu_codewriter: HashMap<u32, CodeWriter> = cpus
-        .iter()
-        .map(|(id, _)| (*id, CodeWriter::new()))
-        .collect(); // codewriter for each cpu
+    let mut slots: BTreeMap<u32, Vec<Slot>> = cpus.keys().map(|id| (*id, vec![])).collect(); // slots of each cpu
 
     // let mut task2cpus: HashMap<Task, u32> = HashMap::new(); // task -> cpu_id
-    let mut scheduled_tasks: BinaryHeap<(i32, Task)> = BinaryHeap::new(); // currently scheduled tasks
-    let mut next_tasks: Vec<(Task, u8)>; // stores the next set of tasks to be scheduled
-    let mut pending_tasks: HashMap<Task, u8> = HashMap::new(); // stores the waits of tasks which failed to get scheduled
+    let mut scheduled_tasks: BinaryHeap<(Reverse<i32>, Task)> = BinaryHeap::new(); // currently scheduled tasks by finish time
+    let mut next_tasks: Vec<(Task, u32)>; // stores the next set of tasks to be scheduled
+    let mut pending_tasks: HashMap<Task, u32> = HashMap::new(); // stores the waits of tasks which failed to get scheduled
     let mut unutilized_cpus: HashSet<u32> = cpus.keys().fold(HashSet::new(), |mut acc, x| {
         acc.insert(*x);
         acc
@@ -72,53 +173,66 @@ pub fn schedule(topology: &HashMap<u32, Conf>) -> Result<SensorJson, String> {
     let mut time = 0;
 
     loop {
-        println!("Cycle {}", time);
         loop {
             let mut next_tasks_with_runnable_tasks_left: Vec<_> = get_next_tasks(&unutilized_cpus, &mut cpus)
-                .into_iter()
-                .filter_map(|(cpu_id, task)| {
-                    let Some((task, runnable_tasks_left)) = task else {
-                        return None;
-                    };
+                .into_values()
+                .filter_map(|task| {
+                    let (task, runnable_tasks_left) = task?;
+                    let priority = Reverse(task.priority());
                     if pending_tasks.contains_key(&task) {
-                        Some((runnable_tasks_left, (task.clone(), pending_tasks[&task] as u8)))
+                        Some(((priority, runnable_tasks_left), (task.clone(), pending_tasks[&task])))
                     } else {
-                        // initially set task-wt to 1
-                        Some((runnable_tasks_left, (task, 1 as u8)))
+                        // initially set task-wt to 1 more than its priority
+                        let weight = task.priority() as u32 + 1;
+                        Some(((priority, runnable_tasks_left), (task, weight)))
                     }
                 })
                 .collect();
             next_tasks_with_runnable_tasks_left.sort();
 
             next_tasks = next_tasks_with_runnable_tasks_left.into_iter().map(|(_, x)| x).collect();
-            println!("next_tasks: {:?}", next_tasks);
 
             if next_tasks.is_empty() {
-                // reset and continue
-                cpus.iter_mut().for_each(|(id, cpu)| {
-                    cpu.reset();
-                });
-                unutilized_cpus.iter().for_each(|id| {
-                    let Some(codewriter) = cpu_codewriter.get_mut(id) else {
-                        return;
-                    };
-                    codewriter.start_delay(time);
-                });
+                // idle cpus wait for the next event
                 break;
             }
 
             // pushed newly scheduled tasks into scheduled tasks
-            // println!("sensors_to_int: {:?}", sensors_to_int);
-            let task_currently_scheduled =
-                task_schedule(&next_tasks, &sensors_to_int, sensor_bitmap);
-            // println!("task_currently_scheduled: {:?}", task_currently_scheduled);
+            let (task_currently_scheduled, optimal) =
+                task_schedule(&next_tasks, &sensors_to_int, &sensor_locks);
+            if !optimal {
+                warnings.push(Problem::UnprovenChoice { time });
+            }
             for task in &task_currently_scheduled {
-                task.args
-                    .iter()
-                    .for_each(|sensor| sensor_bitmap.set(sensors_to_int[sensor], true));
+                sensor_locks.lock(task, &sensors_to_int);
                 // remove the cpu of these tasks from unutilized
                 unutilized_cpus.remove(&task.obc_id);
-                scheduled_tasks.push((-(task.cycles as i32), task.clone()));
+
+                let task_cpu = &cpus[&task.obc_id];
+                let finish = time + task.cycles as i32;
+                if let Some(deadline) = task_cpu.deadline(task) {
+                    if finish > deadline {
+                        return Err(ScheduleError::DeadlineMiss {
+                            obc_id: task.obc_id,
+                            task: task.name.clone(),
+                            release: task_cpu.release_time(task),
+                            deadline,
+                        });
+                    }
+                }
+
+                slots
+                    .get_mut(&task.obc_id)
+                    .expect("Did not find the slots of this cpu. Impossible!")
+                    .push(Slot {
+                        task: task.name.clone(),
+                        start: time,
+                        end: finish,
+                        bcet: task.bcet.unwrap_or(task.cycles),
+                        sensors: task.args.clone(),
+                        resources: task.uses.clone(),
+                    });
+                scheduled_tasks.push((Reverse(finish), task.clone()));
             }
 
             // append the rest of the tasks to pending tasks
@@ -127,61 +241,276 @@ pub fn schedule(topology: &HashMap<u32, Conf>) -> Result<SensorJson, String> {
                     pending_tasks.insert(task.clone(), weight + 1);
                 }
             });
-            println!("task_currently_scheduled: {:?}", task_currently_scheduled);
-            println!("unutilized_cpus: {:?}", unutilized_cpus);
         }
-        // if empty then schduling completed
-        if scheduled_tasks.is_empty() {
+
+        // move to the next time a task finishes or a periodic task is released
+        let next_finish = scheduled_tasks.peek().map(|(Reverse(finish), _)| *finish);
+        let next_release = cpus.values().filter_map(|cpu| cpu.next_release(time)).min();
+        let Some(next_time) = next_finish.into_iter().chain(next_release).min() else {
+            // nothing is running and nothing will be released, schduling completed
             break;
-        }
+        };
+        time = next_time;
 
-        let mut curr_cycle = 0;
         // set requirements satisfied
-        while let Some(task_entry) = scheduled_tasks.pop() {
-            println!("task_entry: {:?}", task_entry);
+        while let Some((Reverse(finish), _)) = scheduled_tasks.peek() {
+            if *finish != time {
+                break;
+            }
+            let (_, curr_task) = scheduled_tasks.pop().expect("peeked above");
             let task_cpu = cpus
-                .get_mut(&task_entry.1.obc_id)
+                .get_mut(&curr_task.obc_id)
                 .expect("Did not find CPU for id. Impossible!");
-            let curr_task = task_entry.1;
-            println!("curr_task: {:?}", curr_task);
-            // task2cpus.remove(&curr_task); // freeing space
             unutilized_cpus.insert(task_cpu.id); // added this cpu to unutilized
-
-            // println!("unutilized_cpus: {:?}", unutilized_cpus);
-            curr_cycle = curr_task.cycles;
-
-            // free up the sensors
-            curr_task
-                .args
-                .iter()
-                .for_each(|sensor| sensor_bitmap.set(sensors_to_int[sensor], false));
             task_cpu.task_complete(&curr_task);
-            task_cpu.reset();
-            let Some(codewriter) = cpu_codewriter.get_mut(&task_cpu.id) else {
-                Err("Did not found the codewriter for this cpu")?
-            };
-            // this function written to the code writer
-            codewriter.append(
-                CodeTask::FunctionCall(FunctionCall {
-                    fn_identifier: curr_task.name.clone(),
-                    cycles: curr_task.cycles,
-                    args: curr_task.args.clone(),
-                }),
-                time.into(),
-            );
-
-            // break no more tasks are finishing at this timestamp
-            if scheduled_tasks.peek().is_some() {
-                if scheduled_tasks.peek().unwrap().1.cycles != curr_cycle {
-                    break;
-                }
+            // other OBCs only see the conditions from the time the task finishes
+            for condition in &curr_task.satisfies {
+                let condition = qualified(curr_task.obc_id, condition);
+                cpus.values_mut().for_each(|cpu| cpu.satisfy(condition.clone()));
+            }
+            for port in curr_task.on_port.iter().filter(|port| writes_port(&curr_task, port, port2obc)) {
+                *written.entry(port.clone()).or_default() += 1;
             }
         }
-        time += curr_cycle as i32;
+        // free up the sensors, readers share a sensor so the locks are rebuilt from the running tasks
+        sensor_locks = SensorLocks::new(scheduled_tasks.iter().map(|(_, task)| task), &sensors_to_int);
+        cpus.values_mut().for_each(|cpu| cpu.reset(time, &written));
     }
 
-    for (id, cpu_cw) in cpu_codewriter.iter_mut() {
-        cpu_cw.commit(PathBuf::from(format!("./obc{id}")), time)?;
+    let mut cpu_ids: Vec<_> = cpus.keys().copied().collect();
+    cpu_ids.sort();
+    for id in cpu_ids {
+        let task_cpu = &cpus[&id];
+        let Some(task) = task_cpu.unfinished() else {
+            continue;
+        };
+        return Err(match task_cpu.deadline(task) {
+            Some(deadline) => ScheduleError::DeadlineMiss {
+                obc_id: id,
+                task: task.name.clone(),
+                release: task_cpu.release_time(task),
+                deadline,
+            },
+            None => ScheduleError::NeverRuns { obc_id: id, task: task.name.clone() },
+        });
+    }
+
+    // periodic schedules repeat every hyperperiod
+    let end_time = match hyperperiod {
+        Some(hyperperiod) if time > hyperperiod => {
+            return Err(ScheduleError::HyperperiodOverrun {
+                end: time,
+                hyperperiod,
+            })
+        }
+        Some(hyperperiod) => hyperperiod,
+        None => time,
+    };
+
+    Ok(Schedule { frame: end_time, obcs: slots, warnings })
+}
+
+/// Writes the `entry.cpp` of each OBC of `schedule` to its folder in `obc_dirs`
+pub fn write_entries(schedule: &Schedule, obc_dirs: &HashMap<u32, PathBuf>) -> Result<(), ScheduleError> {
+    for (id, slots) in &schedule.obcs {
+        CodeWriter::from_slots(slots).commit(obc_dirs[id].clone(), schedule.frame)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use confparse::{conf_from_str, Access, Arg, Duration};
+
+    use super::*;
+
+    const HEADER: &str = "IN:\nOUT:\n\nINIT_CONDITIONS:\n\n";
+
+    /// Schedules the tasks of each OBC, given after the header of its tasks.conf
+    fn run(obcs: &[(u32, &str)]) -> Result<Schedule, ScheduleError> {
+        let files: Vec<_> = obcs.iter().map(|(id, tasks)| (*id, format!("{HEADER}{tasks}"))).collect();
+        run_files(&files)
+    }
+
+    /// Schedules each OBC with its tasks.conf
+    fn run_files(obcs: &[(u32, String)]) -> Result<Schedule, ScheduleError> {
+        let topology: HashMap<u32, Conf> = obcs
+            .iter()
+            .map(|(id, text)| (*id, conf_from_str("tasks.conf", text, *id, Duration::DEFAULT_TICK).unwrap()))
+            .collect();
+        let sensors = ["S", "T", "U"]
+            .map(|name| Sensors { name: name.into(), from: "a".into(), to: "b".into() })
+            .to_vec();
+        // each port is written by the OBC with it in `OUT:`
+        let port2obc: HashMap<String, u32> = topology
+            .iter()
+            .flat_map(|(id, conf)| conf.outports.iter().map(|port| (port.to_string(), *id)))
+            .collect();
+        let ports = port2obc.keys().map(|port| Port { name: port.as_str().into(), message: None }).collect();
+        schedule(&topology, &port2obc, &SensorJson { sensors, ports, resources: vec![] })
+    }
+
+    /// Start of each slot of the OBC
+    fn starts(schedule: &Schedule, obc_id: u32) -> Vec<(String, i32)> {
+        schedule.obcs[&obc_id].iter().map(|slot| (slot.task.to_string(), slot.start)).collect()
+    }
+
+    #[test]
+    fn sensors_errors() {
+        let dir = std::env::temp_dir().join(format!("decert_scheduler-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(matches!(read_sensors(dir.join("missing.json")), Err(ScheduleError::ReadSensors(_))));
+        std::fs::write(dir.join("sensors.json"), r#"{"sensors": []}"#).unwrap();
+        assert!(matches!(read_sensors(dir.join("sensors.json")), Err(ScheduleError::ParseSensors(_))));
+    }
+
+    #[test]
+    fn entries_of_a_schedule() {
+        let slot = |task: &str, start, end, sensors: &[&str]| Slot {
+            task: task.into(),
+            start,
+            end,
+            bcet: (end - start) as u16,
+            sensors: sensors.iter().map(|sensor| Arg { sensor: (*sensor).into(), access: Access::Write }).collect(),
+            resources: vec![],
+        };
+        let mut schedule = Schedule { frame: 10, ..Schedule::default() };
+        // idle before, between and after the tasks, and none between tasks which follow each other
+        schedule.obcs.insert(1, vec![slot("a", 1, 3, &["S"]), slot("b", 3, 4, &["T", "S"]), slot("a", 6, 8, &["S"])]);
+        schedule.obcs.insert(2, vec![]);
+        schedule.obcs.insert(3, vec![slot("c", 0, 10, &[])]);
+        let dir = std::env::temp_dir().join(format!("decert_scheduler-{}-entries", std::process::id()));
+        let obc_dirs = schedule.obcs.keys().map(|id| (*id, dir.join(format!("obc{id}")))).collect();
+        write_entries(&schedule, &obc_dirs).unwrap();
+
+        let entry = |id: u32| std::fs::read_to_string(obc_dirs[&id].join("entry.cpp")).unwrap();
+        // the calls of the loop, in the order they run
+        let calls = |id: u32| {
+            let entry = entry(id);
+            let body = &entry[entry.find("while (1) {").unwrap()..];
+            body.lines().map(str::trim).filter(|line| line.ends_with(");")).map(String::from).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            calls(1),
+            [
+                "delay(1);",
+                "runTask(wrapper_a, args_a, 2);",
+                "runTask(wrapper_b, args_b, 1);",
+                "delay(2);",
+                "runTask(wrapper_a, args_a, 2);",
+                "delay(2);",
+            ]
+        );
+        assert_eq!(calls(2), ["delay(10);"]);
+        assert_eq!(calls(3), ["runTask(wrapper_c, args_c, 10);"]);
+
+        // a task which runs twice is defined once, and so is a sensor used by two tasks
+        let entry1 = entry(1);
+        assert_eq!(entry1.matches("void wrapper_a (void** args)").count(), 1);
+        assert_eq!(entry1.matches("S* var_s = new S();").count(), 1);
+        assert!(entry1.contains("void* args_b[] = { (void*) var_t, (void*) var_s };"));
+
+        schedule.frame = 7;
+        let error = write_entries(&schedule, &obc_dirs).unwrap_err();
+        assert!(matches!(error, ScheduleError::FrameOverrun { total_time: 8, end_time: 7 }), "{error}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn periodic_tasks_repeat_over_the_hyperperiod() {
+        let schedule = run(&[(1, "@period: 2\nTask a(S): 1\n"), (2, "@period: 3\nTask b(T): 2\n")]).unwrap();
+        assert_eq!(schedule.frame, 6);
+        assert_eq!(starts(&schedule, 1), [("a".into(), 0), ("a".into(), 2), ("a".into(), 4)]);
+        assert_eq!(starts(&schedule, 2), [("b".into(), 0), ("b".into(), 3)]);
+    }
+
+    #[test]
+    fn deadline_miss() {
+        let error = run(&[(1, "@period: 4\n@deadline: 2\nTask a(S): 3\n")]).unwrap_err();
+        assert!(
+            matches!(error, ScheduleError::DeadlineMiss { obc_id: 1, ref task, release: 0, deadline: 2 } if &**task == "a"),
+            "{error}"
+        );
+        // the deadline defaults to the period
+        let error = run(&[(1, "@period: 2\nTask a(S): 3\n")]).unwrap_err();
+        assert!(matches!(error, ScheduleError::DeadlineMiss { deadline: 2, .. }), "{error}");
+    }
+
+    #[test]
+    fn hyperperiod_overrun() {
+        let error = run(&[(1, "@period: 2\nTask a(S): 1\n"), (2, "Task b(T): 5\n")]).unwrap_err();
+        assert!(matches!(error, ScheduleError::HyperperiodOverrun { end: 5, hyperperiod: 2 }), "{error}");
+    }
+
+    #[test]
+    fn higher_priority_runs_first() {
+        let schedule = run(&[(1, "Task a(S): 1\n\n@priority: 2\nTask b(T): 3\n")]).unwrap();
+        assert_eq!(starts(&schedule, 1), [("b".into(), 0), ("a".into(), 3)]);
+        // without priorities the shorter task goes first
+        let schedule = run(&[(1, "Task a(S): 1\n\nTask b(T): 3\n")]).unwrap();
+        assert_eq!(starts(&schedule, 1), [("a".into(), 0), ("b".into(), 1)]);
+    }
+
+    #[test]
+    fn conditions_of_other_obcs() {
+        let schedule = run_files(&[
+            (1, "IN:\nOUT:\n\nINIT_CONDITIONS: ready\n\nTask a(S): 3\n@satisfies: done\n".to_string()),
+            (2, format!("{HEADER}@requires: obc1.done\nTask b(T): 1\n\n@requires: obc1.ready\nTask c(U): 1\n")),
+        ])
+        .unwrap();
+        // initial conditions hold from the start, others once the task satisfying them finishes
+        assert_eq!(starts(&schedule, 2), [("c".into(), 0), ("b".into(), 3)]);
+
+        // without the prefix the condition is one of the task's own OBC
+        let error = run(&[(1, "Task a(S): 3\n@satisfies: done\n"), (2, "@requires: done\nTask b(T): 1\n")]).unwrap_err();
+        assert!(matches!(error, ScheduleError::Invalid(ref problems) if problems.len() == 1), "{error}");
+    }
+
+    #[test]
+    fn port_readers_wait_for_the_writer() {
+        let sender = "IN:\nOUT: p\n\nINIT_CONDITIONS:\n\n\
+                      Task prep(S): 3\n@satisfies: ready\n\n@requires: ready\n@on_port: p\nTask send(S): 2\n";
+        let reader = "IN: p\nOUT:\n\nINIT_CONDITIONS:\n\n@on_port: p\nTask recv(T): 1\n\nTask other(U): 1\n";
+        let schedule = run_files(&[(1, sender.into()), (2, reader.into())]).unwrap();
+        assert_eq!(starts(&schedule, 1), [("prep".into(), 0), ("send".into(), 3)]);
+        // held until send has finished, while the other task of the OBC runs
+        assert_eq!(starts(&schedule, 2), [("other".into(), 0), ("recv".into(), 5)]);
+
+        // each run of a periodic reader waits for one more write
+        let writer = "IN:\nOUT: p\n\nINIT_CONDITIONS:\n\n@period: 8\n@priority: 1\nTask prep(S): 1\n\n\
+                      @period: 4\n@on_port: p\nTask send(S): 1\n";
+        let reader = "IN: p\nOUT:\n\nINIT_CONDITIONS:\n\n@period: 4\n@on_port: p\nTask recv(T): 1\n";
+        let schedule = run_files(&[(1, writer.into()), (2, reader.into())]).unwrap();
+        assert_eq!(schedule.frame, 8);
+        assert_eq!(starts(&schedule, 1), [("prep".into(), 0), ("send".into(), 1), ("send".into(), 4)]);
+        assert_eq!(starts(&schedule, 2), [("recv".into(), 2), ("recv".into(), 5)]);
+
+        // a task of the writing OBC without `@on_port:` does not release the reader
+        let error = run_files(&[(1, sender.replace("@on_port: p\n", "")), (2, reader.into())]).unwrap_err();
+        let ScheduleError::Invalid(problems) = error else { panic!("{error}") };
+        assert!(matches!(&problems[..], [Problem::NoPortWriter { obc_id: 2, task, port }] if &**task == "recv" && &**port == "p"));
+    }
+
+    #[test]
+    fn syntax_example() {
+        let syntax = include_str!("../../docs/syntax.md");
+        let example = syntax.split("### Example:\n```bash\n").nth(1).and_then(|rest| rest.split("```").next()).unwrap();
+        let topology = HashMap::from([(1, conf_from_str("tasks.conf", example, 1, Duration::DEFAULT_TICK).unwrap())]);
+        let sensors = ["TEMP", "RELAY"]
+            .map(|name| Sensors { name: name.into(), from: "a".into(), to: "b".into() })
+            .to_vec();
+        let ports = ["port1", "port2"].map(|name| Port { name: name.into(), message: None }).to_vec();
+        let schedule = schedule(&topology, &HashMap::new(), &SensorJson { sensors, ports, resources: vec![] }).unwrap();
+        assert_eq!(schedule.frame, 20);
+        assert_eq!(starts(&schedule, 1), [("task1".into(), 0), ("task2".into(), 2), ("task3".into(), 9)]);
+        assert!(schedule.warnings.is_empty());
+    }
+
+    #[test]
+    fn warnings_come_with_the_schedule() {
+        let schedule = run_files(&[(1, "IN:\nOUT:\n\nINIT_CONDITIONS: spare\n\nTask a(S): 1\n".to_string())]).unwrap();
+        assert_eq!(starts(&schedule, 1), [("a".into(), 0)]);
+        assert_eq!(schedule.warnings, [Problem::UnusedInitCondition { obc_id: 1, condition: "spare".into() }]);
+        assert!(run(&[(1, "Task a(S): 1\n")]).unwrap().warnings.is_empty());
     }
-    Ok(sensorjson)
 }
@@ -0,0 +1,225 @@
+use std::{
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+use serde::{
+    de::{self, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+/// Fixed-width type of a field of a port message.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Bool,
+}
+
+impl FieldType {
+    pub fn cpp_type(self) -> &'static str {
+        match self {
+            FieldType::U8 => "uint8_t",
+            FieldType::U16 => "uint16_t",
+            FieldType::U32 => "uint32_t",
+            FieldType::U64 => "uint64_t",
+            FieldType::I8 => "int8_t",
+            FieldType::I16 => "int16_t",
+            FieldType::I32 => "int32_t",
+            FieldType::I64 => "int64_t",
+            FieldType::F32 => "float",
+            FieldType::F64 => "double",
+            FieldType::Bool => "bool",
+        }
+    }
+
+    /// Size in bytes
+    pub fn size(self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::I8 | FieldType::Bool => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 4,
+            FieldType::U64 | FieldType::I64 | FieldType::F64 => 8,
+        }
+    }
+}
+
+impl Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // spelled the way it is written in sensors.json
+        let name = match self {
+            FieldType::U8 => "u8",
+            FieldType::U16 => "u16",
+            FieldType::U32 => "u32",
+            FieldType::U64 => "u64",
+            FieldType::I8 => "i8",
+            FieldType::I16 => "i16",
+            FieldType::I32 => "i32",
+            FieldType::I64 => "i64",
+            FieldType::F32 => "f32",
+            FieldType::F64 => "f64",
+            FieldType::Bool => "bool",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Field {
+    pub name: Arc<str>,
+    #[serde(rename = "type")]
+    pub ty: FieldType,
+}
+
+/// A port of `sensors.json`: a plain name, or a name with the message sent over it.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(from = "PortEntry")]
+pub struct Port {
+    pub name: Arc<str>,
+    /// Fields in the order they are laid out, `None` for untyped ports
+    pub message: Option<Vec<Field>>,
+}
+
+impl Port {
+    /// The fields as `{name: type, ...}`, or `no message` for untyped ports
+    pub fn describe_message(&self) -> String {
+        match &self.message {
+            Some(fields) => format!(
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|f| format!("{}: {}", f.name, f.ty))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            None => "no message".to_string(),
+        }
+    }
+
+    /// Size of the packed message in bytes
+    pub fn message_size(&self) -> Option<usize> {
+        self.message
+            .as_ref()
+            .map(|fields| fields.iter().map(|f| f.ty.size()).sum())
+    }
+}
+
+/// The message of a typed port as written in `sensors.json`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TypedPort {
+    name: Arc<str>,
+    message: Vec<Field>,
+}
+
+// Not `#[serde(untagged)]`, so that errors inside a typed port are reported as they are.
+enum PortEntry {
+    Name(Arc<str>),
+    Typed(TypedPort),
+}
+
+impl<'de> Deserialize<'de> for PortEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PortVisitor;
+
+        impl<'de> Visitor<'de> for PortVisitor {
+            type Value = PortEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a port name or an object with name and message")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<PortEntry, E> {
+                Ok(PortEntry::Name(name.into()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<PortEntry, A::Error> {
+                TypedPort::deserialize(de::value::MapAccessDeserializer::new(map)).map(PortEntry::Typed)
+            }
+        }
+
+        deserializer.deserialize_any(PortVisitor)
+    }
+}
+
+impl From<PortEntry> for Port {
+    fn from(entry: PortEntry) -> Self {
+        match entry {
+            PortEntry::Name(name) => Port {
+                name,
+                message: None,
+            },
+            PortEntry::Typed(TypedPort { name, message }) => Port {
+                name,
+                message: Some(message),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(json: &str) -> Result<Port, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+
+    fn field(name: &str, ty: FieldType) -> Field {
+        Field { name: name.into(), ty }
+    }
+
+    #[test]
+    fn ports_by_name_or_with_message() {
+        assert_eq!(port(r#""raw""#), Ok(Port { name: "raw".into(), message: None }));
+        let typed = port(r#"{"name": "att", "message": [{"name": "angle", "type": "f64"}, {"name": "ok", "type": "bool"}]}"#);
+        let message = vec![field("angle", FieldType::F64), field("ok", FieldType::Bool)];
+        assert_eq!(typed, Ok(Port { name: "att".into(), message: Some(message) }));
+        assert_eq!(port(r#"{"name": "empty", "message": []}"#), Ok(Port { name: "empty".into(), message: Some(vec![]) }));
+    }
+
+    #[test]
+    fn invalid_ports() {
+        let error = |json| port(json).unwrap_err();
+        assert!(error("3").contains("a port name or an object with name and message"));
+        assert!(error(r#"{"name": "p"}"#).contains("missing field `message`"));
+        assert!(error(r#"{"name": "p", "message": [], "rate": 3}"#).contains("unknown field `rate`"));
+        assert!(error(r#"{"name": "p", "message": [{"name": "a", "type": "u8", "unit": "m"}]}"#).contains("unknown field `unit`"));
+        let bad_type = error(r#"{"name": "p", "message": [{"name": "a", "type": "float"}]}"#);
+        assert!(bad_type.contains("unknown variant `float`"), "{bad_type}");
+        assert!(error(r#"{"name": "p", "message": [{"name": "a", "type": "U8"}]}"#).contains("unknown variant `U8`"));
+    }
+
+    #[test]
+    fn size_and_description() {
+        let untyped = Port { name: "raw".into(), message: None };
+        assert_eq!(untyped.message_size(), None);
+        assert_eq!(untyped.describe_message(), "no message");
+
+        let empty = Port { name: "empty".into(), message: Some(vec![]) };
+        assert_eq!(empty.message_size(), Some(0));
+        assert_eq!(empty.describe_message(), "{}");
+
+        // packed: no padding between the fields
+        let message = vec![
+            field("flag", FieldType::Bool),
+            field("count", FieldType::U16),
+            field("time", FieldType::I64),
+            field("t", FieldType::F32),
+            field("b", FieldType::U8),
+        ];
+        let typed = Port { name: "p".into(), message: Some(message) };
+        assert_eq!(typed.message_size(), Some(1 + 2 + 8 + 4 + 1));
+        assert_eq!(typed.describe_message(), "{flag: bool, count: u16, time: i64, t: f32, b: u8}");
+    }
+}
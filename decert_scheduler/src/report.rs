@@ -0,0 +1,154 @@
+use std::{
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+use crate::schedule::{Schedule, Slot};
+
+// The schedule reserves the worst case (WCET) of every task, the report shows
+// the time left over when tasks finish at their best case (BCET) instead.
+
+/// The runs of one task in a frame of an OBC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskSlack {
+    pub task: Arc<str>,
+    pub runs: u32,
+    pub bcet: u16,
+    pub wcet: u16,
+}
+
+impl TaskSlack {
+    /// Ticks left over by one run finishing at BCET
+    pub fn per_run(&self) -> u32 {
+        (self.wcet - self.bcet) as u32
+    }
+}
+
+/// The frame of one OBC, which repeats forever.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameSlack {
+    pub obc_id: u32,
+    /// Length of the frame in ticks
+    pub frame: i32,
+    /// Ticks the tasks run when every task takes its WCET
+    pub busy_wcet: i32,
+    /// Ticks the tasks run when every task takes its BCET
+    pub busy_bcet: i32,
+    /// Tasks in the order of their first run
+    pub tasks: Vec<TaskSlack>,
+}
+
+impl FrameSlack {
+    pub fn new(obc_id: u32, slots: &[Slot], frame: i32) -> Self {
+        let mut tasks: Vec<TaskSlack> = vec![];
+        for slot in slots {
+            match tasks.iter_mut().find(|t| t.task == slot.task) {
+                Some(task) => task.runs += 1,
+                None => tasks.push(TaskSlack {
+                    task: slot.task.clone(),
+                    runs: 1,
+                    bcet: slot.bcet,
+                    wcet: slot.cycles() as u16,
+                }),
+            }
+        }
+        let busy = |length: fn(&TaskSlack) -> u16| -> i32 {
+            tasks.iter().map(|t| t.runs as i32 * length(t) as i32).sum()
+        };
+        FrameSlack {
+            obc_id,
+            frame,
+            busy_wcet: busy(|t| t.wcet),
+            busy_bcet: busy(|t| t.bcet),
+            tasks,
+        }
+    }
+}
+
+/// Slack of every OBC, in the order of OBC ids.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SlackReport(pub Vec<FrameSlack>);
+
+impl SlackReport {
+    pub fn new(schedule: &Schedule) -> Self {
+        SlackReport(
+            schedule
+                .obcs
+                .iter()
+                .map(|(obc_id, slots)| FrameSlack::new(*obc_id, slots, schedule.frame))
+                .collect(),
+        )
+    }
+}
+
+impl Display for SlackReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Slack if tasks run at BCET, in ticks:")?;
+        for frame in &self.0 {
+            writeln!(
+                f,
+                "obc{}: frame {}, busy {} at WCET and {} at BCET, slack {} at WCET and {} at BCET",
+                frame.obc_id,
+                frame.frame,
+                frame.busy_wcet,
+                frame.busy_bcet,
+                frame.frame - frame.busy_wcet,
+                frame.frame - frame.busy_bcet,
+            )?;
+            for task in &frame.tasks {
+                let length = if task.bcet == task.wcet {
+                    task.wcet.to_string()
+                } else {
+                    format!("{}..{}", task.bcet, task.wcet)
+                };
+                writeln!(
+                    f,
+                    "  {}: {} run(s) of {length}, slack {} per run and {} per frame",
+                    task.task,
+                    task.runs,
+                    task.per_run(),
+                    task.per_run() * task.runs,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn slot(task: &str, start: i32, end: i32, bcet: u16) -> Slot {
+        Slot { task: task.into(), start, end, bcet, sensors: vec![], resources: vec![] }
+    }
+
+    #[test]
+    fn slack_at_bcet() {
+        let slots = vec![slot("a", 0, 3, 1), slot("b", 3, 5, 2), slot("a", 5, 8, 1)];
+        let schedule = Schedule { frame: 10, obcs: BTreeMap::from([(1, slots)]), warnings: vec![] };
+        let report = SlackReport::new(&schedule);
+        assert_eq!(
+            report.0,
+            [FrameSlack {
+                obc_id: 1,
+                frame: 10,
+                busy_wcet: 8,
+                busy_bcet: 4,
+                tasks: vec![
+                    TaskSlack { task: "a".into(), runs: 2, bcet: 1, wcet: 3 },
+                    TaskSlack { task: "b".into(), runs: 1, bcet: 2, wcet: 2 },
+                ],
+            }]
+        );
+        assert_eq!(
+            report.to_string(),
+            "Slack if tasks run at BCET, in ticks:\n\
+             obc1: frame 10, busy 8 at WCET and 4 at BCET, slack 2 at WCET and 6 at BCET\n  \
+             a: 2 run(s) of 1..3, slack 2 per run and 4 per frame\n  \
+             b: 1 run(s) of 2, slack 0 per run and 0 per frame\n"
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use confparse::{Access, Arg};
+use serde::{Deserialize, Serialize};
+
+use crate::Problem;
+
+// What `schedule` decides, before any code is generated: when each task of
+// each OBC runs in the frame, and what it holds while it runs. Every OBC runs
+// its slots in order and waits in the gaps between them, then starts over.
+
+/// A run of a task on its OBC.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Slot {
+    pub task: Arc<str>,
+    /// Tick the task starts at, from the start of the frame
+    pub start: i32,
+    /// Tick the task ends at, when it takes its WCET
+    pub end: i32,
+    /// Best case length of the task in ticks
+    pub bcet: u16,
+    /// Sensors passed to the task, in the order of its arguments
+    pub sensors: Vec<Arg>,
+    /// Resources of `@uses:`, held like written sensors
+    pub resources: Vec<Arc<str>>,
+}
+
+impl Slot {
+    /// Length of the slot in ticks, the WCET of the task
+    pub fn cycles(&self) -> i32 {
+        self.end - self.start
+    }
+
+    /// Sensors and resources the task holds, with how
+    pub fn held(&self) -> impl Iterator<Item = (&Arc<str>, Access)> {
+        self.sensors
+            .iter()
+            .map(|arg| (&arg.sensor, arg.access))
+            .chain(self.resources.iter().map(|resource| (resource, Access::Write)))
+    }
+}
+
+/// The static schedule of every OBC of a project.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    /// Length of the frame in ticks, the hyperperiod when any task is periodic
+    pub frame: i32,
+    /// Slots of each OBC in the order they start, by OBC id
+    pub obcs: BTreeMap<u32, Vec<Slot>>,
+    /// Problems of the project which did not keep it from being scheduled
+    #[serde(skip)]
+    pub warnings: Vec<Problem>,
+}
+
+impl Schedule {
+    /// Ticks of the frame where the OBC runs no task, each as its start and end
+    pub fn idle(&self, obc_id: u32) -> Vec<(i32, i32)> {
+        let mut idle = vec![];
+        let mut time = 0;
+        for slot in self.obcs.get(&obc_id).into_iter().flatten() {
+            if slot.start > time {
+                idle.push((time, slot.start));
+            }
+            time = slot.end;
+        }
+        if self.frame > time {
+            idle.push((time, self.frame));
+        }
+        idle
+    }
+}
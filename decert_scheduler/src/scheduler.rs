@@ -1,129 +1,148 @@
-use std::{collections::HashMap, fmt::Debug, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 
-use confparse::Task;
+use confparse::{Access, Task};
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
-pub struct BitMap {
-    map: u128,
-}
+use crate::{bitset::BitSet, select::heaviest_independent_set};
 
-impl Debug for BitMap {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let hm = self.iter().fold(HashMap::new(), |mut hm, i| {
-            hm.insert(i, self.get(i));
-            hm
-        });
-        write!(f, "BitMap {{ {:?} }} val: {:?}", hm, self.map)
-    }
+/// Sensors held by running tasks: any number of readers or a single writer.
+/// Shared resources of `@uses:` are held like written sensors.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct SensorLocks {
+    read: BitSet,
+    write: BitSet,
 }
 
-impl BitMap {
-    pub fn new() -> Self {
-        BitMap { map: 0 }
-    }
-    
-    pub fn get(&self, index: u8) -> bool {
-        self.map & (1 << index) == (1 << index)
+impl SensorLocks {
+    /// Locks held by all the given tasks
+    pub fn new<'a>(tasks: impl IntoIterator<Item = &'a Task>, sensors_to_int: &HashMap<Arc<str>, usize>) -> Self {
+        let mut locks = SensorLocks::default();
+        for task in tasks {
+            locks.lock(task, sensors_to_int);
+        }
+        locks
     }
 
-    pub fn set(&mut self, index: u8, val: bool) {
-        // println!("index: {:?}, val: {:?}", index, val);
-        self.map &= !(1 << index);
-        self.map |= (val as u128) << index;
+    pub fn can_lock(&self, task: &Task, sensors_to_int: &HashMap<Arc<str>, usize>) -> bool {
+        let wanted = SensorLocks::new([task], sensors_to_int);
+        !self.write.intersects(&wanted.read)
+            && !self.write.intersects(&wanted.write)
+            && !self.read.intersects(&wanted.write)
     }
 
-    pub fn conflict(&self, other: &Self) -> bool {
-        self.map & other.map == 1
+    pub fn lock(&mut self, task: &Task, sensors_to_int: &HashMap<Arc<str>, usize>) {
+        for (name, access) in locks(task) {
+            let Some(i) = sensors_to_int.get(name) else {
+                continue;
+            };
+            match access {
+                Access::Read => self.read.insert(*i),
+                Access::Write => self.write.insert(*i),
+            }
+        }
     }
+}
 
-    pub fn conflict_u64(&self, other_value: u128) -> bool {
-        self.map & other_value == 1
-    }
+/// The sensors of the task with their access, followed by its resources
+fn locks(task: &Task) -> impl Iterator<Item = (&Arc<str>, Access)> {
+    task.args
+        .iter()
+        .map(|arg| (&arg.sensor, arg.access))
+        .chain(task.uses.iter().map(|resource| (resource, Access::Write)))
+}
 
-    pub fn combine(&self, other: &Self) -> Self {
-        BitMap {
-            map: self.map & other.map,
+/// Conflict free set of `tasks` which can start alongside `sensors_used` with the
+/// largest total weight. Tasks are given with their weights, ties go to earlier tasks.
+/// Also tells whether no other set weighs more, see [`Selection::optimal`].
+///
+/// [`Selection::optimal`]: crate::select::Selection::optimal
+pub fn task_schedule(
+    tasks: &[(Task, u32)],
+    sensors_to_int: &HashMap<Arc<str>, usize>,
+    sensors_used: &SensorLocks,
+) -> (Vec<Task>, bool) {
+    let runnable: Vec<&(Task, u32)> = tasks
+        .iter()
+        .filter(|(task, _)| sensors_used.can_lock(task, sensors_to_int))
+        .collect();
+    let weights: Vec<u64> = runnable.iter().map(|(_, weight)| *weight as u64).collect();
+
+    // the writers of a sensor conflict with each other and with every reader of it
+    let mut access: BTreeMap<&Arc<str>, (Vec<usize>, Vec<usize>)> = BTreeMap::new();
+    for (i, (task, _)) in runnable.iter().enumerate() {
+        for (name, mode) in locks(task) {
+            let (writers, readers) = access.entry(name).or_default();
+            let list = match mode {
+                Access::Read => readers,
+                Access::Write => writers,
+            };
+            if list.last() != Some(&i) {
+                list.push(i);
+            }
         }
     }
-
-    pub fn combine_u64(&self, other_value: u128) -> Self {
-        BitMap {
-            map: self.map & other_value,
+    let mut groups = vec![];
+    for (writers, readers) in access.into_values().filter(|(writers, _)| !writers.is_empty()) {
+        let readers: Vec<usize> = readers.into_iter().filter(|reader| !writers.contains(reader)).collect();
+        if readers.is_empty() {
+            groups.push(writers);
+        } else {
+            groups.extend(readers.iter().map(|reader| [writers.as_slice(), &[*reader]].concat()));
         }
     }
 
-    pub fn is_filled(&self, index: u8) -> bool {
-        self.map & ((1 << (index + 1)) - 1) == ((1 << (index + 1)) - 1)
-    }
-
-    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
-        (0..128).filter(move |&i| self.get(i))
-    }
-
-    pub fn get_map(&self) -> u128 {
-        self.map
-    }
+    let selection = heaviest_independent_set(&weights, &groups);
+    let chosen = selection.tasks.into_iter().map(|i| runnable[i].0.clone()).collect();
+    (chosen, selection.optimal)
 }
 
-pub fn task_schedule(
-    tasks: &Vec<(Task, u8)>,
-    sensors_to_int: &HashMap<Arc<str>, u8>,
-    sensors_used: BitMap,
-) -> Vec<Task> {
-    task_schedule_rec(tasks, 0, sensors_to_int, sensors_used)
-        .iter()
-        .map(|i|{
-            tasks[i as usize].0.clone()  
-        })
-        .collect()
-}
+#[cfg(test)]
+mod tests {
+    use confparse::{conf_from_str, Duration};
+
+    use super::*;
 
-fn task_schedule_rec(
-    tasks: &Vec<(Task, u8)>,
-    index: u8,
-    sensors_to_int: &HashMap<Arc<str>, u8>,
-    sensors_used: BitMap,
-) -> BitMap {
-    println!("index: {:?}, sensors_used: {:?}, sensors_to_int: {:?}", index, sensors_used, sensors_to_int);
-    if sensors_used.is_filled(sensors_to_int.len() as u8) {
-        return BitMap { map: 0 };
+    /// Tasks of a tasks.conf on obc1, with the index of each sensor and resource
+    fn tasks(text: &str) -> (Vec<Task>, HashMap<Arc<str>, usize>) {
+        let conf = conf_from_str("tasks.conf", &format!("IN:\nOUT:\n\nINIT_CONDITIONS:\n\n{text}"), 1, Duration::DEFAULT_TICK)
+            .unwrap();
+        let sensors = ["S", "T", "BUS"].into_iter().enumerate().map(|(i, name)| (name.into(), i)).collect();
+        (conf.tasks, sensors)
     }
-    let Some((task, _)) = tasks.get(index as usize) else {
-        return BitMap { map: 0 };
-    };
-    // to check if the task is runnable
-    if task.args.iter().any(|f| sensors_used.get(sensors_to_int[f])) {
-        return BitMap { map: 0 };
+
+    #[test]
+    fn readers_share_writers_do_not() {
+        let (tasks, sensors) = tasks("Task r1(S:r): 1\n\nTask r2(S:r): 1\n\nTask w(S:w): 1\n\nTask d(S): 1\n\n@uses: BUS\nTask u(T:r): 1\n");
+        let [r1, r2, w, d, u] = &tasks[..] else { unreachable!() };
+        let reading = SensorLocks::new([r1], &sensors);
+        assert!(reading.can_lock(r2, &sensors));
+        assert!(!reading.can_lock(w, &sensors));
+        // write access is the default
+        assert!(!reading.can_lock(d, &sensors));
+        let writing = SensorLocks::new([w], &sensors);
+        assert!(!writing.can_lock(r1, &sensors));
+        assert!(writing.can_lock(u, &sensors));
+        // resources are held like written sensors
+        assert!(!SensorLocks::new([u], &sensors).can_lock(u, &sensors));
     }
-    let mut s = sensors_used.clone();
-    task.args.iter().for_each(|sensor| {
-        let Some(i) = sensors_to_int.get(sensor) else {
-            return;
+
+    #[test]
+    fn readers_start_together() {
+        let (tasks, sensors) = tasks("Task r1(S:r): 1\n\nTask r2(S:r): 1\n\nTask w(S): 1\n");
+        let weighted = |weights: [u32; 3]| tasks.iter().cloned().zip(weights).collect::<Vec<_>>();
+        let names = |(chosen, optimal): (Vec<Task>, bool)| {
+            assert!(optimal);
+            chosen.iter().map(|task| task.name.to_string()).collect::<Vec<_>>()
         };
-        s.set(*i, true);
-    });
-    println!("task: {:?}", task);
-    println!("s: {:?}", s);
-
-    let mut task_marked = task_schedule_rec(tasks, index + 1, sensors_to_int, s);
-    task_marked.set(index, true);
-    println!("task_marked_before: {:?}", task_marked);
-
-    let s = sensors_used.clone();
-    let task_unmarked = task_schedule_rec(tasks, index + 1, sensors_to_int, s);
-
-    let tml = task_marked.iter().fold(0, |acc, x| {
-        acc + tasks.get(x as usize).map(|f| f.1).unwrap_or(0)
-    });
-    let tuml = task_unmarked.iter().fold(0, |acc, x| {
-        acc + tasks.get(x as usize).map(|f| f.1).unwrap_or(0)
-    });
-
-    println!("task_marked: {:?}", task_marked);
-    println!("task_unmarked: {:?}", task_unmarked);
-    if tml >= tuml {
-        task_marked
-    } else {
-        task_unmarked
+
+        let none = SensorLocks::default();
+        assert_eq!(names(task_schedule(&weighted([1, 1, 1]), &sensors, &none)), ["r1", "r2"]);
+        // the writer wins when it outweighs the readers together
+        assert_eq!(names(task_schedule(&weighted([1, 1, 3]), &sensors, &none)), ["w"]);
+        // tasks which conflict with running ones wait
+        let running = SensorLocks::new([&tasks[2]], &sensors);
+        assert!(names(task_schedule(&weighted([1, 1, 1]), &sensors, &running)).is_empty());
     }
 }
@@ -0,0 +1,455 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use crate::bitset::BitSet;
+
+// Choosing which runnable tasks start together is a maximum weight independent
+// set problem on the conflict graph of the tasks: two tasks conflict when they
+// need the same sensor or resource and one of them writes it. The graph is given
+// as groups of tasks which all conflict with each other, like the writers of a
+// sensor and one of its readers. It is solved by branch and bound: tasks which
+// a heaviest set takes or can do without are settled first, the rest is split
+// into components of tasks which share sensors, and the groups bound the weight
+// left. What is found of a set of tasks is remembered for the next searches.
+//
+// The heaviest weight is found first, counting down from the bound of all the
+// tasks, then the tasks are walked in order to find the heaviest set with the
+// earliest tasks. Searches are limited by the work they do, a graph too large to
+// search in time gets the greedy choice and is told it may not be the heaviest.
+
+/// Work of the searches before they give up, each search and each round of the
+/// bound counting the tasks of its set
+const WORK: usize = 200_000_000;
+/// Rounds of the bound before it settles for the lowest it found
+const ROUNDS: usize = 300;
+/// Rounds without a lower bound before the steps are halved
+const STALE: usize = 10;
+/// Margin of the bound over rounding errors
+const EPSILON: f64 = 1e-6;
+
+struct Solver<'a> {
+    weights: &'a [u64],
+    /// The tasks each task conflicts with
+    conflicts: Vec<BitSet>,
+    /// The groups of each task
+    task_groups: Vec<Vec<usize>>,
+    /// Share of the weight of its tasks each group takes in the bound, kept from
+    /// one bound to the next as sets of tasks close to each other have close shares
+    shares: Vec<f64>,
+    memo: HashMap<BitSet, Known>,
+    /// Work left, once there is none the search gives up
+    work: usize,
+}
+
+/// What a search found out about the heaviest weight of a set of tasks
+#[derive(Clone, Debug)]
+enum Known {
+    Best(u64, BitSet),
+    /// The heaviest weighs less
+    Below(u64),
+}
+
+impl Solver<'_> {
+    /// The tasks of `set` connected to `start` through conflicts
+    fn component(&self, set: &BitSet, start: usize) -> BitSet {
+        let mut component = BitSet::new();
+        component.insert(start);
+        let mut stack = vec![start];
+        while let Some(task) = stack.pop() {
+            for next in self.conflicts[task].intersection(set).difference(&component).iter() {
+                component.insert(next);
+                stack.push(next);
+            }
+        }
+        component
+    }
+
+    fn weight(&self, set: &BitSet) -> u64 {
+        set.iter().map(|task| self.weights[task]).sum()
+    }
+
+    /// Upper bound of the weight of the tasks of `set` without conflicts, given up
+    /// on once it is below `floor`. At most one task of a group can be chosen, so
+    /// if every group takes a share of the weight of each of its tasks, the shares
+    /// and what is left of the weight of every task add up to a bound. Shares are
+    /// raised in groups with more than one task left with weight and lowered in
+    /// groups with none, each round of it lowering the bound towards the one of
+    /// the linear relaxation.
+    fn bound(&mut self, set: &BitSet, floor: u64) -> f64 {
+        let tasks: Vec<usize> = set.iter().collect();
+        let mut groups: Vec<usize> = tasks.iter().flat_map(|task| self.task_groups[*task].iter().copied()).collect();
+        groups.sort_unstable();
+        groups.dedup();
+
+        // tasks of each group left with weight
+        let mut left = vec![0i64; self.shares.len()];
+        let mut lowest = f64::INFINITY;
+        let mut scale = 2.0;
+        let mut stale = 0;
+        for _ in 0..ROUNDS {
+            self.work = self.work.saturating_sub(tasks.len());
+            let mut bound: f64 = groups.iter().map(|group| self.shares[*group]).sum();
+            for &group in &groups {
+                left[group] = 0;
+            }
+            for &task in &tasks {
+                let shares: f64 = self.task_groups[task].iter().map(|group| self.shares[*group]).sum();
+                let weight = self.weights[task] as f64 - shares;
+                if weight > 0.0 {
+                    bound += weight;
+                    for &group in &self.task_groups[task] {
+                        left[group] += 1;
+                    }
+                }
+            }
+            if bound < lowest {
+                lowest = bound;
+                stale = 0;
+                if lowest + EPSILON < floor as f64 {
+                    break;
+                }
+            } else {
+                stale += 1;
+                if stale == STALE {
+                    scale /= 2.0;
+                    stale = 0;
+                }
+            }
+            // each group lowers the bound by one for every task left with weight but one
+            let slopes: Vec<i64> = groups.iter().map(|group| left[*group] - 1).collect();
+            let norm: i64 = slopes.iter().map(|slope| slope * slope).sum();
+            if norm == 0 || scale < 0.01 {
+                break;
+            }
+            let step = scale * (bound - floor as f64 + 1.0) / norm as f64;
+            for (group, slope) in groups.iter().zip(slopes) {
+                self.shares[*group] = (self.shares[*group] + step * slope as f64).max(0.0);
+            }
+        }
+        lowest
+    }
+
+    /// Removes the tasks of `set` a heaviest set can do without: they conflict
+    /// with a task weighing at least as much whose other conflicts they have too
+    fn remove_dominated(&self, set: &mut BitSet) -> bool {
+        let mut removed = false;
+        for task in set.iter().collect::<Vec<_>>() {
+            let conflicts = self.conflicts[task].intersection(set);
+            let dominated = conflicts.iter().any(|other| {
+                self.weights[other] >= self.weights[task] && {
+                    let mut others = self.conflicts[other].intersection(set);
+                    others.remove(task);
+                    others.difference(&conflicts).first().is_none()
+                }
+            });
+            if dominated {
+                set.remove(task);
+                removed = true;
+            }
+        }
+        removed
+    }
+
+    /// Heaviest set of tasks of `set` without conflicts with its weight, if it
+    /// weighs at least `floor`. What is found is remembered for the next searches.
+    fn solve(&mut self, set: &BitSet, floor: u64) -> Option<(u64, BitSet)> {
+        match self.memo.get(set) {
+            Some(Known::Best(weight, best)) => return (*weight >= floor).then(|| (*weight, best.clone())),
+            Some(Known::Below(weight)) if floor >= *weight => return None,
+            _ => {}
+        }
+        let found = self.search(set, floor);
+        if self.work == 0 {
+            // what an unfinished search found is wrong
+            return None;
+        }
+        let known = match &found {
+            Some((weight, best)) => Known::Best(*weight, best.clone()),
+            None => Known::Below(floor),
+        };
+        self.memo.insert(set.clone(), known);
+        found
+    }
+
+    fn search(&mut self, set: &BitSet, floor: u64) -> Option<(u64, BitSet)> {
+        if self.work == 0 {
+            return None;
+        }
+        self.work = self.work.saturating_sub(set.len().max(1));
+
+        // a task weighing at least as much as the tasks it conflicts with is in a heaviest set
+        let mut set = set.clone();
+        let mut taken = BitSet::new();
+        loop {
+            let mut settled = false;
+            for task in set.iter().collect::<Vec<_>>() {
+                let conflicts = self.conflicts[task].intersection(&set);
+                if set.contains(task) && self.weights[task] >= self.weight(&conflicts) {
+                    taken.insert(task);
+                    set = set.difference(&conflicts);
+                    set.remove(task);
+                    settled = true;
+                }
+            }
+            if !(self.remove_dominated(&mut set) || settled) {
+                break;
+            }
+        }
+        let taken_weight = self.weight(&taken);
+        let floor = floor.saturating_sub(taken_weight);
+        let add_taken = |(weight, best): (u64, BitSet)| (weight + taken_weight, best.union(&taken));
+
+        let Some(first) = set.first() else {
+            return (floor == 0).then(|| add_taken((0, set)));
+        };
+
+        let component = self.component(&set, first);
+        if component != set {
+            // the components are solved apart, the largest making up for the others
+            let mut components = vec![component];
+            let mut rest = set.difference(&components[0]);
+            while let Some(start) = rest.first() {
+                let component = self.component(&rest, start);
+                rest = rest.difference(&component);
+                components.push(component);
+            }
+            components.sort_by_key(|component| Reverse(component.len()));
+            let mut weight = 0;
+            let mut best = BitSet::new();
+            for component in &components[1..] {
+                let (component_weight, component_best) = self.solve(component, 0)?;
+                weight += component_weight;
+                best = best.union(&component_best);
+            }
+            let (largest_weight, largest_best) = self.solve(&components[0], floor.saturating_sub(weight))?;
+            return Some(add_taken((weight + largest_weight, best.union(&largest_best))));
+        }
+
+        if floor > 0 && self.bound(&set, floor) + EPSILON < floor as f64 {
+            return None;
+        }
+
+        // the task with the most conflicts splits the set the most
+        let task = set
+            .iter()
+            .max_by_key(|task| self.conflicts[*task].intersection_len(&set))
+            .unwrap_or(first);
+        let mut rest = set.clone();
+        rest.remove(task);
+
+        let weight = self.weights[task];
+        let with = self
+            .solve(&rest.difference(&self.conflicts[task]), floor.saturating_sub(weight))
+            .map(|(with_weight, mut with)| {
+                with.insert(task);
+                (with_weight + weight, with)
+            });
+        // without the task the set has to weigh more
+        let floor = with.as_ref().map_or(floor, |(with_weight, _)| with_weight + 1);
+        match self.solve(&rest, floor) {
+            Some(without) => Some(add_taken(without)),
+            None => with.map(add_taken),
+        }
+    }
+}
+
+/// Tasks chosen by [`heaviest_independent_set`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    /// Indices of the tasks, in increasing order
+    pub tasks: Vec<usize>,
+    /// Whether no other choice weighs more. Only a graph too large to search in
+    /// time gets a choice which may not be the heaviest.
+    pub optimal: bool,
+}
+
+/// Tasks with the largest total weight such that no two of them are in the same
+/// group. When several choices weigh the same, the one with the earliest tasks wins.
+pub fn heaviest_independent_set(weights: &[u64], groups: &[Vec<usize>]) -> Selection {
+    select(weights, groups, WORK)
+}
+
+/// [`heaviest_independent_set`] with the work the searches can do
+fn select(weights: &[u64], groups: &[Vec<usize>], work: usize) -> Selection {
+    let len = weights.len();
+    let mut conflicts = vec![BitSet::new(); len];
+    let mut task_groups = vec![vec![]; len];
+    for (i, group) in groups.iter().enumerate() {
+        for &task in group {
+            task_groups[task].push(i);
+            for &other in group {
+                if other != task {
+                    conflicts[task].insert(other);
+                }
+            }
+        }
+    }
+    let mut solver = Solver {
+        weights,
+        conflicts,
+        task_groups,
+        shares: vec![0.0; groups.len()],
+        memo: HashMap::new(),
+        work,
+    };
+
+    // tasks taken greedily, the heaviest for the tasks they shut out, weigh the least the heaviest can
+    let all = BitSet::full(len);
+    let mut greedy = BitSet::new();
+    let mut free = all.clone();
+    while let Some(task) = free.iter().max_by_key(|task| {
+        (weights[*task] * 1024 / (solver.conflicts[*task].intersection_len(&free) as u64 + 1), Reverse(*task))
+    }) {
+        greedy.insert(task);
+        free = free.difference(&solver.conflicts[task]);
+        free.remove(task);
+    }
+    let floor = solver.weight(&greedy);
+
+    // the bound is usually the heaviest weight, a search which has to reach it prunes the most
+    let mut target = floor.max((solver.bound(&all, floor) + EPSILON) as u64);
+    let (mut target, mut best) = loop {
+        if let Some(found) = solver.solve(&all, target) {
+            break found;
+        }
+        if solver.work == 0 || target == floor {
+            return Selection { tasks: greedy.iter().collect(), optimal: false };
+        }
+        target -= 1;
+    };
+
+    // each task in turn is chosen if the tasks left can still make up the heaviest weight with it
+    let mut rest = all;
+    let mut chosen = BitSet::new();
+    for (task, weight) in weights.iter().enumerate() {
+        if !rest.contains(task) {
+            continue;
+        }
+        let mut with = rest.difference(&solver.conflicts[task]);
+        with.remove(task);
+        if !best.contains(task) {
+            let Some((_, with_best)) = solver.solve(&with, target - weight.min(&target)) else {
+                if solver.work == 0 {
+                    // the heaviest set found so far, with its earliest tasks where they were found
+                    break;
+                }
+                rest.remove(task);
+                continue;
+            };
+            best = with_best;
+            best.insert(task);
+        }
+        chosen.insert(task);
+        target -= weight;
+        rest = with;
+    }
+    Selection { tasks: chosen.union(&best.intersection(&rest)).iter().collect(), optimal: true }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// Xorshift, random enough for graphs
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    /// Groups of the tasks using each sensor like [`task_schedule`] makes them: the
+    /// writers of a sensor with each of its readers. Each task uses two of `sensors`
+    /// and writes each with a chance of one in `write`.
+    ///
+    /// [`task_schedule`]: crate::scheduler::task_schedule
+    fn graph(rng: &mut Rng, tasks: usize, sensors: u64, write: u64) -> (Vec<u64>, Vec<Vec<usize>>) {
+        let weights = (0..tasks).map(|_| rng.below(10) + 1).collect();
+        let mut access = vec![(vec![], vec![]); sensors as usize];
+        for task in 0..tasks {
+            for _ in 0..2 {
+                let (writers, readers) = &mut access[rng.below(sensors) as usize];
+                let list = if rng.below(write) == 0 { writers } else { readers };
+                if list.last() != Some(&task) {
+                    list.push(task);
+                }
+            }
+        }
+        let mut groups = vec![];
+        for (writers, readers) in access.into_iter().filter(|(writers, _)| !writers.is_empty()) {
+            let readers: Vec<usize> = readers.into_iter().filter(|reader| !writers.contains(reader)).collect();
+            if readers.is_empty() {
+                groups.push(writers);
+            } else {
+                groups.extend(readers.iter().map(|reader| [writers.as_slice(), &[*reader]].concat()));
+            }
+        }
+        (weights, groups)
+    }
+
+    fn independent(set: &[usize], groups: &[Vec<usize>]) -> bool {
+        groups.iter().all(|group| group.iter().filter(|task| set.contains(task)).count() <= 1)
+    }
+
+    /// Every set of tasks, the heaviest with the earliest tasks first
+    fn brute_force(weights: &[u64], groups: &[Vec<usize>]) -> Vec<usize> {
+        let mut best: (u64, Vec<usize>) = (0, vec![]);
+        // counting down from all tasks meets sets with earlier tasks first
+        for mask in (0..1u32 << weights.len()).rev().map(|mask| mask.reverse_bits() >> (32 - weights.len())) {
+            let set: Vec<usize> = (0..weights.len()).filter(|task| mask >> task & 1 == 1).collect();
+            let weight = set.iter().map(|task| weights[*task]).sum();
+            if weight > best.0 && independent(&set, groups) {
+                best = (weight, set);
+            }
+        }
+        best.1
+    }
+
+    #[test]
+    fn same_as_brute_force() {
+        let mut rng = Rng(12345);
+        for _ in 0..2000 {
+            let tasks = rng.below(12) as usize + 1;
+            let sensors = rng.below(8) + 1;
+            let write = rng.below(3) + 1;
+            let (weights, groups) = graph(&mut rng, tasks, sensors, write);
+            let selection = heaviest_independent_set(&weights, &groups);
+            assert!(selection.optimal);
+            assert_eq!(selection.tasks, brute_force(&weights, &groups), "{weights:?} {groups:?}");
+        }
+    }
+
+    #[test]
+    fn hundreds_of_tasks_in_time() {
+        let mut rng = Rng(67890);
+        // unoptimized builds take about ten times as long
+        let limit = Duration::from_millis(if cfg!(debug_assertions) { 5000 } else { 500 });
+        // the heaviest weights, checked apart: where every task writes its sensors they are
+        // those of the heaviest matchings of the sensors, the others reach the linear relaxation
+        let graphs = [(300, 300, 1, 797), (300, 300, 2, 944), (200, 60, 1, 245), (200, 60, 2, 376), (600, 200, 2, 1170)];
+        for (tasks, sensors, write, heaviest) in graphs {
+            let (weights, groups) = graph(&mut rng, tasks, sensors, write);
+            let start = Instant::now();
+            let selection = heaviest_independent_set(&weights, &groups);
+            assert!(start.elapsed() < limit, "{tasks} tasks took {:?}", start.elapsed());
+            assert!(selection.optimal);
+            assert!(independent(&selection.tasks, &groups));
+            assert_eq!(selection.tasks.iter().map(|task| weights[*task]).sum::<u64>(), heaviest);
+        }
+    }
+
+    #[test]
+    fn out_of_work() {
+        let (weights, groups) = graph(&mut Rng(67890), 300, 300, 1);
+        let selection = select(&weights, &groups, 1000);
+        assert!(!selection.optimal);
+        // the greedy choice, which is lighter than the heaviest of 797
+        assert!(independent(&selection.tasks, &groups));
+        assert_eq!(selection.tasks.iter().map(|task| weights[*task]).sum::<u64>(), 791);
+    }
+}
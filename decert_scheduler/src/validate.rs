@@ -0,0 +1,362 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+use confparse::{qualified_condition, Conf};
+
+use crate::{
+    deadlock::{deadlocks, Condition, Wait},
+    qualified, SensorJson,
+};
+
+// Checks of the whole project which are done before scheduling, so that mistakes
+// are reported together instead of one at a time, deep in the scheduler.
+
+const CPP_KEYWORDS: &[&str] = &[
+    "alignas", "alignof", "and", "and_eq", "asm", "auto", "bitand", "bitor", "bool", "break",
+    "case", "catch", "char", "char8_t", "char16_t", "char32_t", "class", "compl", "concept",
+    "const", "consteval", "constexpr", "constinit", "const_cast", "continue", "co_await",
+    "co_return", "co_yield", "decltype", "default", "delete", "do", "double", "dynamic_cast",
+    "else", "enum", "explicit", "export", "extern", "false", "float", "for", "friend", "goto",
+    "if", "inline", "int", "long", "mutable", "namespace", "new", "noexcept", "not", "not_eq",
+    "nullptr", "operator", "or", "or_eq", "private", "protected", "public", "register",
+    "reinterpret_cast", "requires", "return", "short", "signed", "sizeof", "static",
+    "static_assert", "static_cast", "struct", "switch", "template", "this", "thread_local",
+    "throw", "true", "try", "typedef", "typeid", "typename", "union", "unsigned", "using",
+    "virtual", "void", "volatile", "wchar_t", "while", "xor", "xor_eq",
+];
+
+/// Whether `name` can be used as a name in the generated C++: an identifier which is not a keyword.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !CPP_KEYWORDS.contains(&name)
+}
+
+/// A mistake in the project found by [`validate`], or a choice of [`schedule`] to warn about.
+///
+/// [`schedule`]: crate::schedule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// A task uses a sensor which is not listed in `sensors.json`
+    UndefinedSensor { obc_id: u32, task: Arc<str>, sensor: Arc<str> },
+    /// A task uses a resource which is not listed in `sensors.json`
+    UndefinedResource { obc_id: u32, task: Arc<str>, resource: Arc<str> },
+    /// A resource of `sensors.json` has the name of a sensor or of another resource
+    DuplicateResource(Arc<str>),
+    /// An OBC reads or writes a port which is not listed in `sensors.json`
+    UndefinedPort { obc_id: u32, port: Arc<str> },
+    /// A task requires a condition of an OBC which is not part of the project
+    UndefinedObc { obc_id: u32, task: Arc<str>, condition: Arc<str> },
+    /// No task of the OBC satisfies a condition a task requires, and it is not initially set
+    UnsatisfiableRequirement { obc_id: u32, task: Arc<str>, condition: Arc<str> },
+    /// Tasks which never run as each requires a condition the next one satisfies, or
+    /// waits on a port the next one writes, the last one from the first. `fix` is a
+    /// condition which lets them all run when added to `INIT_CONDITIONS:`.
+    DependencyCycle { cycle: Vec<(u32, Arc<str>, Wait)>, fix: Option<Condition> },
+    /// A task requires a condition which only tasks that never run satisfy
+    UnreachableTask { obc_id: u32, task: Arc<str>, condition: Arc<str> },
+    /// A task waits on a port which only tasks that never run write
+    UnwrittenPort { obc_id: u32, task: Arc<str>, port: Arc<str> },
+    /// A condition of `INIT_CONDITIONS:` which no task requires
+    UnusedInitCondition { obc_id: u32, condition: Arc<str> },
+    /// A task waits on a port with `@on_port:` which its OBC neither reads nor writes
+    UndeclaredTriggerPort { obc_id: u32, task: Arc<str>, port: Arc<str> },
+    /// A task waits on a port which no task of the writing OBC declares with `@on_port:`
+    NoPortWriter { obc_id: u32, task: Arc<str>, port: Arc<str> },
+    /// Two tasks or templates of an OBC share a name
+    DuplicateTaskName { obc_id: u32, task: Arc<str> },
+    /// A name which ends up in the generated C++ is not an identifier or is a C++ keyword.
+    /// `obc_id` is `None` for names from `sensors.json`.
+    InvalidIdentifier { obc_id: Option<u32>, kind: &'static str, name: Arc<str> },
+    /// The tasks started at `time` may not be the heaviest choice, as the search for it
+    /// ran out of work. Found while scheduling.
+    UnprovenChoice { time: i32 },
+}
+
+impl Problem {
+    /// Warnings are reported but do not keep the project from being scheduled
+    pub fn is_warning(&self) -> bool {
+        matches!(self, Problem::UnusedInitCondition { .. } | Problem::UnprovenChoice { .. })
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::UndefinedSensor { obc_id, task, sensor } => write!(
+                f,
+                "obc{obc_id}: sensor {sensor} used by task '{task}' is not defined in sensors.json"
+            ),
+            Problem::UndefinedResource { obc_id, task, resource } => write!(
+                f,
+                "obc{obc_id}: resource {resource} used by task '{task}' is not defined in sensors.json"
+            ),
+            Problem::DuplicateResource(resource) => {
+                write!(f, "resource {resource} is listed more than once or has the name of a sensor in sensors.json")
+            }
+            Problem::UndefinedPort { obc_id, port } => {
+                write!(f, "obc{obc_id}: port {port} is not defined in sensors.json")
+            }
+            Problem::UndefinedObc { obc_id, task, condition } => write!(
+                f,
+                "obc{obc_id}: task '{task}' requires '{condition}' of an OBC which is not in the project"
+            ),
+            Problem::UnsatisfiableRequirement { obc_id, task, condition } => write!(
+                f,
+                "obc{obc_id}: task '{task}' requires '{condition}', which no task satisfies and is not in INIT_CONDITIONS"
+            ),
+            Problem::DependencyCycle { cycle, fix } => {
+                // names of other OBCs than the first task's are given with their OBC
+                let first = cycle.first().map_or(0, |(obc_id, _, _)| *obc_id);
+                let name = |obc_id: u32, name: &str| {
+                    if obc_id == first {
+                        format!("'{name}'")
+                    } else {
+                        format!("'obc{obc_id}.{name}'")
+                    }
+                };
+                write!(f, "obc{first}: tasks never run as they wait on each other: ")?;
+                for (obc_id, task, wait) in cycle {
+                    match wait {
+                        Wait::Condition((condition_obc, condition)) => {
+                            write!(f, "{} → {} → ", name(*obc_id, task), name(*condition_obc, condition))?
+                        }
+                        Wait::Port(port) => write!(f, "{} → port {port} → ", name(*obc_id, task))?,
+                    }
+                }
+                if let Some((obc_id, task, _)) = cycle.first() {
+                    write!(f, "{}", name(*obc_id, task))?;
+                }
+                if let Some((obc_id, condition)) = fix {
+                    write!(f, ". Adding '{condition}' to INIT_CONDITIONS of obc{obc_id} breaks the cycle")?;
+                }
+                Ok(())
+            }
+            Problem::UnreachableTask { obc_id, task, condition } => write!(
+                f,
+                "obc{obc_id}: task '{task}' never runs, it requires '{condition}' which only tasks that never run satisfy"
+            ),
+            Problem::UnwrittenPort { obc_id, task, port } => write!(
+                f,
+                "obc{obc_id}: task '{task}' never runs, it waits on port {port} which only tasks that never run write"
+            ),
+            Problem::UnusedInitCondition { obc_id, condition } => write!(
+                f,
+                "obc{obc_id}: initial condition '{condition}' is not required by any task"
+            ),
+            Problem::UndeclaredTriggerPort { obc_id, task, port } => write!(
+                f,
+                "obc{obc_id}: task '{task}' waits on port {port}, which is not in IN: or OUT:"
+            ),
+            Problem::NoPortWriter { obc_id, task, port } => write!(
+                f,
+                "obc{obc_id}: task '{task}' waits on port {port}, but no task of the OBC writing it has @on_port: {port}"
+            ),
+            Problem::DuplicateTaskName { obc_id, task } => {
+                write!(f, "obc{obc_id}: more than one task or template is named '{task}'")
+            }
+            Problem::InvalidIdentifier { obc_id, kind, name } => {
+                if let Some(obc_id) = obc_id {
+                    write!(f, "obc{obc_id}: ")?;
+                }
+                write!(f, "{kind} name '{name}' must be a C++ identifier and not a keyword")
+            }
+            Problem::UnprovenChoice { time } => write!(
+                f,
+                "the tasks started at tick {time} may not be the heaviest choice, there are too many to compare in time"
+            ),
+        }
+    }
+}
+
+/// Every problem of the project, in the order of OBC ids, followed by the tasks which never
+/// run. Empty when the project can be scheduled.
+pub fn validate(topology: &HashMap<u32, Conf>, sensorjson: &SensorJson) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    for sensor in &sensorjson.sensors {
+        if !is_identifier(&sensor.name) {
+            problems.push(Problem::InvalidIdentifier { obc_id: None, kind: "sensor", name: sensor.name.clone() });
+        }
+    }
+    for (i, port) in sensorjson.ports.iter().enumerate() {
+        // ports may be listed more than once
+        if !is_identifier(&port.name) && !sensorjson.ports[..i].iter().any(|p| p.name == port.name) {
+            problems.push(Problem::InvalidIdentifier { obc_id: None, kind: "port", name: port.name.clone() });
+        }
+    }
+
+    for (i, resource) in sensorjson.resources.iter().enumerate() {
+        let taken = sensorjson.sensors.iter().any(|s| s.name == *resource)
+            || sensorjson.resources[..i].contains(resource);
+        if taken {
+            problems.push(Problem::DuplicateResource(resource.clone()));
+        }
+    }
+
+    let mut obc_ids: Vec<_> = topology.keys().copied().collect();
+    obc_ids.sort();
+    for obc_id in obc_ids {
+        let conf = &topology[&obc_id];
+
+        for port in conf.inports.iter().chain(&conf.outports) {
+            if !sensorjson.ports.iter().any(|p| p.name == *port) {
+                problems.push(Problem::UndefinedPort { obc_id, port: port.clone() });
+            }
+        }
+
+        let mut names: Vec<(&'static str, &Arc<str>)> = conf.templates.iter().map(|t| ("template", &t.name)).collect();
+        names.extend(conf.tasks.iter().map(|t| ("task", &t.name)));
+        for (i, (kind, name)) in names.iter().enumerate() {
+            if !is_identifier(name) {
+                problems.push(Problem::InvalidIdentifier { obc_id: Some(obc_id), kind, name: (*name).clone() });
+            }
+            // reported once, at the first repetition
+            if names[..i].iter().filter(|(_, other)| other == name).count() == 1 {
+                problems.push(Problem::DuplicateTaskName { obc_id, task: (*name).clone() });
+            }
+        }
+
+        for task in &conf.tasks {
+            for arg in &task.args {
+                if !sensorjson.sensors.iter().any(|s| s.name == arg.sensor) {
+                    problems.push(Problem::UndefinedSensor { obc_id, task: task.name.clone(), sensor: arg.sensor.clone() });
+                }
+            }
+            for resource in &task.uses {
+                if !sensorjson.resources.contains(resource) {
+                    problems.push(Problem::UndefinedResource { obc_id, task: task.name.clone(), resource: resource.clone() });
+                }
+            }
+            for port in &task.on_port {
+                if conf.outports.contains(port) {
+                    continue;
+                }
+                if !conf.inports.contains(port) {
+                    problems.push(Problem::UndeclaredTriggerPort { obc_id, task: task.name.clone(), port: port.clone() });
+                    continue;
+                }
+                let written = topology
+                    .values()
+                    .filter(|other| other.outports.contains(port))
+                    .flat_map(|other| &other.tasks)
+                    .any(|writer| writer.on_port.contains(port));
+                if !written {
+                    problems.push(Problem::NoPortWriter { obc_id, task: task.name.clone(), port: port.clone() });
+                }
+            }
+            for condition in &task.requires {
+                // a condition of another OBC is looked for in its tasks
+                let (owner, name) = match qualified_condition(condition) {
+                    Some((owner, name)) => (topology.get(&owner), name),
+                    None => (Some(conf), &**condition),
+                };
+                let Some(owner) = owner else {
+                    problems.push(Problem::UndefinedObc { obc_id, task: task.name.clone(), condition: condition.clone() });
+                    continue;
+                };
+                let satisfied = owner.initial.iter().any(|initial| &**initial == name)
+                    || owner.tasks.iter().any(|other| other.satisfies.iter().any(|s| &**s == name));
+                if !satisfied {
+                    problems.push(Problem::UnsatisfiableRequirement {
+                        obc_id,
+                        task: task.name.clone(),
+                        condition: condition.clone(),
+                    });
+                }
+            }
+        }
+
+        for condition in &conf.initial {
+            let qualified = qualified(obc_id, condition);
+            let required = conf.tasks.iter().any(|task| task.requires.contains(condition))
+                || topology.values().flat_map(|other| &other.tasks).any(|task| task.requires.contains(&qualified));
+            if !required {
+                problems.push(Problem::UnusedInitCondition { obc_id, condition: condition.clone() });
+            }
+        }
+    }
+
+    problems.extend(deadlocks(topology));
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use confparse::{conf_from_str, Duration};
+
+    use super::*;
+    use crate::{Port, Sensors};
+
+    fn topology(obcs: &[(u32, &str)]) -> HashMap<u32, Conf> {
+        obcs.iter()
+            .map(|(id, text)| (*id, conf_from_str("tasks.conf", text, *id, Duration::DEFAULT_TICK).unwrap()))
+            .collect()
+    }
+
+    fn sensorjson(sensors: &[&str], ports: &[&str], resources: &[&str]) -> SensorJson {
+        SensorJson {
+            sensors: sensors.iter().map(|name| Sensors { name: (*name).into(), from: "a".into(), to: "b".into() }).collect(),
+            ports: ports.iter().map(|name| Port { name: (*name).into(), message: None }).collect(),
+            resources: resources.iter().map(|name| (*name).into()).collect(),
+        }
+    }
+
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("_temp2"));
+        assert!(!is_identifier("2temp"));
+        assert!(!is_identifier("temp-2"));
+        assert!(!is_identifier(""));
+        assert!(!is_identifier("class"));
+    }
+
+    #[test]
+    fn warnings() {
+        assert!(Problem::UnusedInitCondition { obc_id: 1, condition: "x".into() }.is_warning());
+        assert!(!Problem::DuplicateResource("x".into()).is_warning());
+    }
+
+    #[test]
+    fn valid_project() {
+        let topology = topology(&[
+            (1, "IN:\nOUT: P\n\nINIT_CONDITIONS: ready\n\n@requires: ready\n@on_port: P\nTask a(S): 1\n@satisfies: done\n"),
+            (2, "IN: P\nOUT:\n\nINIT_CONDITIONS:\n\n@requires: obc1.done\n@on_port: P\n@uses: BUS\nTask b(T:r): 1\n"),
+        ]);
+        assert_eq!(validate(&topology, &sensorjson(&["S", "T"], &["P"], &["BUS"])), []);
+    }
+
+    #[test]
+    fn problems_of_each_obc() {
+        let topology = topology(&[
+            (1, "IN: Q\nOUT: R\n\nINIT_CONDITIONS: unused\n\n@requires: never, obc3.x\n@on_port: Q, Z\n@uses: CAN\nTask a(S, U): 1\n\nTask a(S): 1\n\nTask \"class\"(S): 1\n"),
+            (2, "IN:\nOUT: Q\n\nINIT_CONDITIONS:\n\nTask b(S): 1\n"),
+        ]);
+        let name = |name: &str| Arc::<str>::from(name);
+        assert_eq!(
+            validate(&topology, &sensorjson(&["S", "do"], &["Q", "Q", "P-1"], &["BUS", "S", "BUS"])),
+            [
+                Problem::InvalidIdentifier { obc_id: None, kind: "sensor", name: name("do") },
+                Problem::InvalidIdentifier { obc_id: None, kind: "port", name: name("P-1") },
+                Problem::DuplicateResource(name("S")),
+                Problem::DuplicateResource(name("BUS")),
+                Problem::UndefinedPort { obc_id: 1, port: name("R") },
+                Problem::DuplicateTaskName { obc_id: 1, task: name("a") },
+                Problem::InvalidIdentifier { obc_id: Some(1), kind: "task", name: name("class") },
+                Problem::UndefinedSensor { obc_id: 1, task: name("a"), sensor: name("U") },
+                Problem::UndefinedResource { obc_id: 1, task: name("a"), resource: name("CAN") },
+                Problem::NoPortWriter { obc_id: 1, task: name("a"), port: name("Q") },
+                Problem::UndeclaredTriggerPort { obc_id: 1, task: name("a"), port: name("Z") },
+                Problem::UnsatisfiableRequirement { obc_id: 1, task: name("a"), condition: name("never") },
+                Problem::UndefinedObc { obc_id: 1, task: name("a"), condition: name("obc3.x") },
+                Problem::UnusedInitCondition { obc_id: 1, condition: name("unused") },
+            ]
+        );
+    }
+}
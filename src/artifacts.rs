@@ -1,130 +1,110 @@
 use std::{
-    collections::HashMap, env::set_current_dir, fs::create_dir_all, path::PathBuf,
-    process::Command, sync::Arc,
+    collections::HashMap,
+    env::set_current_dir,
+    fs::create_dir_all,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::Arc,
 };
 
-pub fn compile_entry_cpp(obc_id: u32) -> std::io::Result<()> {
+use confparse::Conf;
+
+use crate::{
+    error::{BuildError, Error},
+    manifest::{Compiler, Manifest},
+};
+
+fn check_output(target: &str, output: io::Result<Output>) -> Result<(), BuildError> {
+    let message = match output {
+        Ok(x) if x.status.success() => return Ok(()),
+        Ok(x) => String::from_utf8_lossy(&x.stderr).to_string(),
+        Err(e) => e.to_string(),
+    };
+    Err(BuildError::Compiler {
+        target: target.to_string(),
+        message,
+    })
+}
+
+fn cpp_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for file in dir.read_dir()? {
+        let path = file?.path();
+        if path.extension().is_some_and(|ext| ext == "cpp") {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn object_names(sources: &[PathBuf]) -> Vec<PathBuf> {
+    sources
+        .iter()
+        .filter_map(|f| f.with_extension("o").file_name().map(PathBuf::from))
+        .collect()
+}
+
+/// Compiles the generated code of the OBC in `obc_dir` with the `entry/` files of the
+/// tasks in `conf`, into `obc<id>.o` in `output`.
+pub fn compile_entry_cpp(
+    obc_id: u32,
+    conf: &Conf,
+    obc_dir: &Path,
+    compiler: &Compiler,
+    output: &Path,
+) -> Result<(), Error> {
     // println!("Compiling entry cpp for obc{obc_id}");
 
-    let path_to_obc = PathBuf::from(format!("./obc{obc_id}")).canonicalize()?;
-    let dist_folder = path_to_obc.join("dist/").canonicalize()?;
-    create_dir_all(&dist_folder)?;
+    let path_to_obc = obc_dir.canonicalize()?;
+    create_dir_all(output)?;
+    let dist_folder = output.canonicalize()?;
+
+    // tasks of other profiles are left out
+    let mut entry_files = cpp_files(&path_to_obc.join("entry"))?;
+    entry_files.retain(|file| {
+        let name = file.file_stem().and_then(|f| f.to_str()).unwrap_or_default();
+        conf.tasks.iter().any(|task| &*task.name == name)
+    });
+    let lib_files = cpp_files(&path_to_obc.join("lib"))?;
 
     let curr_dir = std::env::current_dir()?;
 
     let temp_dir = std::env::temp_dir();
     set_current_dir(temp_dir)?;
 
-    let compilation_command = Command::new("g++")
-        .arg("-O2") // optimisation level 2
+    let compilation_command = Command::new(&compiler.command)
+        .args(&compiler.flags)
         .arg("-c")
         .arg(path_to_obc.join("entry.cpp"))
         .arg(path_to_obc.join("ports.cpp"))
-        .args(
-            path_to_obc
-                .join("entry")
-                .read_dir()
-                .unwrap()
-                .map(|f| f.unwrap().path()),
-        )
-        .args(
-            path_to_obc
-                .join("lib")
-                .read_dir()
-                .unwrap()
-                .filter(|f| {
-                    f.as_ref()
-                        .unwrap()
-                        .path()
-                        .extension()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        == "cpp"
-                })
-                .map(|f| f.unwrap().path()),
-        )
+        .args(&entry_files)
+        .args(&lib_files)
         // .arg("-o")
         // .arg(dist_folder.join(format!("obc{obc_id}.o")))
         .output();
 
-    match compilation_command {
-        Ok(x) => {
-            if x.status.success() {
-                println!("Compiled obc{obc_id} files Successfully");
-                // println!("Compilation output: [{x:?}]");
-            } else {
-                println!("Compiling obc{obc_id} files Failed");
-                println!("Error message: [{x:?}]");
-            }
-        }
-        Err(e) => {
-            println!("Compiling obc{obc_id} files Failed");
-            println!("Error message: [{e:?}]");
-        }
+    let compiled = check_output(&format!("obc{obc_id} files"), compilation_command);
+    if compiled.is_err() {
+        set_current_dir(&curr_dir)?;
+        compiled?;
     }
+    println!("Compiled obc{obc_id} files Successfully");
 
-    let linking_command = Command::new("ld")
+    let linking_command = Command::new(&compiler.linker)
         .arg("-r")
         .arg("entry.o")
         .arg("ports.o")
-        .args(path_to_obc.join("entry").read_dir().unwrap().map(|f| {
-            f.unwrap()
-                .path()
-                .with_extension("o")
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-        }))
-        .args(
-            path_to_obc
-                .join("lib")
-                .read_dir()
-                .unwrap()
-                .filter(|f| {
-                    f.as_ref()
-                        .unwrap()
-                        .path()
-                        .extension()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        == "cpp"
-                })
-                .map(|f| {
-                    f.unwrap()
-                        .path()
-                        .with_extension("o")
-                        .file_name()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_string()
-                }),
-        )
+        .args(object_names(&entry_files))
+        .args(object_names(&lib_files))
         .arg("-o")
         .arg(dist_folder.join(format!("obc{obc_id}.o")))
         .output();
 
     set_current_dir(&curr_dir)?;
 
-    match linking_command {
-        Ok(x) => {
-            if x.status.success() {
-                println!("Compiled obc{obc_id} Successfully");
-                // println!("Compilation output: [{x:?}]");
-            } else {
-                println!("Compiling obc{obc_id} Failed");
-                println!("Error message: [{x:?}]");
-            }
-        }
-        Err(e) => {
-            println!("Compiling obc{obc_id} Failed");
-            println!("Error message: [{e:?}]");
-        }
-    }
+    check_output(&format!("obc{obc_id}"), linking_command)?;
+    println!("Compiled obc{obc_id} Successfully");
     Ok(())
 }
 
@@ -138,10 +118,13 @@ fn get_names_array(names: HashMap<Arc<str>, u64>) -> Vec<Arc<str>> {
     id_names.into_iter().map(|(_, name)| name).collect()
 }
 
+/// Compiles the demo rtos for the sensors and ports of the project, into `rtos.o` in `output`.
 pub fn compile_demo_rtos(
     sensor_names: HashMap<Arc<str>, u64>,
     port_names: HashMap<Arc<str>, u64>,
-) -> std::io::Result<()> {
+    manifest: &Manifest,
+    output: &Path,
+) -> Result<(), Error> {
     let rtos_cpp_template = include_str!("../cpp_snippets/rtos.cpp");
 
     let rtos_cpp = rtos_cpp_template
@@ -166,37 +149,24 @@ pub fn compile_demo_rtos(
                     .collect::<Vec<_>>()
                     .join(", ")
             ),
-        );
+        )
+        .replace("{TICK_NS}", &manifest.tick.as_nanos().to_string());
 
     let temp_dir = std::env::temp_dir();
     let rtos_cpp_path = temp_dir.join("rtos.cpp");
     std::fs::write(&rtos_cpp_path, rtos_cpp)?;
 
-    let rtos_dir = PathBuf::from("./rtos");
-    create_dir_all(&rtos_dir)?;
+    create_dir_all(output)?;
 
-    let compilation_command = Command::new("g++")
-        .arg("-O2") // optimisation level 2
+    let compilation_command = Command::new(&manifest.compiler.command)
+        .args(&manifest.compiler.flags)
         .arg("-c")
         .arg(rtos_cpp_path)
         .arg("-o")
-        .arg(rtos_dir.join("rtos.o"))
+        .arg(output.join("rtos.o"))
         .output();
 
-    match compilation_command {
-        Ok(x) => {
-            if x.status.success() {
-                println!("Compiled demo rtos Successfully");
-                // println!("Compilation output: [{x:?}]");
-            } else {
-                println!("Compiling demo rtos files Failed");
-                println!("Error message: [{x:?}]");
-            }
-        }
-        Err(e) => {
-            println!("Compiling demo rtos Failed");
-            println!("Error message: [{e:?}]");
-        }
-    }
+    check_output("demo rtos", compilation_command)?;
+    println!("Compiled demo rtos Successfully");
     Ok(())
 }
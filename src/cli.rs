@@ -3,35 +3,101 @@ use std::{
     env::{current_dir, set_current_dir},
     fs::{self, create_dir, File},
     io::{self, Write},
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
-use confparse::Conf;
-use decert_scheduler::schedule;
+use confparse::{Access, Arg, Conf, Duration, Format, Task};
+use decert_scheduler::{is_identifier, read_sensors, schedule, write_entries, Port, Schedule, SensorJson, SlackReport};
 use itertools::Itertools;
 
-use crate::artifacts::{compile_demo_rtos, compile_entry_cpp};
+use crate::{
+    artifacts::{compile_demo_rtos, compile_entry_cpp},
+    error::{BuildError, Error},
+    gantt::Chart,
+    manifest::{find_root, Backend, Manifest, Obc},
+};
 
-fn write_input_port(port_name: &str, ports_hpp: &mut File) -> io::Result<()> {
-    let input_port_snippet = include_str!("../cpp_snippets/input_port.cpp");
+/// Packed struct of the message of a typed port, checked against the size from `sensors.json`.
+fn message_struct(port: &Port) -> String {
+    let message_snippet = include_str!("../cpp_snippets/port_message.cpp");
+    let fields = port
+        .message
+        .iter()
+        .flatten()
+        .map(|field| format!("    {} {};\n", field.ty.cpp_type(), field.name))
+        .collect::<String>();
+    message_snippet
+        .replace("NAME", &port.name)
+        .replace("FIELDS", &fields)
+        .replace("SIZE", &port.message_size().unwrap_or(0).to_string())
+}
+
+fn write_input_port(port_name: &str, message: Option<&Port>, ports_hpp: &mut File) -> io::Result<()> {
+    let input_port_snippet = match message {
+        Some(port) => format!(
+            "{}\n{}",
+            message_struct(port),
+            include_str!("../cpp_snippets/typed_input_port.cpp")
+        ),
+        None => include_str!("../cpp_snippets/input_port.cpp").to_string(),
+    };
     ports_hpp
-        .write(format!("\n{}\n", input_port_snippet.replace("NAME", &port_name)).as_bytes())?;
+        .write_all(format!("\n{}\n", input_port_snippet.replace("NAME", port_name)).as_bytes())?;
 
     Ok(())
 }
 
-fn write_output_port(port_name: &str, ports_hpp: &mut File) -> io::Result<()> {
-    let output_port_snippet = include_str!("../cpp_snippets/output_port.cpp");
+fn write_output_port(port_name: &str, message: Option<&Port>, ports_hpp: &mut File) -> io::Result<()> {
+    let output_port_snippet = match message {
+        Some(port) => format!(
+            "{}\n{}",
+            message_struct(port),
+            include_str!("../cpp_snippets/typed_output_port.cpp")
+        ),
+        None => include_str!("../cpp_snippets/output_port.cpp").to_string(),
+    };
     ports_hpp
-        .write(format!("\n{}\n", output_port_snippet.replace("NAME", &port_name)).as_bytes())?;
+        .write_all(format!("\n{}\n", output_port_snippet.replace("NAME", port_name)).as_bytes())?;
 
     Ok(())
 }
 
+/// Ports with a message, by name
+type Messages = HashMap<Arc<str>, Port>;
+
+/// The typed ports of `sensors.json` by name.
+///
+/// A port may be listed more than once, but always with the same message,
+/// so that every OBC reading or writing it agrees on the layout.
+fn port_messages(sensors: &SensorJson) -> Result<Messages, BuildError> {
+    let mut messages = Messages::new();
+    for port in &sensors.ports {
+        if let Some(other) = sensors.ports.iter().find(|f| f.name == port.name && f.message != port.message) {
+            return Err(BuildError::PortMessageMismatch {
+                port: port.name.to_string(),
+                messages: (port.describe_message(), other.describe_message()),
+            });
+        }
+        let Some(fields) = &port.message else {
+            continue;
+        };
+        for (i, field) in fields.iter().enumerate() {
+            if !is_identifier(&field.name) || fields[..i].iter().any(|f| f.name == field.name) {
+                return Err(BuildError::InvalidMessageField {
+                    port: port.name.to_string(),
+                    field: field.name.to_string(),
+                });
+            }
+        }
+        messages.insert(port.name.clone(), port.clone());
+    }
+    Ok(messages)
+}
+
 fn write_sensor(sensor_name: &str, ports_hpp: &mut File) -> io::Result<()> {
     let sensor_snippet = include_str!("../cpp_snippets/sensor.cpp");
-    ports_hpp.write(format!("\n{}\n", sensor_snippet.replace("NAME", &sensor_name)).as_bytes())?;
+    ports_hpp.write_all(format!("\n{}\n", sensor_snippet.replace("NAME", sensor_name)).as_bytes())?;
 
     Ok(())
 }
@@ -39,50 +105,119 @@ fn write_sensor(sensor_name: &str, ports_hpp: &mut File) -> io::Result<()> {
 pub fn create_project(project_name: &str) -> std::io::Result<()> {
     let path_dir = Path::new(project_name);
     fs::create_dir(path_dir)?;
-    File::create(path_dir.join("sensors.json"))?;
+    let manifest = Manifest {
+        name: project_name.to_string(),
+        ..Manifest::default()
+    };
+    manifest.create(path_dir)?;
+    File::create(path_dir.join(&manifest.sensors))?;
     Ok(())
 }
 
-pub fn add_obc(id: u32) -> io::Result<()> {
-    create_dir(format!("obc{id}"))?;
-    create_dir(format!("obc{id}/entry"))?;
-    create_dir(format!("obc{id}/lib"))?;
-    File::create(format!("obc{id}/ports.hpp"))?;
+pub fn add_obc(id: u32) -> Result<(), Error> {
+    let mut manifest = Manifest::load(Path::new("."))?;
+    if manifest.obcs.iter().any(|obc| obc.id == id) {
+        Err(BuildError::ObcExists(id))?
+    }
+    let dir = manifest.obc_dir(id);
+    create_dir(&dir)?;
+    create_dir(dir.join("entry"))?;
+    create_dir(dir.join("lib"))?;
 
-    let mut port_file = File::create(format!("obc{id}/ports.hpp"))?;
-    port_file.write(
+    let mut port_file = File::create(dir.join("ports.hpp"))?;
+    port_file.write_all(
         b"// not to be touched by user\n// will be regenerated to ensure correctness on each build",
     )?;
-    File::create(format!("obc{id}/tasks.conf"))?;
+    File::create(dir.join("tasks.conf"))?;
+    manifest.add_obc(Path::new("."), Obc { id, path: dir })?;
     Ok(())
 }
 
-fn get_args_string(args: &Vec<Arc<str>>) -> String {
+/// Parameter names of a task function, numbered where sensors share a prefix.
+fn arg_names(args: &[Arg]) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for arg in args.iter().filter(|f| !f.sensor.is_empty()) {
+        let first3lower = arg.sensor.chars().take(3).collect::<String>().to_lowercase();
+        let mut name = first3lower.clone();
+        let mut n = 1;
+        while names.contains(&name) {
+            n += 1;
+            name = format!("{first3lower}{n}");
+        }
+        names.push(name);
+    }
+    names
+}
+
+fn get_args_string(args: &[Arg]) -> String {
     args.iter()
-        .filter(|f| f.len() > 0)
-        .map(|x| {
-            let first3lower = x[..3].to_lowercase();
-            format!("{x}* {first3lower}")
+        .filter(|f| !f.sensor.is_empty())
+        .zip(arg_names(args))
+        .map(|(x, name)| {
+            // tasks which only read a sensor get a handle without write
+            let constness = if x.access == Access::Read { "const " } else { "" };
+            format!("{constness}{}* {name}", x.sensor)
         })
         .collect::<Vec<String>>()
         .join(", ")
 }
 
-pub fn update_tasks() -> Result<Conf, String> {
-    let dir =
-        current_dir().map_err(|e| format!("Failed to read current dir: {}", e.to_string()))?;
-    let dir_name = dir
-        .file_name()
-        .ok_or("Could not get name of current dir")?
-        .to_str()
-        .ok_or("Could not get name of current dir")?;
-    let obc_id = dir_name
-        .strip_prefix("obc")
-        .ok_or("Could not get obc id")?
-        .parse::<u32>()
-        .map_err(|e| e.to_string())?;
-
-    let conf = confparse::get_conf("tasks.conf", obc_id)?;
+/// Header with the shared function of a template, generic over the type of each sensor.
+fn template_code(template: &Task) -> String {
+    let params: Vec<String> = template
+        .args
+        .iter()
+        .map(|param| {
+            let lower = param.sensor.to_lowercase();
+            let name = if lower == *param.sensor { format!("{lower}_") } else { lower };
+            let constness = if param.access == Access::Read { "const " } else { "" };
+            format!("{constness}{}* {name}", param.sensor)
+        })
+        .collect();
+    let header = if template.args.is_empty() {
+        "inline".to_string()
+    } else {
+        let typenames = template.args.iter().map(|param| format!("typename {}", param.sensor));
+        format!("template <{}>", typenames.collect::<Vec<_>>().join(", "))
+    };
+    include_str!("../cpp_snippets/template.hpp")
+        .replace("TEMPLATENAME", &template.name)
+        .replace("TEMPLATE", &header)
+        .replace("ARGS", &params.join(", "))
+}
+
+/// Id of the OBC in `dir`, with the tick and the typed ports of its project.
+fn obc_settings(dir: &Path) -> Result<(u32, Duration, Messages), Error> {
+    // inside a project the OBC is looked up in the manifest, which also gives the
+    // tick and the message types of the ports, also without `decert.toml`. Outside of
+    // one the folder is `obc<id>`, and without a `sensors.json` no port has a message.
+    match find_root(dir) {
+        Some(root) => {
+            let manifest = Manifest::load(root)?;
+            let obc_id = manifest.obc_at(root, dir).ok_or(BuildError::NotObcDir(dir.to_path_buf()))?;
+            let messages = port_messages(&read_sensors(root.join(&manifest.sensors))?)?;
+            Ok((obc_id, manifest.tick, messages))
+        }
+        None => {
+            let obc_id = dir
+                .file_name()
+                .and_then(|f| f.to_str())
+                .and_then(|f| f.strip_prefix("obc"))
+                .and_then(|f| f.parse::<u32>().ok())
+                .ok_or(BuildError::NotObcDir(dir.to_path_buf()))?;
+            Ok((obc_id, Duration::DEFAULT_TICK, HashMap::new()))
+        }
+    }
+}
+
+/// Generates `ports.hpp` and `entry.hpp` for the tasks of `profile`, and creates the
+/// `entry/` stubs of new tasks of every profile. Returns the whole configuration.
+pub fn update_tasks(profile: Option<&str>) -> Result<Conf, Error> {
+    let dir = current_dir()?;
+
+    let (obc_id, tick, messages) = obc_settings(&dir)?;
+    let all = confparse::get_conf(tasks_file(&dir)?, obc_id, tick)?;
+    let conf = all.for_profile(profile);
 
     // println!("{:?}", conf);
 
@@ -90,144 +225,283 @@ pub fn update_tasks() -> Result<Conf, String> {
         .create(true)
         .write(true)
         .truncate(true)
-        .open("ports.hpp")
-        .map_err(|e| e.to_string())?;
+        .open("ports.hpp")?;
 
-    ports_hpp.write(
+    ports_hpp.write_all(
         b"// not to be touched by user\n// will be regenerated to ensure correctness on each build\n",
-    ).map_err(|e| e.to_string())?;
+    )?;
 
-    ports_hpp
-        .write(
-            format!("void syslog(const char*, ...) __attribute__ ((format (printf, 1, 2)));")
-                .as_bytes(),
-        )
-        .map_err(|e| e.to_string())?;
+    ports_hpp.write_all(
+        "#include <cstdint>\n\nvoid syslog(const char*, ...) __attribute__ ((format (printf, 1, 2)));".as_bytes(),
+    )?;
 
     for inports in conf.inports.iter() {
-        write_input_port(&inports, &mut ports_hpp).map_err(|e| e.to_string())?;
+        write_input_port(inports, messages.get(inports), &mut ports_hpp)?;
     }
     for outports in conf.outports.iter() {
-        write_output_port(&outports, &mut ports_hpp).map_err(|e| e.to_string())?;
+        write_output_port(outports, messages.get(outports), &mut ports_hpp)?;
     }
 
-    let sensors = conf.tasks.iter().flat_map(|x| x.args.clone()).unique();
+    let sensors = conf.tasks.iter().flat_map(|x| x.args.iter().map(|arg| arg.sensor.clone())).unique();
 
     for sensor in sensors {
         // println!("{:?}", sensor);
-        write_sensor(&sensor, &mut ports_hpp).map_err(|e| e.to_string())?;
+        write_sensor(&sensor, &mut ports_hpp)?;
     }
 
     let task_snippet = include_str!("../cpp_snippets/task.cpp");
 
-    for task in &conf.tasks {
+    // instances share the function of their template, which is a header so it can be instantiated
+    // stubs are kept for the tasks of other profiles, they are only left out of the build
+    for template in &all.templates {
+        let mut file = match File::create_new(format!("entry/{}.hpp", template.name)) {
+            Ok(x) => x,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        };
+        file.write_all(template_code(template).as_bytes())?;
+    }
+
+    for task in all.tasks.iter().filter(|task| task.template.is_none()) {
         let mut file = match File::create_new(format!("entry/{}.cpp", task.name)) {
             Ok(x) => x,
             Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
-            Err(e) => return Err(e.to_string()),
+            Err(e) => return Err(e.into()),
         };
 
         let task_code = task_snippet
             .replace("TASKNAME", &task.name)
             .replace("ARGS", &get_args_string(&task.args));
 
-        file.write(task_code.as_bytes())
-            .map_err(|e| e.to_string())?;
+        file.write_all(task_code.as_bytes())?;
     }
 
-    for file in Path::new("entry").read_dir().map_err(|e| e.to_string())? {
-        let file = file.map_err(|e| e.to_string())?.path();
+    for file in Path::new("entry").read_dir()? {
+        let file = file?.path();
         let filename = file.file_stem().unwrap_or_default();
-        let filename = filename.to_str().ok_or("Filename is not valid UTF-8")?;
-        if !conf.tasks.iter().any(|f| &*f.name == filename) {
-            Err(format!("'{filename}' is not the name of any task."))?
+        let filename = filename
+            .to_str()
+            .ok_or(BuildError::InvalidFileName(file.clone()))?;
+        let is_task = all.tasks.iter().any(|f| &*f.name == filename && f.template.is_none());
+        let is_template = all.templates.iter().any(|f| &*f.name == filename);
+        if !is_task && !is_template {
+            Err(BuildError::StrayEntryFile(filename.to_string()))?
         }
     }
 
     // create entry.hpp
-    let entry_hpp =
-        conf.tasks
-            .iter()
-            .fold(String::from("#include \"ports.hpp\"\n\n"), |acc, task| {
-                acc + &"\n\nvoid TASKNAME(ARGS);"
-                    .replace("TASKNAME", &task.name)
-                    .replace("ARGS", &get_args_string(&task.args))
-            });
+    let includes = conf
+        .templates
+        .iter()
+        .fold(String::from("#include \"ports.hpp\"\n"), |acc, template| {
+            acc + &format!("#include \"entry/{}.hpp\"\n", template.name)
+        });
+    let entry_hpp = conf.tasks.iter().fold(includes + "\n", |acc, task| match &task.template {
+        // instances call the function of their template with their sensors
+        Some(template) => {
+            acc + &"\n\ninline void TASKNAME(ARGS) { TEMPLATE(NAMES); }"
+                .replace("TASKNAME", &task.name)
+                .replace("ARGS", &get_args_string(&task.args))
+                .replace("TEMPLATE", template)
+                .replace("NAMES", &arg_names(&task.args).join(", "))
+        }
+        None => {
+            acc + &"\n\nvoid TASKNAME(ARGS);"
+                .replace("TASKNAME", &task.name)
+                .replace("ARGS", &get_args_string(&task.args))
+        }
+    });
 
     let mut entry_hpp_file = fs::OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
-        .open("entry.hpp")
-        .map_err(|e| e.to_string())?;
+        .open("entry.hpp")?;
 
-    entry_hpp_file
-        .write(entry_hpp.as_bytes())
-        .map_err(|e| e.to_string())?;
+    entry_hpp_file.write_all(entry_hpp.as_bytes())?;
 
-    Ok(conf)
+    Ok(all)
 }
 
-fn precompilation() -> io::Result<HashMap<u32, Conf>> {
-    let is_root = Path::new("./sensors.json").exists();
-    if !is_root {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "Not in project's root directory",
-        ));
+/// The configuration file of the OBC in `dir`: `tasks.conf`, `tasks.toml` or `tasks.json`.
+fn tasks_file(dir: &Path) -> Result<PathBuf, Error> {
+    let found: Vec<PathBuf> = Format::ALL
+        .iter()
+        .map(|format| dir.join(format.file_name()))
+        .filter(|path| path.exists())
+        .collect();
+    match found.len() {
+        0 => Ok(dir.join(Format::Conf.file_name())),
+        1 => Ok(found[0].clone()),
+        _ => Err(BuildError::ConflictingTasksFiles(found).into()),
     }
+}
 
-    let mut obc_ids = Vec::new();
+/// The configuration of every OBC in `profile`, by OBC id.
+fn precompilation(manifest: &Manifest, profile: Option<&str>) -> Result<HashMap<u32, Conf>, Error> {
+    let root_dir = current_dir()?;
 
-    for dir in Path::new(".").read_dir()? {
-        let dir = dir?;
-        let path = dir.path();
-        if path.is_dir() {
-            let Some(last_component_osstr) = path.components().last() else {
-                continue;
-            };
-            let Some(last_component) = last_component_osstr.as_os_str().to_str() else {
-                continue;
-            };
-            let Some(obc_id_str) = last_component.strip_prefix("obc") else {
-                if last_component.starts_with("rtos") {
-                    continue;
-                }
-                println!("Non obc folder found: {path:?}");
-                continue;
-            };
+    let mut topology = HashMap::new();
+    let mut profiles = Vec::new();
 
-            let obc_id = obc_id_str
-                .parse::<u32>()
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    for obc_id in manifest.obc_ids() {
+        set_current_dir(root_dir.join(manifest.obc_dir(obc_id)))?;
+        let conf = update_tasks(profile)?;
+        profiles.extend(conf.profiles());
+        topology.insert(obc_id, conf.for_profile(profile));
+    }
+
+    set_current_dir(root_dir)?;
+    warn_unused_profile(profile, &profiles);
+    Ok(topology)
+}
+
+/// The configuration of every OBC in `profile`, read as it is: unlike [`precompilation`]
+/// no code is generated.
+fn read_topology(manifest: &Manifest, profile: Option<&str>) -> Result<HashMap<u32, Conf>, Error> {
+    let mut topology = HashMap::new();
+    let mut profiles = Vec::new();
+
+    for obc_id in manifest.obc_ids() {
+        let conf = confparse::get_conf(tasks_file(&manifest.obc_dir(obc_id))?, obc_id, manifest.tick)?;
+        profiles.extend(conf.profiles());
+        topology.insert(obc_id, conf.for_profile(profile));
+    }
+
+    warn_unused_profile(profile, &profiles);
+    Ok(topology)
+}
+
+fn warn_unused_profile(profile: Option<&str>, profiles: &[Arc<str>]) {
+    if let Some(profile) = profile.filter(|p| !profiles.iter().any(|f| &**f == *p)) {
+        eprintln!("Warning: profile '{profile}' is not used by any OBC");
+    }
+}
 
-            obc_ids.push(obc_id);
+/// Rewrites the configuration file of every OBC in canonical form.
+/// With `check` nothing is written and the unformatted files are reported instead.
+pub fn fmt(check: bool) -> Result<(), Error> {
+    let mut unformatted = Vec::new();
+
+    let manifest = Manifest::load(Path::new("."))?;
+    for obc_id in manifest.obc_ids() {
+        let path = tasks_file(&manifest.obc_dir(obc_id))?;
+        let format = Format::from_path(&path).unwrap_or(Format::Conf);
+        let content = fs::read_to_string(&path)?;
+        let formatted = confparse::get_conf(&path, obc_id, manifest.tick)?.to_format(format);
+        if formatted == content {
+            continue;
+        }
+        if check {
+            println!("Not formatted: {}", path.display());
+            unformatted.push(path);
+        } else {
+            fs::write(&path, formatted)?;
+            println!("Formatted {}", path.display());
         }
     }
 
-    let root_dir = current_dir()?;
+    if !unformatted.is_empty() {
+        Err(Error::Unformatted(unformatted))?
+    }
+    Ok(())
+}
 
-    let mut topology = HashMap::new();
+/// Writes the configuration in `input` to `output`, in the format of the
+/// extension of `output`. Comments and includes are kept.
+pub fn convert(input: &Path, output: &Path) -> Result<(), Error> {
+    let Some(format) = Format::from_path(output) else {
+        Err(Error::Usage(
+            "decert convert <input> <output.conf|output.toml|output.json>".to_string(),
+        ))?
+    };
+    // the OBC id is not part of the file, the tick is the project's when run inside one
+    let tick = match find_root(&current_dir()?) {
+        Some(root) => Manifest::load(root)?.tick,
+        None => Duration::DEFAULT_TICK,
+    };
+    let conf = confparse::get_conf(input, 0, tick)?;
+    fs::write(output, conf.to_format(format))?;
+    Ok(())
+}
+
+/// The OBC which writes each port, the one with the port in `OUT:`.
+fn port_writers(topology: &HashMap<u32, Conf>) -> Result<HashMap<String, u32>, Error> {
+    let mut port2obc: HashMap<String, u32> = HashMap::new(); // port_name:OBC which declares it as out port
 
-    for obc_id in obc_ids {
-        set_current_dir(root_dir.join(Path::new(&format!("obc{obc_id}/"))))?;
-        let conf = update_tasks().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        topology.insert(obc_id, conf);
+    // ports, mapped before scheduling as tasks of the reading OBC wait for the writing one
+    for (obc_id, conf) in topology {
+        for port in &conf.outports {
+            if let Some(other_obc) = port2obc.get(&port.to_string()) {
+                return Err(BuildError::DuplicateOutputPort {
+                    port: port.to_string(),
+                    obcs: (*obc_id, *other_obc),
+                }
+                .into());
+            }
+            // Insert the port if it doesn't exist to track it
+            port2obc.insert(port.to_string(), *obc_id);
+        }
     }
 
-    set_current_dir(root_dir)?;
+    // Restricting one to one mapping for input and output ports
+    // TODO: could be deleted after the RTOS is implemented to handle many input ports
+    if let Some(port) = topology.values().flat_map(|conf| {
+        conf.inports.clone()
+    }).duplicates().next() {
+        Err(BuildError::DuplicateInputPort(port.to_string()))?
+    };
+    Ok(port2obc)
+}
 
-    Ok(topology)
+/// Reports the warnings found while scheduling, on stderr so that they stay out of the output
+fn print_warnings(schedule: &Schedule) {
+    for warning in &schedule.warnings {
+        eprintln!("Warning: {warning}");
+    }
 }
 
-pub fn compile() -> Result<(), String> {
-    let topology = precompilation().map_err(|e| e.to_string())?;
-    let sensors = schedule(&topology)?;
+/// Draws the schedule of the project as a Gantt chart, in SVG to `svg` and in HTML to `html`.
+pub fn schedule_chart(svg: Option<&Path>, html: Option<&Path>, profile: Option<&str>) -> Result<(), Error> {
+    let manifest = Manifest::load(Path::new("."))?;
+    let topology = read_topology(&manifest, profile)?;
+    let sensors = read_sensors(&manifest.sensors)?;
+    let port2obc = port_writers(&topology)?;
+    let schedule = schedule(&topology, &port2obc, &sensors)?;
+    print_warnings(&schedule);
+
+    let chart = Chart {
+        title: manifest.name.clone(),
+        tick: manifest.tick.to_string(),
+        schedule: &schedule,
+        topology: &topology,
+        port2obc: &port2obc,
+        sensorjson: &sensors,
+    };
+    if let Some(path) = svg {
+        fs::write(path, chart.svg())?;
+    }
+    if let Some(path) = html {
+        fs::write(path, chart.html())?;
+    }
+    Ok(())
+}
+
+pub fn compile(profile: Option<&str>) -> Result<(), Error> {
+    let manifest = Manifest::load(Path::new("."))?;
+    let topology = precompilation(&manifest, profile)?;
+    let sensors = read_sensors(&manifest.sensors)?;
+    let port2obc = port_writers(&topology)?;
+
+    let obc_dirs: HashMap<u32, PathBuf> = topology.keys().map(|id| (*id, manifest.obc_dir(*id))).collect();
+    let schedule = schedule(&topology, &port2obc, &sensors)?;
+    print_warnings(&schedule);
+    write_entries(&schedule, &obc_dirs)?;
+    print!("{}", SlackReport::new(&schedule));
 
     // creating class strings for each sensors and ports in Vec:sensors
     let mut sensor_impl: HashMap<String, String> = HashMap::new(); // sensor_name: implementation
                                                                    // let mut port_impl: HashMap<String, String> = HashMap::new(); // port_name: implementation
-    let mut port2obc: HashMap<String, u32> = HashMap::new(); // port_name:OBC which declares it as out port
 
     // sensors
     let sensor_impl_snippet = include_str!("../cpp_snippets/sensor_impl.cpp");
@@ -248,41 +522,20 @@ pub fn compile() -> Result<(), String> {
         sensor_impl.insert(sensor.name.to_string(), sensor_code);
     }
 
-    // ports
-    for (obc_id, conf) in &topology {
-        for port in &conf.outports {
-            if port2obc.contains_key(&port.to_string()) {
-                return Err(format!(
-                    "Two OBCs cannot have the same output port: {obc_id} and {}",
-                    port2obc[&port.to_string()]
-                ));
-            }
-            // Insert the port if it doesn't exist to track it
-            port2obc.insert(port.to_string(), obc_id.clone());
-        }
-    }
-
-    // Restricting one to one mapping for input and output ports
-    // TODO: could be deleted after the RTOS is implemented to handle many input ports
-    match topology.iter().flat_map(|(_, conf)| {
-        conf.inports.clone()
-    }).duplicates().next()  {
-        Some(port) => {
-            Err(format!("There must be one to one mapping of ports.input port:{port} was used in more than one OBC."))?
-        },
-        None => {}
-    };
-
-    // ports implementations
-    let port_impl_snippet = include_str!("../cpp_snippets/port_impl.cpp");
+    // ports implementations, typed ports read and write their message
+    let messages = port_messages(&sensors)?;
     let mut port_names: HashMap<Arc<str>, u64> = HashMap::new();
     let port_impl: HashMap<String, String> = port2obc
         .iter()
         .enumerate()
         .map(|(id, (port_name, _))| {
-            let mut port_code = port_impl_snippet.to_string();
+            let mut port_code = match messages.get(port_name.as_str()) {
+                Some(port) => include_str!("../cpp_snippets/typed_port_impl.cpp")
+                    .replace("{MESSAGE}", &message_struct(port)),
+                None => include_str!("../cpp_snippets/port_impl.cpp").to_string(),
+            };
 
-            port_code = port_code.replace("{NAME}", &port_name);
+            port_code = port_code.replace("{NAME}", port_name);
             port_code = port_code.replace("{ID}", &id.to_string());
 
             port_names.insert(port_name.clone().into(), id as u64);
@@ -290,7 +543,7 @@ pub fn compile() -> Result<(), String> {
         })
         .collect();
 
-    let root_dir = current_dir().map_err(|e| e.to_string())?;
+    let root_dir = current_dir()?;
 
     // creates ports.cpp for each obc file
     for (obc_id, conf) in &topology {
@@ -298,67 +551,135 @@ pub fn compile() -> Result<(), String> {
             .create(true)
             .write(true)
             .truncate(true)
-            .open(root_dir.join(format!("obc{obc_id}")).join("ports.cpp"))
-            .map_err(|e| e.to_string())?;
+            .open(root_dir.join(&obc_dirs[obc_id]).join("ports.cpp"))?;
 
-        ports_cpp
-            .write("#include \"rtos.hpp\"\n\n".as_bytes())
-            .map_err(|e| e.to_string())?;
+        ports_cpp.write_all("#include <cstdint>\n#include \"rtos.hpp\"\n\n".as_bytes())?;
         let mut syslog_impl = include_str!("../cpp_snippets/syslog.cpp").to_string();
 
         syslog_impl = syslog_impl.replace("OBCID", &obc_id.to_string());
 
-        ports_cpp
-            .write(format!("{}\n", syslog_impl).as_bytes())
-            .map_err(|e| e.to_string())?;
+        ports_cpp.write_all(format!("{}\n", syslog_impl).as_bytes())?;
 
         let mut ports_used = conf.outports.clone();
         ports_used.append(&mut conf.inports.clone());
 
         // all the sensors used by this OBC
-        let sensors_used = conf.tasks.iter().flat_map(|x| x.args.clone()).unique();
+        let sensors_used = conf.tasks.iter().flat_map(|x| x.args.iter().map(|arg| arg.sensor.clone())).unique();
         for sensor_name in sensors_used {
             let Some(implementation) = sensor_impl.get(&*sensor_name) else {
-                Err(format!(
-                    "Sensor used : {sensor_name} is not defined in sensor.json"
-                ))?
+                Err(BuildError::UndefinedSensor(sensor_name.to_string()))?
             };
-            ports_cpp
-                .write(implementation.as_bytes())
-                .map_err(|e| e.to_string())?;
+            ports_cpp.write_all(implementation.as_bytes())?;
         }
 
         for port_name in ports_used {
             let Some(implementation) = port_impl.get(&*port_name) else {
-                Err(format!(
-                    "Port used : {port_name} is not defined in sensor.json"
-                ))?
+                Err(BuildError::UndefinedPort(port_name.to_string()))?
             };
-            ports_cpp
-                .write(implementation.as_bytes())
-                .map_err(|e| e.to_string())?;
+            ports_cpp.write_all(implementation.as_bytes())?;
         }
     }
 
-    match compile_demo_rtos(sensor_names, port_names) {
-        Ok(_) => {}
-        Err(e) => return Err(e.to_string()),
+    let output = root_dir.join(&manifest.output);
+    match manifest.backend {
+        Backend::DemoRtos => compile_demo_rtos(sensor_names, port_names, &manifest, &output)?,
     }
 
-    for (obc_id, _) in &topology {
+    for (obc_id, conf) in &topology {
         // copy rtos.hpp in each obc folder
         let rtos_hpp = include_str!("../cpp_snippets/rtos.hpp");
         let mut rtos_hpp_file = fs::OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(root_dir.join(format!("obc{obc_id}")).join("rtos.hpp"))
-            .map_err(|e| e.to_string())?;
-        rtos_hpp_file
-            .write(rtos_hpp.as_bytes())
-            .map_err(|e| e.to_string())?;
+            .open(root_dir.join(&obc_dirs[obc_id]).join("rtos.hpp"))?;
+        rtos_hpp_file.write_all(rtos_hpp.as_bytes())?;
 
-        compile_entry_cpp(*obc_id).map_err(|e| e.to_string())?;
+        compile_entry_cpp(*obc_id, conf, &root_dir.join(&obc_dirs[obc_id]), &manifest.compiler, &output)?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENSORS: &str = r#"{
+        "sensors": [],
+        "ports": ["raw", {"name": "attitude", "message": [{"name": "angle", "type": "f32"}]}]
+    }"#;
+
+    /// A fresh directory with the folders `dirs` and the files `files`
+    fn project(name: &str, dirs: &[&str], files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("decert-{}-cli-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in dirs {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for (file, content) in files {
+            fs::write(root.join(file), content).unwrap();
+        }
+        root
+    }
+
+    fn messages(json: &str) -> Result<Messages, BuildError> {
+        port_messages(&serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn messages_without_manifest() {
+        let root = project("legacy", &["obc2"], &[("sensors.json", SENSORS)]);
+        let (obc_id, tick, messages) = obc_settings(&root.join("obc2")).unwrap();
+        assert_eq!((obc_id, tick), (2, Duration::DEFAULT_TICK));
+        assert_eq!(messages.keys().collect::<Vec<_>>(), vec![&Arc::from("attitude")]);
+        assert_eq!(messages["attitude"].message_size(), Some(4));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn messages_with_manifest() {
+        let manifest = "tick = \"1ms\"\nsensors = \"hw/sensors.json\"\n\n[[obc]]\nid = 4\npath = \"boards/main\"\n";
+        let root = project("manifest", &["hw", "boards/main", "obc5"], &[(crate::manifest::MANIFEST, manifest), ("hw/sensors.json", SENSORS)]);
+        let (obc_id, tick, messages) = obc_settings(&root.join("boards/main")).unwrap();
+        assert_eq!((obc_id, tick.as_nanos()), (4, 1_000_000));
+        assert!(messages.contains_key("attitude"));
+        // a folder named as an OBC but not listed in the manifest
+        let error = obc_settings(&root.join("obc5")).unwrap_err();
+        assert!(matches!(error, Error::Build(BuildError::NotObcDir(_))), "{error:?}");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn obc_outside_of_a_project() {
+        let root = project("outside", &["obc7", "board"], &[]);
+        let (obc_id, _, messages) = obc_settings(&root.join("obc7")).unwrap();
+        assert_eq!(obc_id, 7);
+        assert!(messages.is_empty());
+        assert!(obc_settings(&root.join("board")).is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn messages_of_ports_listed_twice() {
+        let same = r#"{"sensors": [], "ports": [
+            {"name": "p", "message": [{"name": "a", "type": "u8"}]},
+            {"name": "p", "message": [{"name": "a", "type": "u8"}]}
+        ]}"#;
+        assert_eq!(messages(same).unwrap().len(), 1);
+
+        let untyped = r#"{"sensors": [], "ports": [{"name": "p", "message": [{"name": "a", "type": "u8"}]}, "p"]}"#;
+        match messages(untyped) {
+            Err(BuildError::PortMessageMismatch { port, messages }) => {
+                assert_eq!(port, "p");
+                assert_eq!(messages, ("{a: u8}".to_string(), "no message".to_string()));
+            }
+            other => panic!("expected a mismatch, found {other:?}"),
+        }
+
+        for fields in [r#"[{"name": "a", "type": "u8"}, {"name": "a", "type": "u16"}]"#, r#"[{"name": "1a", "type": "u8"}]"#] {
+            let json = format!(r#"{{"sensors": [], "ports": [{{"name": "p", "message": {fields}}}]}}"#);
+            let error = messages(&json).unwrap_err();
+            assert!(matches!(error, BuildError::InvalidMessageField { .. }), "{error:?}");
+        }
+    }
+}
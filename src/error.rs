@@ -0,0 +1,176 @@
+use std::{
+    fmt::{self, Display},
+    io,
+    path::PathBuf,
+};
+
+use confparse::ConfError;
+use decert_scheduler::ScheduleError;
+
+/// An error while generating or compiling the code of a project.
+#[derive(Debug)]
+pub enum BuildError {
+    /// Neither `decert.toml` nor `sensors.json` was found in the current directory
+    NotProjectRoot,
+    /// `decert.toml` could not be read or is not valid
+    InvalidManifest { path: PathBuf, message: String },
+    /// `add-obc` was given the id of an OBC the project already has
+    ObcExists(u32),
+    /// The current directory is not an `obc<id>` folder
+    NotObcDir(PathBuf),
+    /// A file in `entry/` does not belong to any task
+    StrayEntryFile(String),
+    /// A file name that is not valid UTF-8
+    InvalidFileName(PathBuf),
+    /// An OBC has more than one of `tasks.conf`, `tasks.toml` and `tasks.json`
+    ConflictingTasksFiles(Vec<PathBuf>),
+    DuplicateOutputPort { port: String, obcs: (u32, u32) },
+    DuplicateInputPort(String),
+    UndefinedSensor(String),
+    UndefinedPort(String),
+    /// A port is listed in `sensors.json` with different messages
+    PortMessageMismatch { port: String, messages: (String, String) },
+    /// A field of a port message is not a C++ identifier or is repeated
+    InvalidMessageField { port: String, field: String },
+    /// g++ or ld could not be run or reported an error
+    Compiler { target: String, message: String },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::NotProjectRoot => {
+                write!(f, "Not in project's root directory, no decert.toml or sensors.json found")
+            }
+            BuildError::InvalidManifest { path, message } => {
+                write!(f, "Invalid manifest {}: {message}", path.display())
+            }
+            BuildError::ObcExists(id) => write!(f, "The project already has obc {id}"),
+            BuildError::NotObcDir(dir) => {
+                write!(f, "Could not get obc id of {}", dir.display())
+            }
+            BuildError::StrayEntryFile(name) => write!(f, "'{name}' is not the name of any task."),
+            BuildError::InvalidFileName(path) => {
+                write!(f, "Filename is not valid UTF-8: {}", path.display())
+            }
+            BuildError::ConflictingTasksFiles(paths) => write!(
+                f,
+                "Only one configuration file is allowed per OBC, found {}",
+                paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            BuildError::DuplicateOutputPort { port, obcs } => write!(
+                f,
+                "Two OBCs cannot have the same output port {port}: {} and {}",
+                obcs.0, obcs.1
+            ),
+            BuildError::DuplicateInputPort(port) => write!(
+                f,
+                "There must be one to one mapping of ports.input port:{port} was used in more than one OBC."
+            ),
+            BuildError::UndefinedSensor(name) => {
+                write!(f, "Sensor used : {name} is not defined in sensor.json")
+            }
+            BuildError::UndefinedPort(name) => {
+                write!(f, "Port used : {name} is not defined in sensor.json")
+            }
+            BuildError::PortMessageMismatch { port, messages } => write!(
+                f,
+                "Port {port} is declared with different messages in sensors.json: {} and {}",
+                messages.0, messages.1
+            ),
+            BuildError::InvalidMessageField { port, field } => write!(
+                f,
+                "Field '{field}' of the message of port {port} must be a unique C++ identifier"
+            ),
+            BuildError::Compiler { target, message } => {
+                write!(f, "Compiling {target} Failed\n{message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Any error the CLI can end with. Each kind exits with its own code.
+#[derive(Debug)]
+pub enum Error {
+    Usage(String),
+    Io(io::Error),
+    Conf(ConfError),
+    Schedule(ScheduleError),
+    Build(BuildError),
+    /// `fmt --check` found files which are not in canonical form
+    Unformatted(Vec<PathBuf>),
+    /// The language server could not talk to its client
+    Lsp(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) => 1,
+            Error::Usage(_) => 2,
+            Error::Conf(_) => 3,
+            Error::Schedule(_) => 4,
+            Error::Build(_) => 5,
+            Error::Unformatted(_) => 6,
+            Error::Lsp(_) => 7,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Usage(usage) => write!(f, "Usage: {usage}"),
+            Error::Io(e) => write!(f, "Error: {e}"),
+            Error::Conf(e) => write!(f, "Error: {e}"),
+            Error::Schedule(e) => write!(f, "Error: {e}"),
+            Error::Build(e) => write!(f, "Error: {e}"),
+            Error::Unformatted(files) => {
+                write!(f, "Error: {} file(s) are not formatted", files.len())
+            }
+            Error::Lsp(e) => write!(f, "Error: language server: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Usage(_) | Error::Unformatted(_) | Error::Lsp(_) => None,
+            Error::Io(e) => Some(e),
+            Error::Conf(e) => Some(e),
+            Error::Schedule(e) => Some(e),
+            Error::Build(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ConfError> for Error {
+    fn from(e: ConfError) -> Self {
+        Error::Conf(e)
+    }
+}
+
+impl From<ScheduleError> for Error {
+    fn from(e: ScheduleError) -> Self {
+        Error::Schedule(e)
+    }
+}
+
+impl From<BuildError> for Error {
+    fn from(e: BuildError) -> Self {
+        Error::Build(e)
+    }
+}
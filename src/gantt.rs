@@ -0,0 +1,493 @@
+use std::{collections::HashMap, fmt::Write, sync::Arc};
+
+use confparse::{qualified_condition, Access, Conf, Task};
+use decert_scheduler::{Schedule, SensorJson, Slot};
+
+// `decert schedule`: the static schedule drawn as a Gantt chart. Each OBC has a
+// lane with its tasks and delays, each sensor and resource a lane with the tasks
+// holding it, and arrows lead from tasks to the tasks which waited for them.
+
+/// Width of the time axis, lanes are named to its left
+const WIDTH: f64 = 1200.0;
+const NAMES: f64 = 110.0;
+/// Height of a row of a lane, readers sharing a sensor get a row each
+const ROW: f64 = 28.0;
+const TOP: f64 = 50.0;
+/// Colors of the OBCs in the order of their ids
+const COLORS: &[&str] = &[
+    "#4e79a7", "#f28e2b", "#59a14f", "#e15759", "#76b7b2", "#edc948", "#b07aa1", "#ff9da7", "#9c755f",
+];
+
+/// A task or a delay drawn in a lane
+struct Block {
+    start: i32,
+    end: i32,
+    row: usize,
+    label: String,
+    /// Shown when the block is hovered
+    title: String,
+    fill: String,
+    opacity: f64,
+}
+
+struct Lane {
+    name: String,
+    rows: usize,
+    blocks: Vec<Block>,
+}
+
+/// An arrow from the slot of a task to the slot of one which waited for it, as
+/// OBC ids and indices of the slots, with the condition or port it waited on
+struct Arrow {
+    from: (u32, usize),
+    to: (u32, usize),
+    label: Arc<str>,
+}
+
+/// The schedule of a project with what is needed to draw it.
+pub struct Chart<'a> {
+    pub title: String,
+    /// Length of a tick, the unit of the time axis
+    pub tick: String,
+    pub schedule: &'a Schedule,
+    pub topology: &'a HashMap<u32, Conf>,
+    pub port2obc: &'a HashMap<String, u32>,
+    pub sensorjson: &'a SensorJson,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Row of each interval such that the intervals of a row do not overlap, and the number of rows
+fn rows(intervals: &[(i32, i32)]) -> (Vec<usize>, usize) {
+    let mut ends: Vec<i32> = vec![];
+    let rows = intervals
+        .iter()
+        .map(|(start, end)| match ends.iter().position(|last| last <= start) {
+            Some(row) => {
+                ends[row] = *end;
+                row
+            }
+            None => {
+                ends.push(*end);
+                ends.len() - 1
+            }
+        })
+        .collect();
+    (rows, ends.len().max(1))
+}
+
+/// Spacing of the labels of the time axis, so that there are at most about 20
+fn axis_step(frame: i32) -> i32 {
+    let mut step = 1;
+    loop {
+        for factor in [1, 2, 5] {
+            if frame / (step * factor) <= 20 {
+                return step * factor;
+            }
+        }
+        step *= 10;
+    }
+}
+
+impl Chart<'_> {
+    fn color(&self, obc_id: u32) -> &'static str {
+        let index = self.schedule.obcs.keys().position(|id| *id == obc_id).unwrap_or_default();
+        COLORS[index % COLORS.len()]
+    }
+
+    fn task(&self, obc_id: u32, slot: &Slot) -> Option<&Task> {
+        self.topology.get(&obc_id)?.tasks.iter().find(|task| task.name == slot.task)
+    }
+
+    fn slots(&self) -> impl Iterator<Item = (u32, usize, &Slot)> {
+        self.schedule
+            .obcs
+            .iter()
+            .flat_map(|(obc_id, slots)| slots.iter().enumerate().map(|(i, slot)| (*obc_id, i, slot)))
+    }
+
+    /// A lane for each OBC, then for each sensor and resource
+    fn lanes(&self) -> Vec<Lane> {
+        let mut lanes = vec![];
+        for (obc_id, slots) in &self.schedule.obcs {
+            let mut blocks: Vec<Block> = slots
+                .iter()
+                .map(|slot| Block {
+                    start: slot.start,
+                    end: slot.end,
+                    row: 0,
+                    label: slot.task.to_string(),
+                    title: format!("obc{obc_id} {} from {} to {}", slot.task, slot.start, slot.end),
+                    fill: self.color(*obc_id).to_string(),
+                    opacity: 1.0,
+                })
+                .collect();
+            blocks.extend(self.schedule.idle(*obc_id).into_iter().map(|(start, end)| Block {
+                start,
+                end,
+                row: 0,
+                label: format!("delay({})", end - start),
+                title: format!("obc{obc_id} waits from {start} to {end}"),
+                fill: "url(#idle)".to_string(),
+                opacity: 1.0,
+            }));
+            lanes.push(Lane { name: format!("obc{obc_id}"), rows: 1, blocks });
+        }
+
+        let sensors = self.sensorjson.sensors.iter().map(|sensor| &sensor.name);
+        for name in sensors.chain(&self.sensorjson.resources) {
+            let mut held: Vec<(u32, &Slot, Access)> = self
+                .slots()
+                .filter_map(|(obc_id, _, slot)| {
+                    let (_, access) = slot.held().find(|(held, _)| *held == name)?;
+                    Some((obc_id, slot, access))
+                })
+                .collect();
+            held.sort_by_key(|(_, slot, _)| slot.start);
+            let intervals: Vec<(i32, i32)> = held.iter().map(|(_, slot, _)| (slot.start, slot.end)).collect();
+            let (rows, count) = rows(&intervals);
+            let blocks = held
+                .iter()
+                .zip(rows)
+                .map(|((obc_id, slot, access), row)| {
+                    let (mode, opacity) = match access {
+                        Access::Read => ("reads", 0.55),
+                        Access::Write => ("writes", 1.0),
+                    };
+                    Block {
+                        start: slot.start,
+                        end: slot.end,
+                        row,
+                        label: slot.task.to_string(),
+                        title: format!("obc{obc_id} {} {mode} {name} from {} to {}", slot.task, slot.start, slot.end),
+                        fill: self.color(*obc_id).to_string(),
+                        opacity,
+                    }
+                })
+                .collect();
+            lanes.push(Lane { name: name.to_string(), rows: count, blocks });
+        }
+        lanes
+    }
+
+    /// For each slot, the first run of a task satisfying each condition it requires and
+    /// the last write of each port it waits on, among the slots which ended by its start
+    fn arrows(&self) -> Vec<Arrow> {
+        let finished = |obc_id: u32, before: i32, done: &dyn Fn(&Task) -> bool| -> Vec<(usize, i32)> {
+            let slots = self.schedule.obcs.get(&obc_id).map_or(&[][..], |slots| slots);
+            slots
+                .iter()
+                .enumerate()
+                .filter(|(_, slot)| slot.end <= before && self.task(obc_id, slot).is_some_and(done))
+                .map(|(i, slot)| (i, slot.end))
+                .collect()
+        };
+
+        let mut arrows = vec![];
+        for (obc_id, i, slot) in self.slots() {
+            let Some(task) = self.task(obc_id, slot) else {
+                continue;
+            };
+            for condition in &task.requires {
+                let (owner, name) = qualified_condition(condition).unwrap_or((obc_id, &**condition));
+                if self.topology.get(&owner).is_some_and(|conf| conf.initial.iter().any(|c| &**c == name)) {
+                    continue;
+                }
+                let satisfies = |other: &Task| other.satisfies.iter().any(|c| &**c == name);
+                if let Some((j, _)) = finished(owner, slot.start, &satisfies).into_iter().min_by_key(|(_, end)| *end) {
+                    arrows.push(Arrow { from: (owner, j), to: (obc_id, i), label: condition.clone() });
+                }
+            }
+            for port in &task.on_port {
+                let Some(&writer) = self.port2obc.get(&**port).filter(|writer| **writer != obc_id) else {
+                    continue;
+                };
+                let writes = |other: &Task| other.on_port.contains(port);
+                if let Some((j, _)) = finished(writer, slot.start, &writes).into_iter().max_by_key(|(_, end)| *end) {
+                    arrows.push(Arrow { from: (writer, j), to: (obc_id, i), label: port.clone() });
+                }
+            }
+        }
+        arrows
+    }
+
+    fn x(&self, time: i32) -> f64 {
+        NAMES + time as f64 * WIDTH / self.schedule.frame.max(1) as f64
+    }
+
+    /// The chart as a standalone SVG document
+    pub fn svg(&self) -> String {
+        let lanes = self.lanes();
+        let mut tops = vec![];
+        let mut height = TOP;
+        for lane in &lanes {
+            tops.push(height);
+            height += lane.rows as f64 * ROW;
+        }
+        let width = NAMES + WIDTH + 20.0;
+        let frame = self.schedule.frame;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{}" viewBox="0 0 {width} {}" font-family="sans-serif" font-size="11">"#,
+            height + 10.0,
+            height + 10.0
+        );
+        svg.push_str(concat!(
+            "<defs>\n",
+            r##"<marker id="arrow" viewBox="0 0 8 8" refX="8" refY="4" markerWidth="7" markerHeight="7" orient="auto"><path d="M0,0 L8,4 L0,8 z" fill="#333"/></marker>"##,
+            "\n",
+            r##"<pattern id="idle" width="6" height="6" patternUnits="userSpaceOnUse" patternTransform="rotate(45)"><rect width="6" height="6" fill="#f4f4f4"/><line x1="0" y1="0" x2="0" y2="6" stroke="#ccc" stroke-width="2"/></pattern>"##,
+            "\n</defs>\n"
+        ));
+        let _ = writeln!(
+            svg,
+            r#"<text x="{NAMES}" y="18" font-size="14" font-weight="bold">{} — frame of {frame} ticks of {}</text>"#,
+            escape(&self.title),
+            escape(&self.tick)
+        );
+
+        // lanes, then the time axis over them
+        for (lane, top) in lanes.iter().zip(&tops) {
+            let lane_height = lane.rows as f64 * ROW;
+            let _ = writeln!(
+                svg,
+                r##"<rect x="0" y="{top}" width="{width}" height="{lane_height}" fill="none" stroke="#ddd"/><text x="6" y="{}">{}</text>"##,
+                top + ROW / 2.0 + 4.0,
+                escape(&lane.name)
+            );
+        }
+        let step = axis_step(frame);
+        for time in (0..=frame).step_by(step as usize) {
+            let x = self.x(time);
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{x}" y1="{}" x2="{x}" y2="{height}" stroke="#eee"/><text x="{x}" y="{}" text-anchor="middle" fill="#666">{time}</text>"##,
+                TOP - 4.0,
+                TOP - 8.0
+            );
+        }
+
+        for (lane, top) in lanes.iter().zip(&tops) {
+            for block in &lane.blocks {
+                let (x, end) = (self.x(block.start), self.x(block.end));
+                let y = top + block.row as f64 * ROW + 3.0;
+                let _ = write!(
+                    svg,
+                    r##"<g><title>{}</title><rect x="{x}" y="{y}" width="{}" height="{}" fill="{}" fill-opacity="{}" stroke="#fff"/>"##,
+                    escape(&block.title),
+                    end - x,
+                    ROW - 6.0,
+                    block.fill,
+                    block.opacity
+                );
+                // labels are left out of blocks too narrow for them
+                if (block.label.chars().count() as f64) * 6.5 + 6.0 <= end - x {
+                    let _ = write!(svg, r#"<text x="{}" y="{}">{}</text>"#, x + 3.0, y + ROW / 2.0 + 1.0, escape(&block.label));
+                }
+                svg.push_str("</g>\n");
+            }
+        }
+
+        // arrows between the OBC lanes, which come first and in the order of the ids
+        let lane_of = |obc_id: u32| self.schedule.obcs.keys().position(|id| *id == obc_id).unwrap_or_default();
+        for arrow in self.arrows() {
+            let (from, to) = (&self.schedule.obcs[&arrow.from.0][arrow.from.1], &self.schedule.obcs[&arrow.to.0][arrow.to.1]);
+            let (x1, y1) = (self.x(from.end), tops[lane_of(arrow.from.0)] + ROW / 2.0);
+            let (x2, y2) = (self.x(to.start), tops[lane_of(arrow.to.0)] + ROW / 2.0);
+            // an arrow within a lane bends below the blocks
+            let bend = if y1 == y2 { ROW / 2.0 } else { 0.0 };
+            let _ = writeln!(
+                svg,
+                r##"<path d="M{x1},{y1} C{},{} {},{} {x2},{y2}" fill="none" stroke="#333" stroke-width="1.2" marker-end="url(#arrow)"><title>{}</title></path>"##,
+                x1 + 12.0,
+                y1 + bend,
+                x2 - 12.0,
+                y2 + bend,
+                escape(&arrow.label)
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The chart in a standalone HTML page, followed by a table of the slots
+    pub fn html(&self) -> String {
+        let mut rows = String::new();
+        for (obc_id, _, slot) in self.slots() {
+            let held: Vec<String> = slot
+                .held()
+                .map(|(name, access)| match access {
+                    Access::Read => format!("{name}:r"),
+                    Access::Write => name.to_string(),
+                })
+                .collect();
+            let _ = writeln!(
+                rows,
+                "<tr><td>obc{obc_id}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&slot.task),
+                slot.start,
+                slot.end,
+                escape(&held.join(", "))
+            );
+        }
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; margin-top: 1em; }}
+td, th {{ border: 1px solid #ddd; padding: 2px 10px; text-align: left; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>Frame of {frame} ticks of {tick}. Hover a block or an arrow for details.</p>
+{svg}<table>
+<tr><th>OBC</th><th>Task</th><th>Start</th><th>End</th><th>Holds</th></tr>
+{rows}</table>
+</body>
+</html>
+"#,
+            title = escape(&self.title),
+            frame = self.schedule.frame,
+            tick = escape(&self.tick),
+            svg = self.svg(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use confparse::{conf_from_str, Duration};
+    use decert_scheduler::{schedule, Port, Sensors};
+
+    use super::*;
+
+    /// A project of OBCs given with their tasks.conf, its sensors S and T and its port P
+    /// written by obc1, with its schedule
+    struct Project {
+        schedule: Schedule,
+        topology: HashMap<u32, Conf>,
+        port2obc: HashMap<String, u32>,
+        sensorjson: SensorJson,
+    }
+
+    impl Project {
+        fn new(obcs: &[(u32, &str)]) -> Self {
+            let topology: HashMap<u32, Conf> = obcs
+                .iter()
+                .map(|(id, text)| (*id, conf_from_str("tasks.conf", text, *id, Duration::DEFAULT_TICK).unwrap()))
+                .collect();
+            let port2obc = HashMap::from([("P".to_string(), 1)]);
+            let sensorjson = SensorJson {
+                sensors: ["S", "T"].map(|name| Sensors { name: name.into(), from: "a".into(), to: "b".into() }).to_vec(),
+                ports: vec![Port { name: "P".into(), message: None }],
+                resources: vec![],
+            };
+            let schedule = schedule(&topology, &port2obc, &sensorjson).unwrap();
+            Project { schedule, topology, port2obc, sensorjson }
+        }
+
+        fn chart(&self) -> Chart<'_> {
+            Chart {
+                title: "test".to_string(),
+                tick: "1s".to_string(),
+                schedule: &self.schedule,
+                topology: &self.topology,
+                port2obc: &self.port2obc,
+                sensorjson: &self.sensorjson,
+            }
+        }
+    }
+
+    /// The blocks of a lane as their label, start, end and row
+    fn blocks(lane: &Lane) -> Vec<(&str, i32, i32, usize)> {
+        lane.blocks.iter().map(|block| (block.label.as_str(), block.start, block.end, block.row)).collect()
+    }
+
+    #[test]
+    fn axis_steps() {
+        for (frame, step) in [(0, 1), (1, 1), (20, 1), (21, 2), (41, 2), (42, 5), (104, 5), (105, 10), (1000, 50), (1001, 50), (1050, 100)] {
+            assert_eq!(axis_step(frame), step, "frame {frame}");
+        }
+    }
+
+    #[test]
+    fn rows_of_overlapping_intervals() {
+        assert_eq!(rows(&[]), (vec![], 1));
+        // an interval starting where another ends takes its row
+        assert_eq!(rows(&[(0, 2), (2, 3)]), (vec![0, 0], 1));
+        assert_eq!(rows(&[(0, 2), (0, 1), (1, 3), (2, 4)]), (vec![0, 1, 1, 0], 2));
+    }
+
+    #[test]
+    fn lanes_and_arrows() {
+        let project = Project::new(&[
+            (1, "IN:\nOUT: P\n\nINIT_CONDITIONS:\n\n@on_port: P\nTask a(S:r): 2\n@satisfies: done\n\nTask c(T): 3\n"),
+            (
+                2,
+                "IN: P\nOUT:\n\nINIT_CONDITIONS:\n\nTask e(S:r): 1\n\n@requires: obc1.done\nTask b(S:r): 1\n\n@on_port: P\nTask d(T): 2\n",
+            ),
+        ]);
+        let chart = project.chart();
+        assert_eq!(chart.schedule.frame, 7);
+
+        let lanes = chart.lanes();
+        let names: Vec<&str> = lanes.iter().map(|lane| lane.name.as_str()).collect();
+        assert_eq!(names, ["obc1", "obc2", "S", "T"]);
+        // tasks, then the delays between them
+        assert_eq!(blocks(&lanes[0]), [("a", 0, 2, 0), ("c", 2, 5, 0), ("delay(2)", 5, 7, 0)]);
+        assert_eq!(
+            blocks(&lanes[1]),
+            [("e", 0, 1, 0), ("b", 2, 3, 0), ("d", 5, 7, 0), ("delay(1)", 1, 2, 0), ("delay(2)", 3, 5, 0)]
+        );
+        // readers sharing S get a row each
+        assert_eq!(lanes[2].rows, 2);
+        assert_eq!(blocks(&lanes[2]), [("a", 0, 2, 0), ("e", 0, 1, 1), ("b", 2, 3, 0)]);
+        assert_eq!((lanes[3].rows, blocks(&lanes[3])), (1, vec![("c", 2, 5, 0), ("d", 5, 7, 0)]));
+
+        // from the slot of a to those of b, which required its condition, and d, which waited on its port
+        let arrows: Vec<_> = chart.arrows().into_iter().map(|arrow| (arrow.from, arrow.to, arrow.label.to_string())).collect();
+        assert_eq!(arrows, [((1, 0), (2, 1), "obc1.done".to_string()), ((1, 0), (2, 2), "P".to_string())]);
+        // drawn from the end of a on the lane of obc1 to the start of b on the lane of obc2
+        let (x1, x2) = (chart.x(2), chart.x(2));
+        let (y1, y2) = (TOP + ROW / 2.0, TOP + ROW + ROW / 2.0);
+        assert!(chart.svg().contains(&format!(r#"<path d="M{x1},{y1} C{},{y1} {},{y2} {x2},{y2}""#, x1 + 12.0, x2 - 12.0)));
+    }
+
+    #[test]
+    fn empty_schedule() {
+        let project = Project::new(&[(1, "IN:\nOUT:\n\nINIT_CONDITIONS:\n\n")]);
+        let chart = project.chart();
+        assert_eq!(chart.schedule.frame, 0);
+        let lanes = chart.lanes();
+        assert_eq!(lanes.len(), 3);
+        assert!(lanes.iter().all(|lane| lane.blocks.is_empty() && lane.rows == 1));
+        assert!(chart.arrows().is_empty());
+        // a single label on the axis, at the start
+        let svg = chart.svg();
+        assert_eq!(svg.matches(r#"text-anchor="middle""#).count(), 1);
+        assert!(svg.ends_with("</svg>\n"));
+        assert!(chart.html().contains(&svg));
+    }
+
+    #[test]
+    fn frame_of_one_tick() {
+        let project = Project::new(&[(1, "IN:\nOUT:\n\nINIT_CONDITIONS:\n\n@period: 1\nTask a(S): 1\n")]);
+        let chart = project.chart();
+        assert_eq!(chart.schedule.frame, 1);
+        assert_eq!(blocks(&chart.lanes()[0]), [("a", 0, 1, 0)]);
+        // the task spans the whole axis, labelled at both ends
+        assert_eq!((chart.x(0), chart.x(1)), (NAMES, NAMES + WIDTH));
+        let svg = chart.svg();
+        assert_eq!(svg.matches(r#"text-anchor="middle""#).count(), 2);
+        assert!(svg.contains(&format!(r#"<rect x="{NAMES}" y="{}" width="{WIDTH}""#, TOP + 3.0)));
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use confparse::{conf_from_str, qualified_condition, ConfError, Duration};
+use decert_scheduler::read_sensors;
+use lsp_server::{Connection, ExtractError, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+    notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, PublishDiagnostics},
+    request::{Completion, GotoDefinition},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse, Diagnostic,
+    DiagnosticSeverity, GotoDefinitionParams, GotoDefinitionResponse, Location, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+use crate::{
+    error::Error,
+    manifest::{find_root, Manifest},
+};
+
+// `decert lsp`: a language server for the configuration files of the OBCs,
+// speaking the Language Server Protocol over stdin and stdout.
+
+const KEYWORDS: &[&str] = &[
+    "IN:",
+    "OUT:",
+    "INIT_CONDITIONS:",
+    "INCLUDE:",
+    "Task",
+    "Template",
+    "@requires:",
+    "@satisfies:",
+    "@on_port:",
+    "@uses:",
+    "@period:",
+    "@deadline:",
+    "@priority:",
+    "@when(",
+];
+
+/// What the name under the cursor refers to, from the statement it is written in
+#[derive(Debug, PartialEq)]
+enum Context {
+    Sensor,
+    Port,
+    Resource,
+    Condition,
+    Template,
+    Keyword,
+}
+
+fn protocol_error(e: impl std::fmt::Display) -> Error {
+    Error::Lsp(e.to_string())
+}
+
+/// Runs the language server until the client asks it to exit.
+pub fn run() -> Result<(), Error> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(["(", ",", ":", " ", "="].map(String::from).to_vec()),
+            ..Default::default()
+        }),
+        definition_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    let capabilities = serde_json::to_value(capabilities).map_err(protocol_error)?;
+    connection.initialize(capabilities).map_err(protocol_error)?;
+
+    let mut documents: HashMap<Url, String> = HashMap::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request).map_err(protocol_error)? {
+                    break;
+                }
+                let response = respond(&documents, request);
+                connection.sender.send(Message::Response(response)).map_err(protocol_error)?;
+            }
+            Message::Notification(notification) => {
+                let Some(uri) = update(&mut documents, notification) else {
+                    continue;
+                };
+                let diagnostics = documents.get(&uri).map(|text| diagnostics(&uri, text)).unwrap_or_default();
+                let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+                let notification = Notification::new(
+                    <PublishDiagnostics as lsp_types::notification::Notification>::METHOD.to_string(),
+                    params,
+                );
+                connection.sender.send(Message::Notification(notification)).map_err(protocol_error)?;
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    // the writer thread ends once the connection is dropped
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Applies a change of the open documents, returns the document which changed.
+fn update(documents: &mut HashMap<Url, String>, notification: Notification) -> Option<Url> {
+    let notification = match notification.extract::<lsp_types::DidOpenTextDocumentParams>(
+        <DidOpenTextDocument as lsp_types::notification::Notification>::METHOD,
+    ) {
+        Ok(params) => {
+            let uri = params.text_document.uri;
+            documents.insert(uri.clone(), params.text_document.text);
+            return Some(uri);
+        }
+        Err(ExtractError::MethodMismatch(notification)) => notification,
+        Err(ExtractError::JsonError { .. }) => return None,
+    };
+    let notification = match notification.extract::<lsp_types::DidChangeTextDocumentParams>(
+        <DidChangeTextDocument as lsp_types::notification::Notification>::METHOD,
+    ) {
+        Ok(params) => {
+            // full sync, the last change holds the whole text
+            let text = params.content_changes.into_iter().last()?.text;
+            let uri = params.text_document.uri;
+            documents.insert(uri.clone(), text);
+            return Some(uri);
+        }
+        Err(ExtractError::MethodMismatch(notification)) => notification,
+        Err(ExtractError::JsonError { .. }) => return None,
+    };
+    match notification.extract::<lsp_types::DidCloseTextDocumentParams>(
+        <DidCloseTextDocument as lsp_types::notification::Notification>::METHOD,
+    ) {
+        Ok(params) => {
+            // clears the diagnostics of the closed document
+            documents.remove(&params.text_document.uri);
+            Some(params.text_document.uri)
+        }
+        Err(_) => None,
+    }
+}
+
+fn respond(documents: &HashMap<Url, String>, request: Request) -> Response {
+    let id = request.id.clone();
+    let request = match request.extract::<CompletionParams>(<Completion as lsp_types::request::Request>::METHOD) {
+        Ok((id, params)) => {
+            let position = params.text_document_position;
+            let items = documents
+                .get(&position.text_document.uri)
+                .map(|text| completions(&position.text_document.uri, text, position.position))
+                .unwrap_or_default();
+            return Response::new_ok(id, CompletionResponse::Array(items));
+        }
+        Err(ExtractError::MethodMismatch(request)) => request,
+        Err(ExtractError::JsonError { method, error }) => return invalid_params(id, method, error),
+    };
+    match request.extract::<GotoDefinitionParams>(<GotoDefinition as lsp_types::request::Request>::METHOD) {
+        Ok((id, params)) => {
+            let position = params.text_document_position_params;
+            let locations = documents
+                .get(&position.text_document.uri)
+                .map(|text| definitions(&position.text_document.uri, text, position.position))
+                .unwrap_or_default();
+            Response::new_ok(id, GotoDefinitionResponse::Array(locations))
+        }
+        Err(ExtractError::MethodMismatch(request)) => Response::new_err(
+            request.id,
+            lsp_server::ErrorCode::MethodNotFound as i32,
+            format!("Unsupported request {}", request.method),
+        ),
+        Err(ExtractError::JsonError { method, error }) => invalid_params(id, method, error),
+    }
+}
+
+fn invalid_params(id: RequestId, method: String, error: serde_json::Error) -> Response {
+    Response::new_err(
+        id,
+        lsp_server::ErrorCode::InvalidParams as i32,
+        format!("Invalid parameters of {method}: {error}"),
+    )
+}
+
+/// Position of the byte `offset` of `text`, with columns counted in UTF-16 as LSP does
+fn position(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    Position::new(
+        text[..offset].matches('\n').count() as u32,
+        text[line_start..offset].encode_utf16().count() as u32,
+    )
+}
+
+/// Byte offset of `position` in `text`
+fn offset(text: &str, position: Position) -> usize {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+fn diagnostics(uri: &Url, text: &str) -> Vec<Diagnostic> {
+    let Ok(path) = uri.to_file_path() else {
+        return vec![];
+    };
+    // the tick of the project, for task lengths with a unit
+    let tick = project(&path).map_or(Duration::DEFAULT_TICK, |(_, manifest)| manifest.tick);
+    let Err(error) = conf_from_str(&path, text, obc_id(&path), tick) else {
+        return vec![];
+    };
+    let range = match &error {
+        // errors of included files are shown on the first line
+        ConfError::Syntax(diagnostic) if diagnostic.path == path => Range::new(
+            position(text, diagnostic.span.start),
+            position(text, diagnostic.span.end.max(diagnostic.span.start + 1)),
+        ),
+        ConfError::Toml { error, .. } => match error.span() {
+            Some(span) => Range::new(position(text, span.start), position(text, span.end)),
+            None => Range::default(),
+        },
+        ConfError::Json { error, .. } => {
+            let start = Position::new(error.line().saturating_sub(1) as u32, error.column().saturating_sub(1) as u32);
+            Range::new(start, Position::new(start.line, start.character + 1))
+        }
+        _ => Range::default(),
+    };
+    let message = match &error {
+        ConfError::Syntax(diagnostic) if diagnostic.path == path => diagnostic.kind.to_string(),
+        ConfError::Syntax(diagnostic) => format!("{}: {}", diagnostic.path.display(), diagnostic.kind),
+        ConfError::Toml { error, .. } => error.message().to_string(),
+        error => error.to_string(),
+    };
+    vec![Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("decert".to_string()),
+        message,
+        ..Default::default()
+    }]
+}
+
+/// The root and manifest of the project of the file at `path`
+fn project(path: &Path) -> Option<(PathBuf, Manifest)> {
+    let root = find_root(path.parent()?)?;
+    Some((root.to_path_buf(), Manifest::load(root).ok()?))
+}
+
+/// Id of the OBC of a configuration file in an `obc<id>` folder, 0 elsewhere
+fn obc_id(path: &Path) -> u32 {
+    path.parent()
+        .and_then(|dir| dir.file_name())
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix("obc"))
+        .and_then(|id| id.parse().ok())
+        .unwrap_or_default()
+}
+
+/// The `tasks.conf` of every other OBC of the project of the file at `path`, with their ids
+fn other_confs(path: &Path) -> Vec<(u32, PathBuf)> {
+    let Some((root, manifest)) = project(path) else {
+        return vec![];
+    };
+    let own = obc_id(path);
+    manifest
+        .obcs
+        .iter()
+        .filter(|obc| obc.id != own)
+        .map(|obc| (obc.id, root.join(&obc.path).join("tasks.conf")))
+        .collect()
+}
+
+fn strip_comment(line: &str) -> &str {
+    let end = [line.find('#'), line.find("//")].into_iter().flatten().min();
+    end.map_or(line, |end| &line[..end])
+}
+
+fn is_name_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, ':' | ',' | '(' | ')' | '@' | '=' | '"' | '#')
+}
+
+/// The statement the cursor is in: the line up to `offset`, joined with the lines
+/// before it when they end a list with `,`.
+fn statement(text: &str, offset: usize) -> String {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let mut statement = strip_comment(&text[line_start..offset]).to_string();
+    for line in text[..line_start].lines().rev() {
+        let line = strip_comment(line).trim_end();
+        if !line.ends_with(',') {
+            break;
+        }
+        statement = format!("{line} {statement}");
+    }
+    statement
+}
+
+fn context(statement: &str) -> Context {
+    let trimmed = statement.trim_start();
+    let open = statement.rfind('(');
+    let close = statement.rfind(')');
+    if (trimmed.starts_with("Task") || trimmed.starts_with("Template")) && open > close {
+        Context::Sensor
+    } else if trimmed.starts_with("Task") && statement.contains('=') && open.is_none() {
+        Context::Template
+    } else if ["IN:", "OUT:", "@on_port:"].iter().any(|k| trimmed.starts_with(k)) {
+        Context::Port
+    } else if trimmed.starts_with("@uses:") {
+        Context::Resource
+    } else if ["@requires:", "@satisfies:", "INIT_CONDITIONS:"].iter().any(|k| trimmed.starts_with(k)) {
+        Context::Condition
+    } else {
+        Context::Keyword
+    }
+}
+
+/// Names listed after `keyword` at the start of a line, such as the conditions of `@satisfies:`
+fn listed<'a>(text: &'a str, keywords: &[&str]) -> Vec<&'a str> {
+    let mut names = vec![];
+    let mut in_list = false;
+    for line in text.lines() {
+        let line = strip_comment(line).trim();
+        let rest = match keywords.iter().find_map(|k| line.strip_prefix(k)) {
+            Some(rest) => rest,
+            None if in_list => line,
+            None => continue,
+        };
+        in_list = rest.trim_end().ends_with(',');
+        names.extend(rest.split(',').map(|name| name.trim().trim_matches('"')).filter(|name| !name.is_empty()));
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn completions(uri: &Url, text: &str, cursor: Position) -> Vec<CompletionItem> {
+    let statement = statement(text, offset(text, cursor));
+    let sensors = || {
+        let (root, manifest) = project(&uri.to_file_path().ok()?)?;
+        read_sensors(root.join(manifest.sensors)).ok()
+    };
+
+    let (names, kind): (Vec<String>, _) = match context(&statement) {
+        Context::Sensor => (
+            sensors().map(|s| s.sensors.iter().map(|f| f.name.to_string()).collect()).unwrap_or_default(),
+            CompletionItemKind::VARIABLE,
+        ),
+        Context::Port => {
+            let mut ports: Vec<String> = sensors()
+                .map(|s| s.ports.iter().map(|f| f.name.to_string()).collect())
+                .unwrap_or_default();
+            ports.dedup();
+            (ports, CompletionItemKind::INTERFACE)
+        }
+        Context::Resource => (
+            sensors().map(|s| s.resources.iter().map(|r| r.to_string()).collect()).unwrap_or_default(),
+            CompletionItemKind::VARIABLE,
+        ),
+        Context::Condition => {
+            let mut conditions: Vec<String> =
+                listed(text, &["@requires:", "@satisfies:", "INIT_CONDITIONS:"]).into_iter().map(String::from).collect();
+            // the conditions of other OBCs are given with their OBC
+            let path = uri.to_file_path().unwrap_or_default();
+            for (id, conf) in other_confs(&path) {
+                let Ok(other) = fs::read_to_string(conf) else {
+                    continue;
+                };
+                let names = listed(&other, &["@satisfies:", "INIT_CONDITIONS:"]);
+                conditions.extend(names.into_iter().map(|name| format!("obc{id}.{name}")));
+            }
+            conditions.sort();
+            conditions.dedup();
+            (conditions, CompletionItemKind::CONSTANT)
+        }
+        Context::Template => (
+            text.lines()
+                .filter_map(|line| line.trim().strip_prefix("Template"))
+                .filter_map(|rest| rest.split('(').next())
+                .map(|name| name.trim().to_string())
+                .collect(),
+            CompletionItemKind::FUNCTION,
+        ),
+        Context::Keyword => (KEYWORDS.iter().map(|k| k.to_string()).collect(), CompletionItemKind::KEYWORD),
+    };
+    names
+        .into_iter()
+        .map(|name| CompletionItem {
+            label: name,
+            kind: Some(kind),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The name at `offset` and the byte range it spans
+fn name_at(text: &str, offset: usize) -> Option<(&str, usize, usize)> {
+    let start = text[..offset].rfind(|c| !is_name_char(c)).map_or(0, |i| i + 1);
+    let end = text[offset..].find(|c| !is_name_char(c)).map_or(text.len(), |i| offset + i);
+    (start < end).then(|| (&text[start..end], start, end))
+}
+
+fn definitions(uri: &Url, text: &str, cursor: Position) -> Vec<Location> {
+    let Ok(path) = uri.to_file_path() else {
+        return vec![];
+    };
+    let cursor = offset(text, cursor);
+    let Some((name, start, _)) = name_at(text, cursor) else {
+        return vec![];
+    };
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let before = text[line_start..start].trim();
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    // a task name leads to its entry file, a template or the template of an instance to its header
+    let entry = match before {
+        "Task" if text[start..].trim_start_matches(name).trim_start().starts_with('=') => None,
+        "Task" => Some(dir.join("entry").join(format!("{name}.cpp"))),
+        "Template" => Some(dir.join("entry").join(format!("{name}.hpp"))),
+        _ if before.starts_with("Task") && before.ends_with('=') => {
+            Some(dir.join("entry").join(format!("{name}.hpp")))
+        }
+        _ => None,
+    };
+    if let Some(entry) = entry {
+        return file_location(&entry).into_iter().collect();
+    }
+
+    // a condition leads to the tasks which satisfy it, in the configuration of its OBC
+    if statement(text, start).trim_start().starts_with("@requires:") {
+        if let Some((id, condition)) = qualified_condition(name).filter(|(id, _)| *id != obc_id(&path)) {
+            let Some((_, conf)) = other_confs(&path).into_iter().find(|(other, _)| *other == id) else {
+                return vec![];
+            };
+            let (Ok(other), Some(Location { uri, .. })) = (fs::read_to_string(&conf), file_location(&conf)) else {
+                return vec![];
+            };
+            return satisfying_tasks(&other, condition)
+                .into_iter()
+                .map(|(start, end)| Location::new(uri.clone(), Range::new(position(&other, start), position(&other, end))))
+                .collect();
+        }
+        let name = qualified_condition(name).map_or(name, |(_, condition)| condition);
+        return satisfying_tasks(text, name)
+            .into_iter()
+            .map(|(start, end)| Location::new(uri.clone(), Range::new(position(text, start), position(text, end))))
+            .collect();
+    }
+    vec![]
+}
+
+fn file_location(path: &PathBuf) -> Option<Location> {
+    let path = fs::canonicalize(path).ok()?;
+    Some(Location::new(Url::from_file_path(path).ok()?, Range::default()))
+}
+
+/// Byte ranges of the names of the tasks and templates followed by a `@satisfies:` listing `condition`
+fn satisfying_tasks(text: &str, condition: &str) -> Vec<(usize, usize)> {
+    let mut found = vec![];
+    let mut task = None;
+    let mut offset = 0;
+    let mut lines = text.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let code = strip_comment(line);
+        let trimmed = code.trim_start();
+        if let Some(rest) = trimmed.strip_prefix("Task").or_else(|| trimmed.strip_prefix("Template")) {
+            let name = rest.trim_start().split(|c: char| !is_name_char(c)).next().unwrap_or_default();
+            let start = offset + code.len() - trimmed.len() + (trimmed.len() - rest.len()) + (rest.len() - rest.trim_start().len());
+            task = Some((start, start + name.len()));
+        } else if let Some(mut rest) = trimmed.strip_prefix("@satisfies:").map(String::from) {
+            // the list may continue over several lines after a trailing ','
+            while rest.trim_end().ends_with(',') {
+                let Some(next) = lines.next() else {
+                    break;
+                };
+                offset += next.len();
+                rest.push_str(strip_comment(next));
+            }
+            if rest.split(',').any(|name| name.trim().trim_matches('"') == condition) {
+                found.extend(task);
+            }
+        }
+        offset += line.len();
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{DidCloseTextDocumentParams, DidOpenTextDocumentParams, TextDocumentIdentifier, TextDocumentItem};
+
+    use super::*;
+
+    fn notification<N: lsp_types::notification::Notification>(params: N::Params) -> Notification {
+        Notification::new(N::METHOD.to_string(), params)
+    }
+
+    /// Opens `text` as the file at `path` outside of any project, as the client does,
+    /// and returns the diagnostics published for it
+    fn open(documents: &mut HashMap<Url, String>, path: &str, text: &str) -> Vec<Diagnostic> {
+        let uri = Url::from_file_path(Path::new("/nonexistent").join(path)).unwrap();
+        let item = TextDocumentItem::new(uri.clone(), "decert".to_string(), 1, text.to_string());
+        let params = DidOpenTextDocumentParams { text_document: item };
+        assert_eq!(update(documents, notification::<DidOpenTextDocument>(params)), Some(uri.clone()));
+        diagnostics(&uri, &documents[&uri])
+    }
+
+    #[test]
+    fn diagnostics_of_open_documents() {
+        let mut documents = HashMap::new();
+        let text = "IN:\nOUT:\n\nINIT_CONDITIONS:\n\n# grüße\nTask lög(S): x\n";
+        let [diagnostic] = &open(&mut documents, "obc1/tasks.conf", text)[..] else {
+            panic!("one diagnostic expected");
+        };
+        // columns count UTF-16 units, not bytes
+        assert_eq!(diagnostic.range, Range::new(Position::new(6, 13), Position::new(6, 14)));
+        assert_eq!(diagnostic.message, "Cycles must be a number, a time such as 250us or a range such as 2..5, found 'x'");
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+
+        assert!(open(&mut documents, "obc1/tasks.conf", "IN:\nOUT:\n\nINIT_CONDITIONS:\n\nTask a(S): 1\n").is_empty());
+
+        let [diagnostic] = &open(&mut documents, "obc2/tasks.toml", "in = [\"p\"]\nout = 3\n")[..] else {
+            panic!("one diagnostic expected");
+        };
+        assert_eq!(diagnostic.range, Range::new(Position::new(1, 6), Position::new(1, 7)));
+        assert!(diagnostic.message.contains("expected a sequence"), "{}", diagnostic.message);
+
+        // closing a document clears its diagnostics
+        let uri = Url::from_file_path("/nonexistent/obc2/tasks.toml").unwrap();
+        let params = DidCloseTextDocumentParams { text_document: TextDocumentIdentifier::new(uri.clone()) };
+        assert_eq!(update(&mut documents, notification::<DidCloseTextDocument>(params)), Some(uri.clone()));
+        assert!(!documents.contains_key(&uri));
+    }
+
+    #[test]
+    fn positions_and_offsets() {
+        let text = "ab\nçd😀e\n";
+        assert_eq!(position(text, 3), Position::new(1, 0));
+        // ç is two bytes and one UTF-16 unit, 😀 four bytes and two units
+        assert_eq!(position(text, 6), Position::new(1, 2));
+        assert_eq!(position(text, 10), Position::new(1, 4));
+        assert_eq!(offset(text, Position::new(1, 4)), 10);
+        assert_eq!(offset(text, Position::new(1, 99)), 11);
+        assert_eq!(offset(text, Position::new(0, 1)), 1);
+    }
+}
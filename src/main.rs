@@ -1,47 +1,101 @@
-use std::process::exit;
+use std::{path::Path, process::exit};
+
+use error::Error;
 
 mod cli;
 mod artifacts;
+mod error;
+mod gantt;
+mod lsp;
+mod manifest;
 
-fn main() {
-    let args = std::env::args().collect::<Vec<_>>();
-    if args.len() < 2 {
-        println!("Usage: decert <command> [options]");
-        exit(0);
+/// The name given with `--profile <name>`, which must be the only option of `command`.
+fn profile<'a>(args: &'a [String], command: &str) -> Result<Option<&'a str>, Error> {
+    match &args[2..] {
+        [] => Ok(None),
+        [option, name] if option == "--profile" => Ok(Some(name)),
+        _ => Err(Error::Usage(format!("decert {command} [--profile <name>]"))),
     }
-    match args[1].as_str() {
+}
+
+/// Runs the command of `args`, returns the message to print when it succeeds.
+fn run(args: &[String]) -> Result<Option<&'static str>, Error> {
+    let Some(command) = args.get(1) else {
+        Err(Error::Usage("decert <command> [options]".to_string()))?
+    };
+    match command.as_str() {
         "create-project" => {
             let Some(project_name) = args.get(2) else {
-                println!("Usage: decert create-project <name>");
-                exit(1)
-            };
-            match cli::create_project(project_name) {
-                Ok(_) => println!("Project created"),
-                Err(e) => println!("Error: {}", e),
+                Err(Error::Usage("decert create-project <name>".to_string()))?
             };
+            cli::create_project(project_name)?;
+            Ok(Some("Project created"))
         }
         "add-obc" => {
-            let Ok(id) = args[2].parse::<u32>() else {
-                println!("Usage: decert add-obc <id>");
-                exit(2);
-            };
-            match cli::add_obc(id) {
-                Ok(_) => println!("New OBC created"),
-                Err(e) => println!("Error: {}", e),
+            let Some(Ok(id)) = args.get(2).map(|f| f.parse::<u32>()) else {
+                Err(Error::Usage("decert add-obc <id>".to_string()))?
             };
+            cli::add_obc(id)?;
+            Ok(Some("New OBC created"))
         },
         "update-tasks" => {
-            match cli::update_tasks() {
-                Ok(_) => println!("Tasks updated"),
-                Err(e) => println!("Error: {}", e),
+            cli::update_tasks(profile(args, command)?)?;
+            Ok(Some("Tasks updated"))
+        }
+        "fmt" => {
+            let check = match args.get(2).map(|f| f.as_str()) {
+                None => false,
+                Some("--check") => true,
+                Some(_) => Err(Error::Usage("decert fmt [--check]".to_string()))?,
             };
+            cli::fmt(check)?;
+            Ok(Some("Formatting done"))
         }
-        "compile" => {
-            match cli::compile() {
-                Ok(_) => println!("Compilation successful"),
-                Err(e) => println!("Error: {}", e),
+        "convert" => {
+            let (Some(input), Some(output)) = (args.get(2), args.get(3)) else {
+                Err(Error::Usage("decert convert <input> <output>".to_string()))?
             };
+            cli::convert(Path::new(input), Path::new(output))?;
+            Ok(Some("Conversion done"))
+        }
+        "lsp" => {
+            // stdout belongs to the client, nothing is printed once it disconnects
+            lsp::run()?;
+            Ok(None)
+        }
+        "schedule" => {
+            let usage = || Error::Usage("decert schedule [--svg <file>] [--html <file>] [--profile <name>]".to_string());
+            let (mut svg, mut html, mut profile) = (None, None, None);
+            for option in args[2..].chunks(2) {
+                match option {
+                    [option, value] if option == "--svg" => svg = Some(Path::new(value)),
+                    [option, value] if option == "--html" => html = Some(Path::new(value)),
+                    [option, value] if option == "--profile" => profile = Some(value.as_str()),
+                    _ => Err(usage())?,
+                }
+            }
+            if svg.is_none() && html.is_none() {
+                Err(usage())?
+            }
+            cli::schedule_chart(svg, html, profile)?;
+            Ok(Some("Schedule drawn"))
+        }
+        "compile" => {
+            cli::compile(profile(args, command)?)?;
+            Ok(Some("Compilation successful"))
+        }
+        _ => Err(Error::Usage(format!("Unknown command '{command}'"))),
+    }
+}
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    match run(&args) {
+        Ok(Some(message)) => println!("{message}"),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{e}");
+            exit(e.exit_code());
         }
-        _ => println!("Unknown command"),
     }
 }
@@ -0,0 +1,247 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use confparse::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BuildError;
+
+// `decert.toml`: the settings of a project, at its root. Every command which
+// works on a project reads it, `create-project` writes it and `add-obc` adds to it.
+// Projects made before it have none: their root holds `sensors.json` and their
+// OBCs are the `obc<id>` folders, with every other setting left to its default.
+
+pub const MANIFEST: &str = "decert.toml";
+
+/// What the generated code runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// The demo rtos, which sleeps for the length of each task and prints what the OBC does
+    #[default]
+    DemoRtos,
+}
+
+/// The C++ compiler of the generated code and of the `entry/` and `lib/` files,
+/// and the linker which joins the objects of an OBC into one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Compiler {
+    pub command: String,
+    pub flags: Vec<String>,
+    pub linker: String,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler {
+            command: "g++".to_string(),
+            flags: vec!["-O2".to_string()],
+            linker: "ld".to_string(),
+        }
+    }
+}
+
+/// An OBC of the project and its folder, relative to the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Obc {
+    pub id: u32,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    pub name: String,
+    /// Length of one tick, the unit of task cycles, periods and deadlines
+    pub tick: Duration,
+    /// The sensors, ports and resources of the project
+    pub sensors: PathBuf,
+    /// Folder of the compiled objects, relative to the root
+    pub output: PathBuf,
+    pub backend: Backend,
+    pub compiler: Compiler,
+    #[serde(rename = "obc", skip_serializing_if = "Vec::is_empty")]
+    pub obcs: Vec<Obc>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Manifest {
+            name: String::new(),
+            tick: Duration::DEFAULT_TICK,
+            sensors: PathBuf::from("sensors.json"),
+            output: PathBuf::from("dist"),
+            backend: Backend::default(),
+            compiler: Compiler::default(),
+            obcs: vec![],
+        }
+    }
+}
+
+/// The root of the project `dir` is in: the closest folder with a `decert.toml`,
+/// or else with the `sensors.json` of a project without one.
+pub fn find_root(dir: &Path) -> Option<&Path> {
+    let sensors = Manifest::default().sensors;
+    dir.ancestors()
+        .find(|dir| dir.join(MANIFEST).is_file())
+        .or_else(|| dir.ancestors().find(|dir| dir.join(&sensors).is_file()))
+}
+
+impl Manifest {
+    /// Reads the manifest of the project whose root is `root`, or makes up the one of
+    /// a project without `decert.toml`.
+    pub fn load(root: &Path) -> Result<Manifest, BuildError> {
+        let path = root.join(MANIFEST);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Manifest::without_file(root),
+            Err(e) => Err(BuildError::InvalidManifest { path: path.clone(), message: e.to_string() })?,
+        };
+        let manifest: Manifest = toml::from_str(&content)
+            .map_err(|e| BuildError::InvalidManifest { path: path.clone(), message: e.to_string() })?;
+        for (i, obc) in manifest.obcs.iter().enumerate() {
+            if manifest.obcs[..i].iter().any(|other| other.id == obc.id) {
+                let message = format!("obc {} is listed more than once", obc.id);
+                Err(BuildError::InvalidManifest { path: path.clone(), message })?
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// The default settings, with the `obc<id>` folders of `root` as the OBCs,
+    /// for a project with a `sensors.json` but no `decert.toml`.
+    fn without_file(root: &Path) -> Result<Manifest, BuildError> {
+        let mut manifest = Manifest::default();
+        if !root.join(&manifest.sensors).is_file() {
+            Err(BuildError::NotProjectRoot)?
+        }
+        let name = root.canonicalize().ok().and_then(|root| Some(root.file_name()?.to_string_lossy().into_owned()));
+        manifest.name = name.unwrap_or_default();
+        for entry in fs::read_dir(root).into_iter().flatten().flatten() {
+            let name = entry.file_name();
+            let id = name.to_str().and_then(|name| name.strip_prefix("obc")).and_then(|id| id.parse().ok());
+            if let Some(id) = id.filter(|_| entry.path().is_dir()) {
+                manifest.obcs.push(Obc { id, path: PathBuf::from(name) });
+            }
+        }
+        manifest.obcs.sort_by_key(|obc| obc.id);
+        Ok(manifest)
+    }
+
+    /// Writes a new manifest to `root`.
+    pub fn create(&self, root: &Path) -> io::Result<()> {
+        let content = toml::to_string(self).map_err(io::Error::other)?;
+        fs::write(root.join(MANIFEST), content)
+    }
+
+    /// Adds `obc` to the manifest at `root`. The entry is appended to the file,
+    /// so that the rest of it is kept as it is written. Without a file the OBC
+    /// is found by the name of its folder.
+    pub fn add_obc(&mut self, root: &Path, obc: Obc) -> io::Result<()> {
+        let path = root.join(MANIFEST);
+        if path.is_file() {
+            let entry = toml::to_string(&obc).map_err(io::Error::other)?;
+            let mut file = fs::OpenOptions::new().append(true).open(path)?;
+            file.write_all(format!("\n[[obc]]\n{entry}").as_bytes())?;
+        }
+        self.obcs.push(obc);
+        Ok(())
+    }
+
+    /// Ids of the OBCs, in increasing order.
+    pub fn obc_ids(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.obcs.iter().map(|obc| obc.id).collect();
+        ids.sort();
+        ids
+    }
+
+    /// Folder of the OBC `id`, relative to the root.
+    pub fn obc_dir(&self, id: u32) -> PathBuf {
+        self.obcs
+            .iter()
+            .find(|obc| obc.id == id)
+            .map_or_else(|| PathBuf::from(format!("obc{id}")), |obc| obc.path.clone())
+    }
+
+    /// Id of the OBC whose folder is `dir`, with the project at `root`.
+    pub fn obc_at(&self, root: &Path, dir: &Path) -> Option<u32> {
+        let dir = dir.canonicalize().ok()?;
+        self.obcs
+            .iter()
+            .find(|obc| root.join(&obc.path).canonicalize().is_ok_and(|path| path == dir))
+            .map(|obc| obc.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory with the folders `dirs` and the files `files`
+    fn project(name: &str, dirs: &[&str], files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("decert-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in dirs {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for (file, content) in files {
+            fs::write(root.join(file), content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn root_of_a_subdirectory() {
+        let root = project("root", &["obc1/entry", "inner/obc2"], &[(MANIFEST, "name = \"sat\"\n"), ("inner/sensors.json", "{}")]);
+        assert_eq!(find_root(&root.join("obc1/entry")), Some(root.as_path()));
+        // decert.toml is preferred to a closer sensors.json
+        assert_eq!(find_root(&root.join("inner/obc2")), Some(root.as_path()));
+        assert_eq!(find_root(Path::new("/")), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn project_without_manifest() {
+        let root = project("legacy", &["obc2", "obc1", "obc3x", "lib"], &[("sensors.json", "{}"), ("obc4", "")]);
+        assert_eq!(find_root(&root.join("obc1")), Some(root.as_path()));
+        let manifest = Manifest::load(&root).unwrap();
+        assert_eq!(manifest.name, format!("decert-{}-legacy", std::process::id()));
+        assert_eq!(manifest.tick, Duration::DEFAULT_TICK);
+        assert_eq!(manifest.compiler, Compiler::default());
+        assert_eq!(manifest.obc_ids(), vec![1, 2]);
+        assert_eq!(manifest.obc_dir(2), PathBuf::from("obc2"));
+        assert_eq!(manifest.obc_at(&root, &root.join("obc1")), Some(1));
+
+        fs::remove_file(root.join("sensors.json")).unwrap();
+        assert!(matches!(Manifest::load(&root), Err(BuildError::NotProjectRoot)));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn added_obcs_are_read_back() {
+        let text = "name = \"sat\"\n\n[compiler]\n# kept as written\nflags = [\"-O0\"]\n";
+        let root = project("add", &["boards/first"], &[(MANIFEST, text)]);
+        let mut manifest = Manifest::load(&root).unwrap();
+        assert_eq!(manifest.compiler.command, "g++");
+        assert_eq!(manifest.compiler.linker, "ld");
+        manifest.add_obc(&root, Obc { id: 3, path: PathBuf::from("boards/first") }).unwrap();
+        manifest.add_obc(&root, Obc { id: 1, path: PathBuf::from("obc1") }).unwrap();
+
+        let written = fs::read_to_string(root.join(MANIFEST)).unwrap();
+        assert!(written.starts_with(text));
+        assert_eq!(Manifest::load(&root).unwrap(), manifest);
+        assert_eq!(manifest.obc_ids(), vec![1, 3]);
+        assert_eq!(manifest.obc_dir(3), PathBuf::from("boards/first"));
+        assert_eq!(manifest.obc_at(&root, &root.join("boards/first")), Some(3));
+
+        manifest.add_obc(&root, Obc { id: 3, path: PathBuf::from("obc3") }).unwrap();
+        let error = Manifest::load(&root).unwrap_err().to_string();
+        assert!(error.contains("obc 3 is listed more than once"), "{error}");
+        fs::remove_dir_all(root).unwrap();
+    }
+}